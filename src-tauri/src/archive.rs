@@ -0,0 +1,388 @@
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::io::{self, Read, Write};
+use std::path::Path;
+use tauri::AppHandle;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+use crate::queue::{TransferDirection, TransferOptions};
+use crate::sftp::{exec_on_session, open_exec_channel, register_and_run, shell_quote, SessionHostMap, SftpManager, TransferCtx};
+
+/// 本地打包时每次发送的块大小
+const CHUNK: usize = 256 * 1024;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum ArchiveFormat {
+    /// tar + gzip，几乎所有服务器都支持
+    #[default]
+    Gzip,
+    /// tar + zstd，压缩更快，需要远程安装 zstd
+    Zstd,
+    /// 不压缩，适合已压缩的内容或高带宽链路
+    Tar,
+}
+
+/// 以 tar 流方式传输目录的参数；保存在 TransferOptions 中，重试时沿用
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchiveOptions {
+    #[serde(default)]
+    pub format: ArchiveFormat,
+    /// 下载时边接收边解压到 localPath 目录；否则把归档保存为 localPath 文件
+    #[serde(default)]
+    pub extract: bool,
+}
+
+/// 从异步通道读取数据块的同步 Reader，供解压线程使用
+struct ChunkReader {
+    rx: mpsc::Receiver<Vec<u8>>,
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl Read for ChunkReader {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.buf.len() {
+            match self.rx.blocking_recv() {
+                Some(chunk) => {
+                    self.buf = chunk;
+                    self.pos = 0;
+                }
+                None => return Ok(0),
+            }
+        }
+        let n = out.len().min(self.buf.len() - self.pos);
+        out[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// 把写入的数据按块发往异步通道的同步 Writer，供打包线程使用
+struct ChunkWriter {
+    tx: mpsc::Sender<Vec<u8>>,
+    buf: Vec<u8>,
+}
+
+impl ChunkWriter {
+    fn send(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let chunk = std::mem::replace(&mut self.buf, Vec::with_capacity(CHUNK));
+        // 接收端已丢弃说明传输被取消或出错
+        self.tx
+            .blocking_send(chunk)
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "Transfer stopped"))
+    }
+}
+
+impl Write for ChunkWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(data);
+        if self.buf.len() >= CHUNK {
+            self.send()?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.send()
+    }
+}
+
+fn extract_tar<R: Read>(reader: R, dest: &str) -> io::Result<()> {
+    let mut archive = tar::Archive::new(reader);
+    archive.set_preserve_permissions(true);
+    archive.set_overwrite(true);
+    // unpack 会拒绝包含 `..` 或绝对路径的条目
+    archive.unpack(dest)
+}
+
+fn unpack(reader: ChunkReader, dest: &str, format: ArchiveFormat) -> io::Result<()> {
+    match format {
+        ArchiveFormat::Gzip => extract_tar(flate2::read::GzDecoder::new(reader), dest),
+        ArchiveFormat::Zstd => extract_tar(zstd::stream::read::Decoder::new(reader)?, dest),
+        ArchiveFormat::Tar => extract_tar(reader, dest),
+    }
+}
+
+fn append_tar<W: Write>(writer: W, src: &str) -> io::Result<W> {
+    let mut builder = tar::Builder::new(writer);
+    builder.follow_symlinks(false);
+    builder.append_dir_all(".", src)?;
+    builder.into_inner()
+}
+
+fn pack(src: &str, format: ArchiveFormat, writer: ChunkWriter) -> io::Result<()> {
+    let mut writer = match format {
+        ArchiveFormat::Gzip => append_tar(flate2::write::GzEncoder::new(writer, Compression::default()), src)?.finish()?,
+        ArchiveFormat::Zstd => append_tar(zstd::stream::write::Encoder::new(writer, 3)?, src)?.finish()?,
+        ArchiveFormat::Tar => append_tar(writer, src)?,
+    };
+    writer.flush()
+}
+
+/// `producer | consumer`，但两者任一失败都反映在退出码中（管道本身只返回最后一个命令的）。
+/// consumer 失败时返回它的退出码，否则返回 producer 的；不依赖并非所有 sh 都支持的 `pipefail`
+fn checked_pipe(producer: &str, consumer: &str) -> String {
+    let script = format!(
+        "exec 3>&1; p=$( {{ {{ {}; echo $? >&4; }} | {} >&3; }} 4>&1 ); c=$?; [ $c = 0 ] || exit $c; exit \"${{p:-1}}\"",
+        producer, consumer
+    );
+    format!("sh -c {}", shell_quote(&script))
+}
+
+/// 远程打包命令：归档内路径以 `./` 开头，解包到目标目录即得到原目录内容
+fn pack_command(remote_dir: &str, format: ArchiveFormat) -> String {
+    let dir = shell_quote(remote_dir);
+    match format {
+        ArchiveFormat::Gzip => format!("tar czf - -C {} .", dir),
+        ArchiveFormat::Zstd => checked_pipe(&format!("tar cf - -C {} .", dir), "zstd -q -c"),
+        ArchiveFormat::Tar => format!("tar cf - -C {} .", dir),
+    }
+}
+
+fn unpack_command(remote_dir: &str, format: ArchiveFormat) -> String {
+    let dir = shell_quote(remote_dir);
+    let extract = match format {
+        ArchiveFormat::Gzip => format!("tar xzf - -C {}", dir),
+        ArchiveFormat::Zstd => checked_pipe("zstd -d -q -c", &format!("tar xf - -C {}", dir)),
+        ArchiveFormat::Tar => format!("tar xf - -C {}", dir),
+    };
+    format!("mkdir -p {} && {}", dir, extract)
+}
+
+async fn require_tools(mgr: &SftpManager, session_id: &str, format: ArchiveFormat) -> Result<(), String> {
+    let tools: &[&str] = if format == ArchiveFormat::Zstd { &["tar", "zstd"] } else { &["tar"] };
+    for tool in tools {
+        if !matches!(exec_on_session(mgr, session_id, &format!("command -v {}", tool)).await, Ok((0, _))) {
+            return Err(format!("Remote host has no `{}` command", tool));
+        }
+    }
+    Ok(())
+}
+
+fn check_exit(exit: Option<u32>, stderr: &[u8]) -> Result<(), String> {
+    let message = String::from_utf8_lossy(stderr);
+    match exit {
+        Some(0) => Ok(()),
+        // GNU tar 在打包期间文件被修改时返回 1，归档本身仍然完整
+        Some(1) if message.contains("changed as we read it") => {
+            log::warn!("Remote tar reported changed files: {}", message.trim());
+            Ok(())
+        }
+        Some(code) => Err(format!("Remote tar failed (exit {}): {}", code, message.trim())),
+        None => Err(format!("Remote tar terminated: {}", message.trim())),
+    }
+}
+
+/// 下载数据的去向：保存为归档文件，或交给解压线程
+enum Sink {
+    File(tokio::fs::File),
+    Extract {
+        tx: mpsc::Sender<Vec<u8>>,
+        task: tokio::task::JoinHandle<io::Result<()>>,
+    },
+}
+
+impl Sink {
+    async fn write(&mut self, data: &[u8]) -> Result<(), String> {
+        match self {
+            Sink::File(file) => file.write_all(data).await.map_err(|e| e.to_string()),
+            // 解压线程出错退出后发送失败，具体错误由 finish 取得
+            Sink::Extract { tx, .. } => tx.send(data.to_vec()).await.map_err(|_| "Extraction stopped".to_string()),
+        }
+    }
+
+    async fn finish(self) -> Result<(), String> {
+        match self {
+            Sink::File(mut file) => file.flush().await.map_err(|e| e.to_string()),
+            Sink::Extract { tx, task } => {
+                drop(tx);
+                task.await
+                    .map_err(|e| e.to_string())?
+                    .map_err(|e| format!("Extraction failed: {}", e))
+            }
+        }
+    }
+}
+
+/// 在远程运行 `tar c` 并把输出流式写到本地；进度按实际传输的（压缩后）字节计算
+pub(crate) async fn run_download(
+    mgr: &SftpManager,
+    session_id: &str,
+    ctx: &mut TransferCtx,
+    remote_path: &str,
+    local_path: &str,
+    options: ArchiveOptions,
+) -> Result<(), String> {
+    require_tools(mgr, session_id, options.format).await?;
+    let mut sink = if options.extract {
+        tokio::fs::create_dir_all(local_path).await.map_err(|e| e.to_string())?;
+        let (tx, rx) = mpsc::channel(16);
+        let dest = local_path.to_string();
+        let reader = ChunkReader { rx, buf: Vec::new(), pos: 0 };
+        let task = tokio::task::spawn_blocking(move || unpack(reader, &dest, options.format));
+        Sink::Extract { tx, task }
+    } else {
+        Sink::File(tokio::fs::File::create(local_path).await.map_err(|e| e.to_string())?)
+    };
+
+    let mut channel = open_exec_channel(mgr, session_id, &pack_command(remote_path, options.format)).await?;
+    let received: Result<(), String> = async {
+        let mut stderr = Vec::new();
+        let mut exit = None;
+        while let Some(msg) = channel.wait().await {
+            match msg {
+                russh::ChannelMsg::Data { data } => {
+                    ctx.checkpoint().await?;
+                    ctx.throttle(data.len() as u64).await?;
+                    sink.write(&data).await?;
+                    ctx.advance(data.len() as u64, remote_path);
+                }
+                russh::ChannelMsg::ExtendedData { data, ext: 1 } => stderr.extend_from_slice(&data),
+                russh::ChannelMsg::ExitStatus { exit_status } => exit = Some(exit_status),
+                russh::ChannelMsg::Close => break,
+                _ => {}
+            }
+        }
+        check_exit(exit, &stderr)
+    }
+    .await;
+
+    let result = match received {
+        Ok(()) => sink.finish().await,
+        Err(e) => {
+            let _ = channel.close().await;
+            // 解压出错时报告解压错误；取消时保持 "Cancelled"
+            let finished = sink.finish().await;
+            Err(finished.err().filter(|_| e != "Cancelled").unwrap_or(e))
+        }
+    };
+    // 失败时删除不完整的归档文件；已解压的文件无法与原有内容区分，保留
+    if result.is_err() && !options.extract {
+        let _ = tokio::fs::remove_file(local_path).await;
+    }
+    result?;
+    ctx.total = ctx.done;
+    ctx.advance(0, remote_path);
+    Ok(())
+}
+
+/// 在本地打包目录并流式送入远程 `tar x`；remotePath 以 `/` 结尾时解包到其下的同名目录
+pub(crate) async fn run_upload(
+    mgr: &SftpManager,
+    session_id: &str,
+    ctx: &mut TransferCtx,
+    local_path: &str,
+    remote_path: &str,
+    options: ArchiveOptions,
+) -> Result<(), String> {
+    let meta = tokio::fs::metadata(local_path).await.map_err(|e| e.to_string())?;
+    if !meta.is_dir() {
+        return Err("Archive upload requires a local directory".to_string());
+    }
+    let dest = if remote_path.ends_with('/') {
+        let name = Path::new(local_path).file_name().and_then(|n| n.to_str()).unwrap_or("upload");
+        format!("{}{}", remote_path, name)
+    } else {
+        remote_path.to_string()
+    };
+    require_tools(mgr, session_id, options.format).await?;
+
+    let mut channel = open_exec_channel(mgr, session_id, &unpack_command(&dest, options.format)).await?;
+    let (tx, mut rx) = mpsc::channel::<Vec<u8>>(16);
+    let src = local_path.to_string();
+    let packer = tokio::task::spawn_blocking(move || pack(&src, options.format, ChunkWriter { tx, buf: Vec::with_capacity(CHUNK) }));
+
+    let sent: Result<(), String> = async {
+        while let Some(chunk) = rx.recv().await {
+            ctx.checkpoint().await?;
+            ctx.throttle(chunk.len() as u64).await?;
+            channel.data(&chunk[..]).await.map_err(|e| e.to_string())?;
+            ctx.advance(chunk.len() as u64, &dest);
+        }
+        Ok(())
+    }
+    .await;
+    // 丢弃接收端，让打包线程在取消或出错时尽快退出
+    drop(rx);
+    let packed = packer
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| format!("Failed to pack {}: {}", local_path, e));
+    if let Err(e) = sent.and(packed) {
+        let _ = channel.close().await;
+        return Err(e);
+    }
+
+    // 发送 EOF 后等待远程解包结束
+    channel.eof().await.map_err(|e| e.to_string())?;
+    let mut stderr = Vec::new();
+    let mut exit = None;
+    while let Some(msg) = channel.wait().await {
+        match msg {
+            russh::ChannelMsg::ExtendedData { data, ext: 1 } => stderr.extend_from_slice(&data),
+            russh::ChannelMsg::ExitStatus { exit_status } => exit = Some(exit_status),
+            russh::ChannelMsg::Close => break,
+            _ => {}
+        }
+    }
+    check_exit(exit, &stderr)?;
+    ctx.total = ctx.done;
+    ctx.advance(0, &dest);
+    Ok(())
+}
+
+/// 把远程目录打包下载；`extract` 为 true 时 localPath 是解压目标目录，否则是归档文件路径
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn sftp_archive_download(
+    app: AppHandle,
+    host_map: tauri::State<'_, SessionHostMap>,
+    mgr: tauri::State<'_, SftpManager>,
+    session_id: String,
+    transfer_id: String,
+    remote_path: String,
+    local_path: String,
+    format: Option<ArchiveFormat>,
+    extract: Option<bool>,
+    priority: Option<i32>,
+) -> Result<bool, String> {
+    let options = TransferOptions {
+        archive: Some(ArchiveOptions { format: format.unwrap_or_default(), extract: extract.unwrap_or(false) }),
+        ..Default::default()
+    };
+    register_and_run(
+        &app, &host_map, &mgr, TransferDirection::Download,
+        session_id, transfer_id, local_path, remote_path, options, priority.unwrap_or(0),
+    )
+    .await
+}
+
+/// 把本地目录打包上传并在远程解包
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn sftp_archive_upload(
+    app: AppHandle,
+    host_map: tauri::State<'_, SessionHostMap>,
+    mgr: tauri::State<'_, SftpManager>,
+    session_id: String,
+    transfer_id: String,
+    local_path: String,
+    remote_path: String,
+    format: Option<ArchiveFormat>,
+    priority: Option<i32>,
+) -> Result<bool, String> {
+    let options = TransferOptions {
+        archive: Some(ArchiveOptions { format: format.unwrap_or_default(), extract: false }),
+        ..Default::default()
+    };
+    register_and_run(
+        &app, &host_map, &mgr, TransferDirection::Upload,
+        session_id, transfer_id, local_path, remote_path, options, priority.unwrap_or(0),
+    )
+    .await
+}
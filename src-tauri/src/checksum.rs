@@ -0,0 +1,177 @@
+use md5::Md5;
+use russh_sftp::protocol::Packet;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncReadExt;
+use crate::sftp::{exec_on_session, open_raw_sftp, server_extensions, shell_quote, SftpManager};
+
+/// 单个文件的校验结果，记录在传输队列条目中
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ChecksumVerdict {
+    pub path: String,
+    pub algorithm: String,
+    /// 远程哈希的来源：check-file 扩展或 exec 通道
+    pub method: String,
+    #[serde(rename = "localHash")]
+    pub local_hash: String,
+    #[serde(rename = "remoteHash")]
+    pub remote_hash: String,
+    pub matched: bool,
+    /// 无法计算远程哈希时的原因
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ChecksumVerdict {
+    pub fn unavailable(path: &str, error: String) -> Self {
+        ChecksumVerdict {
+            path: path.to_string(),
+            algorithm: String::new(),
+            method: String::new(),
+            local_hash: String::new(),
+            remote_hash: String::new(),
+            matched: false,
+            error: Some(error),
+        }
+    }
+}
+
+/// exec 回退时依次尝试的命令
+const EXEC_HASHERS: &[(&str, &str)] = &[
+    ("sha256", "sha256sum"),
+    ("sha256", "shasum -a 256"),
+    ("md5", "md5sum"),
+];
+
+pub(crate) fn put_string(buf: &mut Vec<u8>, s: &str) {
+    buf.extend_from_slice(&(s.len() as u32).to_be_bytes());
+    buf.extend_from_slice(s.as_bytes());
+}
+
+fn take_string(data: &[u8]) -> Option<(String, &[u8])> {
+    let len = u32::from_be_bytes(data.get(..4)?.try_into().ok()?) as usize;
+    let s = data.get(4..4 + len)?;
+    Some((String::from_utf8_lossy(s).to_string(), &data[4 + len..]))
+}
+
+/// 通过 `check-file-name` 扩展让服务器计算整个文件的哈希
+async fn check_file_extension(mgr: &SftpManager, session_id: &str, path: &str) -> Option<(String, String)> {
+    let extensions = server_extensions(mgr, session_id).await.ok()?;
+    if !extensions.contains_key("check-file") && !extensions.contains_key("check-file-name") {
+        return None;
+    }
+    let raw = open_raw_sftp(mgr, session_id).await.ok()?;
+
+    let mut data = Vec::new();
+    put_string(&mut data, path);
+    put_string(&mut data, "sha256,md5");
+    data.extend_from_slice(&0u64.to_be_bytes()); // start offset
+    data.extend_from_slice(&0u64.to_be_bytes()); // length，0 表示到文件末尾
+    data.extend_from_slice(&0u32.to_be_bytes()); // block size，0 表示整个文件一个哈希
+
+    let reply = match raw.extended("check-file-name", data).await.ok()? {
+        Packet::ExtendedReply(reply) => reply.data,
+        _ => return None,
+    };
+    // 部分实现会在回复开头带上 "check-file"
+    let (mut algorithm, mut rest) = take_string(&reply)?;
+    if algorithm == "check-file" {
+        (algorithm, rest) = take_string(rest)?;
+    }
+    Some((algorithm, hex::encode(rest)))
+}
+
+/// 回退方案：在 exec 通道上运行 sha256sum / md5sum
+async fn exec_hash(mgr: &SftpManager, session_id: &str, path: &str) -> Option<(String, String)> {
+    for (algorithm, command) in EXEC_HASHERS {
+        let cmd = format!("{} -- {} 2>/dev/null", command, shell_quote(path));
+        let Ok((0, out)) = exec_on_session(mgr, session_id, &cmd).await else {
+            continue;
+        };
+        let text = String::from_utf8_lossy(&out);
+        if let Some(hash) = text.split_whitespace().next() {
+            if !hash.is_empty() && hash.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Some((algorithm.to_string(), hash.to_lowercase()));
+            }
+        }
+    }
+    None
+}
+
+/// 计算本地文件哈希
+pub async fn local_hash(path: &str, algorithm: &str) -> Result<String, String> {
+    let mut file = tokio::fs::File::open(path).await.map_err(|e| e.to_string())?;
+    let mut buf = vec![0u8; 256 * 1024];
+    let mut sha256 = Sha256::new();
+    let mut md5 = Md5::new();
+    loop {
+        let n = file.read(&mut buf).await.map_err(|e| e.to_string())?;
+        if n == 0 {
+            break;
+        }
+        match algorithm {
+            "sha256" => sha256.update(&buf[..n]),
+            "md5" => md5.update(&buf[..n]),
+            other => return Err(format!("Unsupported hash algorithm: {}", other)),
+        }
+    }
+    Ok(match algorithm {
+        "sha256" => hex::encode(sha256.finalize()),
+        _ => hex::encode(md5.finalize()),
+    })
+}
+
+/// 计算远程文件哈希，返回 (来源, 算法, 哈希)
+pub(crate) async fn remote_hash(mgr: &SftpManager, session_id: &str, path: &str) -> Result<(&'static str, String, String), String> {
+    if let Some((algorithm, hash)) = check_file_extension(mgr, session_id, path).await {
+        return Ok(("check-file", algorithm, hash));
+    }
+    let (algorithm, hash) = exec_hash(mgr, session_id, path).await.ok_or("Remote checksum unavailable")?;
+    Ok(("exec", algorithm, hash))
+}
+
+/// 比较远程文件与本地文件的哈希
+pub async fn verify(
+    mgr: &SftpManager,
+    session_id: &str,
+    remote_path: &str,
+    local_path: &str,
+) -> Result<ChecksumVerdict, String> {
+    let (method, algorithm, remote_hash) = remote_hash(mgr, session_id, remote_path).await?;
+    let local_hash = local_hash(local_path, &algorithm).await?;
+
+    Ok(ChecksumVerdict {
+        path: remote_path.to_string(),
+        matched: local_hash.eq_ignore_ascii_case(&remote_hash),
+        algorithm,
+        method: method.to_string(),
+        local_hash,
+        remote_hash,
+        error: None,
+    })
+}
+
+/// 比较两个会话中的远程文件；localHash 为源文件哈希，remoteHash 为目标文件哈希
+pub async fn verify_relay(
+    mgr: &SftpManager,
+    source_session: &str,
+    source_path: &str,
+    target_session: &str,
+    target_path: &str,
+) -> Result<ChecksumVerdict, String> {
+    let (source_method, source_algorithm, source_hash) = remote_hash(mgr, source_session, source_path).await?;
+    let (target_method, target_algorithm, target_hash) = remote_hash(mgr, target_session, target_path).await?;
+    // 两台主机可用的哈希工具不同时无法比较
+    if source_algorithm != target_algorithm {
+        return Err(format!("Hash algorithms differ: {} vs {}", source_algorithm, target_algorithm));
+    }
+    Ok(ChecksumVerdict {
+        path: target_path.to_string(),
+        matched: source_hash.eq_ignore_ascii_case(&target_hash),
+        algorithm: source_algorithm,
+        method: format!("{}/{}", source_method, target_method),
+        local_hash: source_hash,
+        remote_hash: target_hash,
+        error: None,
+    })
+}
@@ -2,6 +2,7 @@ use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 use tauri_plugin_store::StoreExt;
 use crate::crypto::{encrypt, decrypt, EncryptedData};
+use crate::triggers::Trigger;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct HostConfig {
@@ -20,6 +21,8 @@ pub struct HostConfig {
     pub last_connected: Option<String>,
     pub identity_file: Option<String>,
     pub description: Option<String>,
+    /// Respond 动作的 input 与凭据一样加密保存
+    pub triggers: Option<Vec<serde_json::Value>>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -52,6 +55,9 @@ pub struct DecryptedHostConfig {
     pub identity_file: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
+    /// 该主机的输出触发器（全局触发器另存）
+    #[serde(default)]
+    pub triggers: Vec<Trigger>,
 }
 
 fn new_id() -> String {
@@ -87,6 +93,51 @@ fn maybe_decrypt(app: &tauri::AppHandle, encrypted: &Option<serde_json::Value>)
     None
 }
 
+/// 触发器写入 hosts.json 前加密 Respond 动作的 input（可能是密码等敏感输入）
+pub(crate) fn encrypt_triggers(app: &tauri::AppHandle, triggers: &[Trigger]) -> Result<Vec<serde_json::Value>, String> {
+    triggers
+        .iter()
+        .map(|t| {
+            let mut value = serde_json::to_value(t).map_err(|e| e.to_string())?;
+            if let Some(action) = value.get_mut("action").and_then(|a| a.as_object_mut()) {
+                if action.get("type").and_then(|t| t.as_str()) == Some("respond") {
+                    let input = action.get("input").and_then(|i| i.as_str()).map(|i| i.to_string());
+                    let encrypted = maybe_encrypt(app, &input)
+                        .ok_or_else(|| format!("Failed to encrypt the response of trigger {:?}", t.name))?;
+                    action.insert("input".to_string(), encrypted);
+                }
+            }
+            Ok(value)
+        })
+        .collect()
+}
+
+/// 读取 hosts.json 中的触发器，解密 Respond 动作的 input；兼容未加密的旧数据
+pub(crate) fn decrypt_triggers(app: &tauri::AppHandle, triggers: Vec<serde_json::Value>) -> Vec<Trigger> {
+    triggers
+        .into_iter()
+        .filter_map(|mut value| {
+            if let Some(action) = value.get_mut("action").and_then(|a| a.as_object_mut()) {
+                if let Some(input) = action.get("input").filter(|i| !i.is_string()).cloned() {
+                    // 解密失败时丢弃该触发器，不能用空内容去回答提示
+                    let Some(input) = maybe_decrypt(app, &Some(input)) else {
+                        log::warn!("Cannot decrypt the response of a trigger in hosts.json, skipping it");
+                        return None;
+                    };
+                    action.insert("input".to_string(), serde_json::Value::String(input));
+                }
+            }
+            match serde_json::from_value(value) {
+                Ok(trigger) => Some(trigger),
+                Err(e) => {
+                    log::warn!("Invalid trigger in hosts.json: {}", e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
 #[tauri::command]
 pub async fn get_hosts(app: tauri::AppHandle) -> Result<Vec<DecryptedHostConfig>, String> {
     let store_path = PathBuf::from("hosts.json");
@@ -122,6 +173,7 @@ pub async fn get_hosts(app: tauri::AppHandle) -> Result<Vec<DecryptedHostConfig>
             last_connected: host.last_connected,
             identity_file: host.identity_file,
             description: host.description,
+            triggers: decrypt_triggers(&app, host.triggers.unwrap_or_default()),
         });
     }
 
@@ -130,6 +182,7 @@ pub async fn get_hosts(app: tauri::AppHandle) -> Result<Vec<DecryptedHostConfig>
 
 #[tauri::command]
 pub async fn save_host(app: tauri::AppHandle, host: DecryptedHostConfig) -> Result<String, String> {
+    crate::triggers::validate(&host.triggers)?;
     let store_path = PathBuf::from("hosts.json");
     let store = app.store(store_path.clone()).map_err(|e| e.to_string())?;
     
@@ -141,6 +194,7 @@ pub async fn save_host(app: tauri::AppHandle, host: DecryptedHostConfig) -> Resu
     let password = maybe_encrypt(&app, &host.password);
     let private_key = maybe_encrypt(&app, &host.private_key);
     let passphrase = maybe_encrypt(&app, &host.passphrase);
+    let triggers = encrypt_triggers(&app, &host.triggers)?;
 
     let encrypted_host = HostConfig {
         id: Some(host.id.clone()),
@@ -158,6 +212,7 @@ pub async fn save_host(app: tauri::AppHandle, host: DecryptedHostConfig) -> Resu
         last_connected: host.last_connected,
         identity_file: host.identity_file,
         description: host.description,
+        triggers: Some(triggers),
     };
 
     if let Some(pos) = hosts.iter().position(|h| h.id == encrypted_host.id) {
@@ -168,6 +223,7 @@ pub async fn save_host(app: tauri::AppHandle, host: DecryptedHostConfig) -> Resu
 
     store.set("hosts", serde_json::to_value(&hosts).map_err(|e| e.to_string())?);
     let _ = store.save();
+    crate::triggers::reload(&app, Some(&host.id)).await;
 
     Ok(host.id)
 }
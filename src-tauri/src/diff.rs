@@ -0,0 +1,429 @@
+use russh_sftp::client::SftpSession;
+use serde::{Deserialize, Serialize};
+use similar::{ChangeTag, TextDiff};
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use tauri::AppHandle;
+use tokio::sync::Mutex;
+use crate::sftp::{join_remote, sftp_get_host, SessionHostMap, SftpManager};
+use crate::sync::{local_join, scan_local, scan_remote, CompareMethod, Stamp, SyncSide, Tree};
+
+/// 哈希不可用时，逐字节比较的文件大小上限
+const BYTE_COMPARE_LIMIT: u64 = 16 * 1024 * 1024;
+/// 文本 diff 每个文件的大小上限
+const TEXT_DIFF_LIMIT: u64 = 2 * 1024 * 1024;
+
+/// 比较的一端：sessionId 为空表示本机
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffSide {
+    pub session_id: Option<String>,
+    pub path: String,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum DiffStatus {
+    OnlyLeft,
+    OnlyRight,
+    Differs,
+    Identical,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum DiffReason {
+    /// 一端是目录，另一端是文件
+    Type,
+    Size,
+    Mtime,
+    Hash,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffEntry {
+    /// 相对于两端根目录的路径
+    pub path: String,
+    pub status: DiffStatus,
+    pub is_dir: bool,
+    pub reasons: Vec<DiffReason>,
+    pub left: Option<SyncSide>,
+    pub right: Option<SyncSide>,
+    /// 无法比较内容时的原因
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffSummary {
+    pub only_left: usize,
+    pub only_right: usize,
+    pub differs: usize,
+    pub identical: usize,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DirDiff {
+    pub left_root: String,
+    pub right_root: String,
+    pub compare: CompareMethod,
+    /// 只存在于一端的目录只列出目录本身，不展开其子项
+    pub entries: Vec<DiffEntry>,
+    pub summary: DiffSummary,
+}
+
+#[derive(Serialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub enum LineKind {
+    Context,
+    Added,
+    Removed,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffLine {
+    pub kind: LineKind,
+    /// 从 1 开始的行号；新增行没有左侧行号，删除行没有右侧行号
+    pub left_line: Option<usize>,
+    pub right_line: Option<usize>,
+    pub text: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffHunk {
+    pub left_start: usize,
+    pub left_len: usize,
+    pub right_start: usize,
+    pub right_len: usize,
+    pub lines: Vec<DiffLine>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FileDiff {
+    /// 两端字节完全相同
+    pub identical: bool,
+    /// 按各自编码解码后的文本相同；identical 为 false 时说明差异只在编码或 BOM
+    pub same_text: bool,
+    /// 任一端是二进制文件时不生成行级 diff
+    pub binary: bool,
+    pub left_encoding: String,
+    pub right_encoding: String,
+    pub hunks: Vec<DiffHunk>,
+    /// 统一格式（unified diff）文本，便于复制
+    pub unified: String,
+}
+
+/// 解析后的一端
+enum Source {
+    Local,
+    Remote {
+        session_id: String,
+        sftp: Arc<Mutex<SftpSession>>,
+    },
+}
+
+impl Source {
+    async fn open(
+        app: &AppHandle,
+        host_map: &SessionHostMap,
+        mgr: &SftpManager,
+        side: &DiffSide,
+    ) -> Result<Source, String> {
+        match &side.session_id {
+            None => Ok(Source::Local),
+            Some(session_id) => {
+                let (sftp, _) = sftp_get_host(app, host_map, mgr, session_id).await?;
+                Ok(Source::Remote { session_id: session_id.clone(), sftp })
+            }
+        }
+    }
+
+    fn join(&self, root: &str, rel: &str) -> String {
+        match self {
+            Source::Local => local_join(root, rel),
+            Source::Remote { .. } => join_remote(root, rel),
+        }
+    }
+
+    /// 返回 (是否目录, 大小)
+    async fn stat(&self, path: &str) -> Result<(bool, u64), String> {
+        match self {
+            Source::Local => {
+                let meta = tokio::fs::metadata(path).await.map_err(|e| format!("{}: {}", path, e))?;
+                Ok((meta.is_dir(), meta.len()))
+            }
+            Source::Remote { sftp, .. } => {
+                let sftp = sftp.lock().await;
+                let meta = sftp.metadata(path).await.map_err(|e| format!("{}: {}", path, e))?;
+                Ok((meta.file_type().is_dir(), meta.size.unwrap_or(0)))
+            }
+        }
+    }
+
+    async fn scan(&self, root: &str) -> Result<Tree, String> {
+        if !self.stat(root).await?.0 {
+            return Err(format!("Not a directory: {}", root));
+        }
+        match self {
+            Source::Local => scan_local(root).await,
+            Source::Remote { sftp, .. } => scan_remote(sftp, root).await,
+        }
+    }
+
+    /// 读取整个文件，超过 limit 时报错
+    async fn read(&self, path: &str, limit: u64) -> Result<Vec<u8>, String> {
+        let (_, size) = self.stat(path).await?;
+        if size > limit {
+            return Err(format!("File too large to compare: {}", path));
+        }
+        match self {
+            Source::Local => tokio::fs::read(path).await.map_err(|e| e.to_string()),
+            Source::Remote { sftp, .. } => crate::range::read_at(sftp, path, 0, size).await,
+        }
+    }
+
+    /// 计算哈希；指定 algorithm 时远程端必须使用同一算法
+    async fn hash(&self, mgr: &SftpManager, path: &str, algorithm: Option<&str>) -> Result<(String, String), String> {
+        match self {
+            Source::Local => {
+                let algorithm = algorithm.unwrap_or("sha256");
+                Ok((algorithm.to_string(), crate::checksum::local_hash(path, algorithm).await?))
+            }
+            Source::Remote { session_id, .. } => {
+                let (_, alg, hash) = crate::checksum::remote_hash(mgr, session_id, path).await?;
+                match algorithm {
+                    Some(expected) if expected != alg => Err(format!("Hash algorithms differ: {} vs {}", expected, alg)),
+                    _ => Ok((alg, hash)),
+                }
+            }
+        }
+    }
+}
+
+/// 比较两端内容：优先比较哈希（远程端先算，以确定算法），不可用时逐字节比较
+async fn same_content(
+    mgr: &SftpManager,
+    left: (&Source, &str),
+    right: (&Source, &str),
+    size: u64,
+) -> Result<bool, String> {
+    let (first, second) = if matches!(left.0, Source::Local) { (right, left) } else { (left, right) };
+    let hashed = async {
+        let (algorithm, a) = first.0.hash(mgr, first.1, None).await?;
+        let (_, b) = second.0.hash(mgr, second.1, Some(&algorithm)).await?;
+        Ok::<bool, String>(a.eq_ignore_ascii_case(&b))
+    }
+    .await;
+    match hashed {
+        Ok(same) => Ok(same),
+        Err(_) if size <= BYTE_COMPARE_LIMIT => {
+            let a = left.0.read(left.1, BYTE_COMPARE_LIMIT).await?;
+            let b = right.0.read(right.1, BYTE_COMPARE_LIMIT).await?;
+            Ok(a == b)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn compare_entry(
+    mgr: &SftpManager,
+    compare: CompareMethod,
+    left: &Source,
+    left_root: &str,
+    right: &Source,
+    right_root: &str,
+    rel: &str,
+    l: &Stamp,
+    r: &Stamp,
+) -> DiffEntry {
+    let mut entry = DiffEntry {
+        path: rel.to_string(),
+        status: DiffStatus::Identical,
+        is_dir: l.is_dir,
+        reasons: Vec::new(),
+        left: Some(l.side()),
+        right: Some(r.side()),
+        error: None,
+    };
+    if l.is_dir != r.is_dir {
+        entry.reasons.push(DiffReason::Type);
+    } else if !l.is_dir {
+        // 部分文件系统的时间精度为 1~2 秒
+        let mtime_differs = l.mtime.abs_diff(r.mtime) > 1;
+        if l.size != r.size {
+            entry.reasons.push(DiffReason::Size);
+        } else if compare == CompareMethod::Checksum {
+            let paths = (left.join(left_root, rel), right.join(right_root, rel));
+            match same_content(mgr, (left, &paths.0), (right, &paths.1), l.size).await {
+                Ok(true) => {}
+                Ok(false) => entry.reasons.push(DiffReason::Hash),
+                Err(e) => entry.error = Some(e),
+            }
+        }
+        // 按哈希比较时内容相同就视为一致，只有内容不同时才附带 mtime 差异
+        if mtime_differs && (compare == CompareMethod::SizeMtime || !entry.reasons.is_empty()) {
+            entry.reasons.push(DiffReason::Mtime);
+        }
+    }
+    if !entry.reasons.is_empty() {
+        entry.status = DiffStatus::Differs;
+    }
+    entry
+}
+
+/// 只存在于一端的目录下的子项不单独列出
+fn under_one_sided(rel: &str, one_sided: &[String]) -> bool {
+    one_sided.iter().any(|dir| rel.len() > dir.len() && rel.starts_with(dir.as_str()) && rel.as_bytes()[dir.len()] == b'/')
+}
+
+/// 比较两棵目录树：本地 vs 远程，或两个会话中的远程目录
+#[tauri::command]
+pub async fn sftp_diff_dirs(
+    app: AppHandle,
+    host_map: tauri::State<'_, SessionHostMap>,
+    mgr: tauri::State<'_, SftpManager>,
+    left: DiffSide,
+    right: DiffSide,
+    compare: Option<CompareMethod>,
+) -> Result<DirDiff, String> {
+    let compare = compare.unwrap_or_default();
+    let left_src = Source::open(&app, &host_map, &mgr, &left).await?;
+    let right_src = Source::open(&app, &host_map, &mgr, &right).await?;
+    let left_tree = left_src.scan(&left.path).await?;
+    let right_tree = right_src.scan(&right.path).await?;
+
+    let mut entries = Vec::new();
+    let mut summary = DiffSummary::default();
+    let mut one_sided: Vec<String> = Vec::new();
+    let paths: BTreeSet<&String> = left_tree.keys().chain(right_tree.keys()).collect();
+    for rel in paths {
+        if under_one_sided(rel, &one_sided) {
+            continue;
+        }
+        let entry = match (left_tree.get(rel), right_tree.get(rel)) {
+            (Some(l), Some(r)) => {
+                compare_entry(&mgr, compare, &left_src, &left.path, &right_src, &right.path, rel, l, r).await
+            }
+            (l, r) => {
+                let stamp = l.or(r).copied().unwrap_or(Stamp { is_dir: false, size: 0, mtime: 0 });
+                if stamp.is_dir {
+                    one_sided.push(rel.clone());
+                }
+                DiffEntry {
+                    path: rel.clone(),
+                    status: if l.is_some() { DiffStatus::OnlyLeft } else { DiffStatus::OnlyRight },
+                    is_dir: stamp.is_dir,
+                    reasons: Vec::new(),
+                    left: l.map(|s| s.side()),
+                    right: r.map(|s| s.side()),
+                    error: None,
+                }
+            }
+        };
+        match entry.status {
+            DiffStatus::OnlyLeft => summary.only_left += 1,
+            DiffStatus::OnlyRight => summary.only_right += 1,
+            DiffStatus::Differs => summary.differs += 1,
+            DiffStatus::Identical => summary.identical += 1,
+        }
+        entries.push(entry);
+    }
+
+    Ok(DirDiff {
+        left_root: left.path,
+        right_root: right.path,
+        compare,
+        entries,
+        summary,
+    })
+}
+
+fn is_binary(data: &[u8]) -> bool {
+    data.iter().take(8192).any(|&b| b == 0)
+}
+
+/// 两个文本文件的行级 diff，context 为每个变更块前后保留的上下文行数（默认 3）
+#[tauri::command]
+pub async fn sftp_diff_file(
+    app: AppHandle,
+    host_map: tauri::State<'_, SessionHostMap>,
+    mgr: tauri::State<'_, SftpManager>,
+    left: DiffSide,
+    right: DiffSide,
+    context: Option<usize>,
+) -> Result<FileDiff, String> {
+    let left_src = Source::open(&app, &host_map, &mgr, &left).await?;
+    let right_src = Source::open(&app, &host_map, &mgr, &right).await?;
+    let left_data = left_src.read(&left.path, TEXT_DIFF_LIMIT).await?;
+    let right_data = right_src.read(&right.path, TEXT_DIFF_LIMIT).await?;
+    let identical = left_data == right_data;
+
+    if is_binary(&left_data) || is_binary(&right_data) {
+        return Ok(FileDiff {
+            identical,
+            same_text: identical,
+            binary: true,
+            left_encoding: String::new(),
+            right_encoding: String::new(),
+            hunks: Vec::new(),
+            unified: String::new(),
+        });
+    }
+
+    // 按各自检测到的编码解码后再比较，GBK 与 UTF-8 的同一内容不产生行级差异，由 same_text 标出
+    let left_text = crate::encoding::decode(&left_data);
+    let right_text = crate::encoding::decode(&right_data);
+    let diff = TextDiff::from_lines(&left_text.text, &right_text.text);
+    let context = context.unwrap_or(3);
+
+    let hunks = diff
+        .grouped_ops(context)
+        .iter()
+        .filter_map(|group| {
+            let (first, last) = (group.first()?, group.last()?);
+            let (left_range, right_range) = (first.old_range().start..last.old_range().end, first.new_range().start..last.new_range().end);
+            let lines = group
+                .iter()
+                .flat_map(|op| diff.iter_changes(op))
+                .map(|change| DiffLine {
+                    kind: match change.tag() {
+                        ChangeTag::Equal => LineKind::Context,
+                        ChangeTag::Insert => LineKind::Added,
+                        ChangeTag::Delete => LineKind::Removed,
+                    },
+                    left_line: change.old_index().map(|i| i + 1),
+                    right_line: change.new_index().map(|i| i + 1),
+                    text: change.value().trim_end_matches(['\r', '\n']).to_string(),
+                })
+                .collect();
+            Some(DiffHunk {
+                left_start: left_range.start + 1,
+                left_len: left_range.len(),
+                right_start: right_range.start + 1,
+                right_len: right_range.len(),
+                lines,
+            })
+        })
+        .collect();
+    let unified = diff
+        .unified_diff()
+        .context_radius(context)
+        .header(&left.path, &right.path)
+        .to_string();
+
+    Ok(FileDiff {
+        identical,
+        same_text: left_text.text == right_text.text,
+        binary: false,
+        left_encoding: left_text.encoding.to_string(),
+        right_encoding: right_text.encoding.to_string(),
+        hunks,
+        unified,
+    })
+}
@@ -0,0 +1,352 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::SystemTime;
+use tauri::{AppHandle, Emitter, Manager};
+use tauri_plugin_opener::OpenerExt;
+use tauri_plugin_shell::ShellExt;
+use tokio::sync::{Mutex, RwLock};
+use crate::sftp::{atomic_write, in_session, sftp_get_host, SaveOptions, SessionHostMap, SftpManager};
+
+/// 一个在外部编辑器中打开的远程文件
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct EditSession {
+    pub edit_id: String,
+    pub session_id: String,
+    pub remote_path: String,
+    pub local_path: String,
+    pub opened_at: String,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum EditState {
+    /// 本地保存已上传
+    Synced,
+    /// 已用远程内容覆盖本地副本
+    Reloaded,
+    /// 上次同步后远程文件被其他人修改，等待 `sftp_edit_resolve`
+    Conflict,
+    Error,
+}
+
+/// `sftp:edit-status` 事件负载
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct EditStatusEvent {
+    pub edit_id: String,
+    pub session_id: String,
+    pub remote_path: String,
+    pub state: EditState,
+    pub error: Option<String>,
+    pub bytes: u64,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub enum EditResolution {
+    /// 用本地副本覆盖远程文件
+    Overwrite,
+    /// 放弃本地修改，重新下载远程文件
+    Reload,
+}
+
+/// 本地文件的大小与修改时间，用于检测保存
+type LocalStamp = (u64, SystemTime);
+/// 远程文件的大小与 mtime，用于检测他人修改
+type RemoteStamp = (u64, u32);
+
+struct WatchState {
+    local: Option<LocalStamp>,
+    remote: RemoteStamp,
+    conflicted: bool,
+}
+
+struct EditEntry {
+    info: EditSession,
+    workspace: PathBuf,
+    stop: AtomicBool,
+    state: Mutex<WatchState>,
+}
+
+pub struct EditManager {
+    /// editId -> 正在编辑的文件
+    edits: RwLock<HashMap<String, Arc<EditEntry>>>,
+}
+
+impl EditManager {
+    pub fn new() -> Self {
+        EditManager { edits: RwLock::new(HashMap::new()) }
+    }
+}
+
+/// 所有临时副本的根目录
+fn edit_root() -> PathBuf {
+    std::env::temp_dir().join("lynxshell-edit")
+}
+
+/// 启动时清理上次异常退出遗留的临时副本
+pub fn cleanup_workspace() {
+    let _ = std::fs::remove_dir_all(edit_root());
+}
+
+async fn local_stamp(path: &str) -> Option<LocalStamp> {
+    let meta = tokio::fs::metadata(path).await.ok()?;
+    Some((meta.len(), meta.modified().ok()?))
+}
+
+async fn remote_stamp(app: &AppHandle, session_id: &str, path: &str) -> Result<RemoteStamp, String> {
+    let host_map = app.state::<SessionHostMap>();
+    let mgr = app.state::<SftpManager>();
+    let (sftp_arc, _) = sftp_get_host(app, &host_map, &mgr, session_id).await?;
+    let sftp = sftp_arc.lock().await;
+    let meta = sftp.metadata(path).await.map_err(|e| e.to_string())?;
+    Ok((meta.size.unwrap_or(0), meta.mtime.unwrap_or(0)))
+}
+
+async fn download(app: &AppHandle, session_id: &str, remote_path: &str, local_path: &str) -> Result<u64, String> {
+    let host_map = app.state::<SessionHostMap>();
+    let mgr = app.state::<SftpManager>();
+    let (sftp_arc, _) = sftp_get_host(app, &host_map, &mgr, session_id).await?;
+    let data = {
+        let sftp = sftp_arc.lock().await;
+        sftp.read(remote_path).await.map_err(|e| e.to_string())?
+    };
+    tokio::fs::write(local_path, &data).await.map_err(|e| e.to_string())?;
+    Ok(data.len() as u64)
+}
+
+async fn upload(app: &AppHandle, session_id: &str, local_path: &str, remote_path: &str) -> Result<u64, String> {
+    let host_map = app.state::<SessionHostMap>();
+    let mgr = app.state::<SftpManager>();
+    let (sftp_arc, _) = sftp_get_host(app, &host_map, &mgr, session_id).await?;
+    let data = tokio::fs::read(local_path).await.map_err(|e| e.to_string())?;
+    atomic_write(&mgr, session_id, &sftp_arc, remote_path, &data, &SaveOptions::default()).await?;
+    Ok(data.len() as u64)
+}
+
+fn emit_status(app: &AppHandle, entry: &EditEntry, state: EditState, error: Option<String>, bytes: u64) {
+    let _ = app.emit("sftp:edit-status", EditStatusEvent {
+        edit_id: entry.info.edit_id.clone(),
+        session_id: entry.info.session_id.clone(),
+        remote_path: entry.info.remote_path.clone(),
+        state,
+        error,
+        bytes,
+    });
+}
+
+/// 把本地副本上传回远程；非强制时若远程已被修改则标记冲突
+async fn sync_back(app: &AppHandle, entry: &EditEntry, state: &mut WatchState, force: bool) {
+    let info = &entry.info;
+    let result: Result<Option<u64>, String> = async {
+        if !force {
+            let current = remote_stamp(app, &info.session_id, &info.remote_path).await?;
+            if current != state.remote {
+                return Ok(None);
+            }
+        }
+        let stamp = local_stamp(&info.local_path).await;
+        let bytes = upload(app, &info.session_id, &info.local_path, &info.remote_path).await?;
+        state.local = stamp;
+        state.remote = remote_stamp(app, &info.session_id, &info.remote_path).await?;
+        Ok(Some(bytes))
+    }
+    .await;
+
+    match result {
+        Ok(Some(bytes)) => {
+            state.conflicted = false;
+            emit_status(app, entry, EditState::Synced, None, bytes);
+        }
+        Ok(None) => {
+            state.conflicted = true;
+            emit_status(app, entry, EditState::Conflict, None, 0);
+        }
+        Err(e) => emit_status(app, entry, EditState::Error, Some(e), 0),
+    }
+}
+
+/// 轮询本地副本；连续两次检测到相同的新状态才上传，避免编辑器分步写入时上传半截文件
+async fn watch(app: AppHandle, entry: Arc<EditEntry>) {
+    let mut pending: Option<LocalStamp> = None;
+    while !entry.stop.load(Ordering::Relaxed) {
+        tokio::time::sleep(std::time::Duration::from_millis(1000)).await;
+        let Some(now) = local_stamp(&entry.info.local_path).await else {
+            continue;
+        };
+        let mut state = entry.state.lock().await;
+        if state.local == Some(now) || state.conflicted {
+            pending = None;
+            continue;
+        }
+        if pending != Some(now) {
+            pending = Some(now);
+            continue;
+        }
+        pending = None;
+        sync_back(&app, &entry, &mut state, false).await;
+    }
+}
+
+/// 用配置的编辑器命令（如 `code --wait`）或系统默认程序打开文件。
+/// 文件名来自远程路径，不能经过 `cmd /C` 之类会重新解析参数的 shell
+fn launch_editor(app: &AppHandle, path: &str, editor: Option<String>) -> Result<(), String> {
+    let editor = editor
+        .or_else(|| crate::settings::load(app).external_editor)
+        .filter(|e| !e.trim().is_empty());
+    match &editor {
+        Some(cmd) => {
+            let mut parts = cmd.split_whitespace();
+            let program = parts.next().ok_or("Empty editor command")?;
+            app.shell().command(program).args(parts).arg(path).spawn().map_err(|e| e.to_string())?;
+        }
+        None => app.opener().open_path(path, None::<&str>).map_err(|e| e.to_string())?,
+    }
+    Ok(())
+}
+
+async fn close_entry(entry: &EditEntry) {
+    entry.stop.store(true, Ordering::Relaxed);
+    let _ = tokio::fs::remove_dir_all(&entry.workspace).await;
+}
+
+/// 会话断开时停止监视并删除该会话的所有临时副本
+pub async fn close_session(edits: &EditManager, session_id: &str) {
+    let closed: Vec<Arc<EditEntry>> = {
+        let mut map = edits.edits.write().await;
+        let ids: Vec<String> = map
+            .iter()
+            .filter(|(_, e)| e.info.session_id == session_id)
+            .map(|(id, _)| id.clone())
+            .collect();
+        ids.iter().filter_map(|id| map.remove(id)).collect()
+    };
+    for entry in closed {
+        close_entry(&entry).await;
+    }
+    let _ = tokio::fs::remove_dir_all(edit_root().join(session_id)).await;
+}
+
+/// 下载到临时目录并用外部编辑器打开，之后每次保存自动上传
+#[tauri::command]
+pub async fn sftp_edit_open(
+    app: AppHandle,
+    edits: tauri::State<'_, EditManager>,
+    session_id: String,
+    remote_path: String,
+    editor: Option<String>,
+) -> Result<EditSession, String> {
+    // 同一文件已在编辑中时只重新唤起编辑器
+    let existing = edits
+        .edits
+        .read()
+        .await
+        .values()
+        .find(|e| e.info.session_id == session_id && e.info.remote_path == remote_path)
+        .cloned();
+    if let Some(entry) = existing {
+        launch_editor(&app, &entry.info.local_path, editor)?;
+        return Ok(entry.info.clone());
+    }
+
+    let edit_id = uuid::Uuid::new_v4().to_string();
+    let workspace = edit_root().join(&session_id).join(&edit_id);
+    tokio::fs::create_dir_all(&workspace).await.map_err(|e| e.to_string())?;
+    let file_name = Path::new(&remote_path)
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| "untitled".to_string());
+    let local_path = workspace.join(file_name).to_string_lossy().to_string();
+
+    let prepared: Result<WatchState, String> = async {
+        download(&app, &session_id, &remote_path, &local_path).await?;
+        Ok(WatchState {
+            local: local_stamp(&local_path).await,
+            remote: remote_stamp(&app, &session_id, &remote_path).await?,
+            conflicted: false,
+        })
+    }
+    .await;
+    let state = match prepared {
+        Ok(state) => state,
+        Err(e) => {
+            let _ = tokio::fs::remove_dir_all(&workspace).await;
+            return Err(e);
+        }
+    };
+
+    let entry = Arc::new(EditEntry {
+        info: EditSession {
+            edit_id: edit_id.clone(),
+            session_id,
+            remote_path,
+            local_path: local_path.clone(),
+            opened_at: chrono::Utc::now().to_rfc3339(),
+        },
+        workspace,
+        stop: AtomicBool::new(false),
+        state: Mutex::new(state),
+    });
+    edits.edits.write().await.insert(edit_id, entry.clone());
+    tauri::async_runtime::spawn(watch(app.clone(), entry.clone()));
+
+    // 启动失败时保留会话，再次打开同一文件会重试启动编辑器
+    launch_editor(&app, &local_path, editor)?;
+    Ok(entry.info.clone())
+}
+
+#[tauri::command]
+pub async fn sftp_edit_list(
+    edits: tauri::State<'_, EditManager>,
+    session_id: Option<String>,
+) -> Result<Vec<EditSession>, String> {
+    Ok(edits
+        .edits
+        .read()
+        .await
+        .values()
+        .filter(|e| in_session(session_id.as_deref(), &e.info.session_id))
+        .map(|e| e.info.clone())
+        .collect())
+}
+
+/// 处理 `sftp:edit-status` 报告的冲突
+#[tauri::command]
+pub async fn sftp_edit_resolve(
+    app: AppHandle,
+    edits: tauri::State<'_, EditManager>,
+    edit_id: String,
+    resolution: EditResolution,
+) -> Result<(), String> {
+    let entry = edits.edits.read().await.get(&edit_id).cloned().ok_or("Edit session not found")?;
+    let mut state = entry.state.lock().await;
+    match resolution {
+        EditResolution::Overwrite => sync_back(&app, &entry, &mut state, true).await,
+        EditResolution::Reload => {
+            let info = &entry.info;
+            let bytes = download(&app, &info.session_id, &info.remote_path, &info.local_path).await?;
+            state.local = local_stamp(&info.local_path).await;
+            state.remote = remote_stamp(&app, &info.session_id, &info.remote_path).await?;
+            state.conflicted = false;
+            emit_status(&app, &entry, EditState::Reloaded, None, bytes);
+        }
+    }
+    Ok(())
+}
+
+/// 停止同步并删除临时副本；未上传的修改会丢失
+#[tauri::command]
+pub async fn sftp_edit_close(
+    edits: tauri::State<'_, EditManager>,
+    edit_id: String,
+) -> Result<bool, String> {
+    let Some(entry) = edits.edits.write().await.remove(&edit_id) else {
+        return Ok(false);
+    };
+    close_entry(&entry).await;
+    Ok(true)
+}
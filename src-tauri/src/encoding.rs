@@ -0,0 +1,198 @@
+use encoding_rs::Encoding;
+
+/// 解码后的文本及其原始编码信息，保存时据此还原
+pub struct DecodedText {
+    pub text: String,
+    /// WHATWG 编码名，如 `UTF-8`、`GBK`、`Shift_JIS`、`windows-1252`
+    pub encoding: &'static str,
+    pub bom: bool,
+    /// 统一使用的换行符：`lf`、`crlf` 或 `cr`；没有换行或混用多种换行时为 None，
+    /// 保存时原样回传即可保持原来的换行不变
+    pub line_ending: Option<&'static str>,
+    /// 文件中是否混用了多种换行符
+    pub mixed_line_endings: bool,
+}
+
+/// 无 BOM 且不是合法 UTF-8 时依次尝试的编码；得分相同时靠前者优先
+const CANDIDATES: &[&Encoding] = &[
+    encoding_rs::GBK,
+    encoding_rs::BIG5,
+    encoding_rs::SHIFT_JIS,
+    encoding_rs::EUC_JP,
+    encoding_rs::EUC_KR,
+];
+
+/// 是否为该编码中的常用汉字（GB2312 一级字、Big5 常用字）。
+/// Latin-1 文本中相邻的两个重音字母（如 `öß` = `F6 DF`）也能凑成合法的双字节字符，但多落在次常用区或扩展区
+fn is_common_hanzi(encoding: &'static Encoding, c: char) -> bool {
+    let range = if encoding == encoding_rs::GBK {
+        0xB0..=0xD7
+    } else if encoding == encoding_rs::BIG5 {
+        0xA4..=0xC6
+    } else {
+        return true;
+    };
+    let mut buf = [0u8; 4];
+    let (bytes, _, had_errors) = encoding.encode(c.encode_utf8(&mut buf));
+    !had_errors && bytes.len() == 2 && range.contains(&bytes[0]) && bytes[1] >= 0xA1
+}
+
+/// 粗略评估解码结果是否像正常文本：常见 CJK 字符加分，
+/// 半角片假名、私用区与其他罕见字符减分；夹在两个 ASCII 字母之间的单个 CJK 字符
+/// 多半是西文重音字母被误配成了双字节字符，同样减分
+fn plausibility(encoding: &'static Encoding, text: &str) -> i64 {
+    let chars: Vec<char> = text.chars().collect();
+    let letter = |i: Option<usize>| i.and_then(|i| chars.get(i)).is_some_and(|c| c.is_ascii_alphabetic());
+    chars
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| !c.is_ascii())
+        .map(|(i, &c)| {
+            let cjk = matches!(c as u32, 0x4E00..=0x9FFF | 0x3040..=0x30FF | 0xAC00..=0xD7AF);
+            if cjk && letter(i.checked_sub(1)) && letter(Some(i + 1)) {
+                return -1;
+            }
+            match c as u32 {
+                0x4E00..=0x9FFF if !is_common_hanzi(encoding, c) => 0,
+                0x4E00..=0x9FFF => 1,   // CJK 统一汉字
+                0x3040..=0x30FF => 2,   // 平假名、片假名
+                0xAC00..=0xD7AF => 1,   // 谚文音节
+                0x3000..=0x303F => 1,   // CJK 标点
+                0xFF01..=0xFF5E => 1,   // 全角 ASCII
+                _ => -2,
+            }
+        })
+        .sum()
+}
+
+fn detect(data: &[u8]) -> &'static Encoding {
+    if std::str::from_utf8(data).is_ok() {
+        return encoding_rs::UTF_8;
+    }
+    let mut best: Option<(&'static Encoding, i64)> = None;
+    for &encoding in CANDIDATES {
+        let Some(text) = encoding.decode_without_bom_handling_and_without_replacement(data) else {
+            continue;
+        };
+        let score = plausibility(encoding, &text);
+        if score > 0 && best.is_none_or(|(_, s)| score > s) {
+            best = Some((encoding, score));
+        }
+    }
+    // 单字节编码总能解码，作为最后的回退（Latin-1 的超集）
+    best.map(|(e, _)| e).unwrap_or(encoding_rs::WINDOWS_1252)
+}
+
+fn detect_line_ending(text: &str) -> (Option<&'static str>, bool) {
+    let bytes = text.as_bytes();
+    let (mut crlf, mut lf, mut cr) = (0usize, 0usize, 0usize);
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\r' if bytes.get(i + 1) == Some(&b'\n') => {
+                crlf += 1;
+                i += 1;
+            }
+            b'\r' => cr += 1,
+            b'\n' => lf += 1,
+            _ => {}
+        }
+        i += 1;
+    }
+    let kinds = [(crlf, "crlf"), (lf, "lf"), (cr, "cr")];
+    let mixed = kinds.iter().filter(|(n, _)| *n > 0).count() > 1;
+    // 混用时不报告某一种，避免保存时把其余的行改写掉
+    let single = kinds.iter().find(|(n, _)| *n > 0).map(|(_, name)| *name).filter(|_| !mixed);
+    (single, mixed)
+}
+
+/// 按 BOM 或启发式检测编码并解码，BOM 不包含在返回的文本中
+pub fn decode(data: &[u8]) -> DecodedText {
+    let (encoding, bom_len) = match Encoding::for_bom(data) {
+        Some((encoding, len)) => (encoding, len),
+        None => (detect(data), 0),
+    };
+    let (text, _) = encoding.decode_without_bom_handling(&data[bom_len..]);
+    let text = text.into_owned();
+    let (line_ending, mixed_line_endings) = detect_line_ending(&text);
+    DecodedText {
+        text,
+        encoding: encoding.name(),
+        bom: bom_len > 0,
+        line_ending,
+        mixed_line_endings,
+    }
+}
+
+/// 把换行统一成指定风格
+fn normalize_line_endings(text: &str, line_ending: &str) -> Result<String, String> {
+    let target = match line_ending {
+        "lf" => "\n",
+        "crlf" => "\r\n",
+        "cr" => "\r",
+        other => return Err(format!("Unknown line ending: {}", other)),
+    };
+    let unified = text.replace("\r\n", "\n").replace('\r', "\n");
+    Ok(if target == "\n" { unified } else { unified.replace('\n', target) })
+}
+
+/// 按指定编码、BOM 与换行符编码文本；存在无法表示的字符时报错而不是静默替换
+pub fn encode(text: &str, encoding: &str, bom: bool, line_ending: Option<&str>) -> Result<Vec<u8>, String> {
+    let text = match line_ending {
+        Some(le) => normalize_line_endings(text, le)?,
+        None => text.to_string(),
+    };
+    let encoding = Encoding::for_label(encoding.as_bytes()).ok_or_else(|| format!("Unknown encoding: {}", encoding))?;
+
+    // encoding_rs 的编码器不输出 UTF-16，需要手动处理
+    let mut out = Vec::new();
+    if encoding == encoding_rs::UTF_16LE || encoding == encoding_rs::UTF_16BE {
+        let le = encoding == encoding_rs::UTF_16LE;
+        if bom {
+            out.extend_from_slice(if le { &[0xFF, 0xFE] } else { &[0xFE, 0xFF] });
+        }
+        for unit in text.encode_utf16() {
+            out.extend_from_slice(&if le { unit.to_le_bytes() } else { unit.to_be_bytes() });
+        }
+        return Ok(out);
+    }
+
+    if bom && encoding == encoding_rs::UTF_8 {
+        out.extend_from_slice(&[0xEF, 0xBB, 0xBF]);
+    }
+    let (bytes, _, had_errors) = encoding.encode(&text);
+    if had_errors {
+        return Err(format!("Content contains characters that cannot be encoded as {}", encoding.name()));
+    }
+    out.extend_from_slice(&bytes);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latin1_with_adjacent_accents_is_not_gbk() {
+        // "Die Größe der Straße ist schön." 的 ISO-8859-1 编码，其中 `öß` 也是合法的 GBK 双字节字符
+        let data = b"Die Gr\xf6\xdfe der Stra\xdfe ist sch\xf6n.\n";
+        let decoded = decode(data);
+        assert_eq!(decoded.encoding, "windows-1252");
+        assert_eq!(decoded.text, "Die Größe der Straße ist schön.\n");
+    }
+
+    #[test]
+    fn gbk_text_is_detected() {
+        let (data, _, _) = encoding_rs::GBK.encode("配置文件：服务器地址与端口\n");
+        assert_eq!(decode(&data).encoding, "GBK");
+    }
+
+    #[test]
+    fn mixed_line_endings_survive_a_round_trip() {
+        let data = b"a\r\nb\nc\r\n";
+        let decoded = decode(data);
+        assert!(decoded.mixed_line_endings);
+        assert_eq!(decoded.line_ending, None);
+        assert_eq!(encode(&decoded.text, decoded.encoding, decoded.bom, decoded.line_ending).unwrap(), data);
+    }
+}
@@ -0,0 +1,345 @@
+use encoding_rs::{Decoder, Encoding};
+use russh_sftp::client::SftpSession;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::{Mutex, RwLock};
+use crate::range::{file_size, read_at, tail_lines, TailResult};
+use crate::sftp::{exec_on_session, in_session, open_exec_channel, shell_quote, sftp_get_host, SessionHostMap, SftpManager};
+
+/// 轮询模式下每次最多读取的字节数，追加很快时分多次读完
+const READ_CHUNK: u64 = 256 * 1024;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum FollowMode {
+    /// 服务器有 `tail` 时用 exec，否则轮询
+    Auto,
+    /// 通过 SFTP 轮询文件大小并按偏移读取新增内容
+    Poll,
+    /// 在 exec 通道上运行 `tail -F`
+    Exec,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FollowSession {
+    pub follow_id: String,
+    pub session_id: String,
+    pub path: String,
+    /// 实际使用的模式（Poll 或 Exec）
+    pub mode: FollowMode,
+    pub started_at: String,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FollowStartResult {
+    pub follow: FollowSession,
+    /// 开始跟随前文件末尾的若干行
+    pub initial: TailResult,
+}
+
+/// `sftp:follow-data` 事件负载
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FollowDataEvent {
+    pub follow_id: String,
+    pub session_id: String,
+    pub path: String,
+    pub data: String,
+    /// 本段内容结束处的文件偏移
+    pub offset: u64,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum FollowState {
+    /// 文件被截断，从头重新读取
+    Truncated,
+    /// 文件被替换（日志轮转），开始跟随新文件
+    Rotated,
+    /// 文件暂时不存在，等待重新出现
+    Missing,
+    /// 跟随已结束（主动停止或出错）
+    Stopped,
+}
+
+/// `sftp:follow-status` 事件负载
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FollowStatusEvent {
+    pub follow_id: String,
+    pub session_id: String,
+    pub path: String,
+    pub state: FollowState,
+    pub error: Option<String>,
+}
+
+struct FollowEntry {
+    info: FollowSession,
+    stop: AtomicBool,
+}
+
+pub struct FollowManager {
+    /// followId -> 正在跟随的文件
+    follows: RwLock<HashMap<String, Arc<FollowEntry>>>,
+}
+
+impl FollowManager {
+    pub fn new() -> Self {
+        FollowManager { follows: RwLock::new(HashMap::new()) }
+    }
+}
+
+/// 跟随过程中的读取位置与流式解码器（跨块保留不完整的多字节字符）
+struct Cursor {
+    offset: u64,
+    encoding: &'static Encoding,
+    decoder: Decoder,
+}
+
+impl Cursor {
+    fn decode(&mut self, data: &[u8]) -> String {
+        let capacity = self.decoder.max_utf8_buffer_length(data.len()).unwrap_or(data.len() * 3);
+        let mut text = String::with_capacity(capacity);
+        let _ = self.decoder.decode_to_string(data, &mut text, false);
+        text
+    }
+
+    fn reset(&mut self) {
+        self.offset = 0;
+        self.decoder = self.encoding.new_decoder_without_bom_handling();
+    }
+}
+
+fn emit_data(app: &AppHandle, entry: &FollowEntry, data: String, offset: u64) {
+    if data.is_empty() {
+        return;
+    }
+    let _ = app.emit("sftp:follow-data", FollowDataEvent {
+        follow_id: entry.info.follow_id.clone(),
+        session_id: entry.info.session_id.clone(),
+        path: entry.info.path.clone(),
+        data,
+        offset,
+    });
+}
+
+fn emit_status(app: &AppHandle, entry: &FollowEntry, state: FollowState, error: Option<String>) {
+    let _ = app.emit("sftp:follow-status", FollowStatusEvent {
+        follow_id: entry.info.follow_id.clone(),
+        session_id: entry.info.session_id.clone(),
+        path: entry.info.path.clone(),
+        state,
+        error,
+    });
+}
+
+/// 轮询模式：文件变小视为截断；消失后重新出现视为轮转。
+/// SFTP 属性不含 inode，轮转后新文件在两次轮询之间就超过旧偏移的情况无法识别，需要 exec 模式
+async fn poll(
+    app: &AppHandle,
+    entry: &FollowEntry,
+    sftp_arc: &Arc<Mutex<SftpSession>>,
+    cursor: &mut Cursor,
+    interval: Duration,
+) -> Result<(), String> {
+    let path = &entry.info.path;
+    let mut present = true;
+    while !entry.stop.load(Ordering::Relaxed) {
+        match file_size(sftp_arc, path).await {
+            Err(_) => {
+                if present {
+                    present = false;
+                    emit_status(app, entry, FollowState::Missing, None);
+                }
+            }
+            Ok(size) => {
+                if !present {
+                    present = true;
+                    cursor.reset();
+                    emit_status(app, entry, FollowState::Rotated, None);
+                } else if size < cursor.offset {
+                    cursor.reset();
+                    emit_status(app, entry, FollowState::Truncated, None);
+                }
+                while cursor.offset < size && !entry.stop.load(Ordering::Relaxed) {
+                    let data = read_at(sftp_arc, path, cursor.offset, (size - cursor.offset).min(READ_CHUNK)).await?;
+                    if data.is_empty() {
+                        break;
+                    }
+                    cursor.offset += data.len() as u64;
+                    let text = cursor.decode(&data);
+                    emit_data(app, entry, text, cursor.offset);
+                }
+            }
+        }
+        tokio::time::sleep(interval).await;
+    }
+    Ok(())
+}
+
+/// exec 模式：`tail -F` 按文件名跟随，能识别轮转与截断；stderr 的提示转成状态事件。
+/// 返回 true 表示已主动停止，false 表示通道意外结束
+async fn exec_tail(
+    app: &AppHandle,
+    mgr: &SftpManager,
+    entry: &FollowEntry,
+    cursor: &mut Cursor,
+) -> Result<bool, String> {
+    let command = format!("tail -c +{} -F -- {}", cursor.offset + 1, shell_quote(&entry.info.path));
+    let mut channel = open_exec_channel(mgr, &entry.info.session_id, &command).await?;
+
+    loop {
+        if entry.stop.load(Ordering::Relaxed) {
+            let _ = channel.close().await;
+            return Ok(true);
+        }
+        // 定时醒来检查停止标记
+        let msg = match tokio::time::timeout(Duration::from_millis(500), channel.wait()).await {
+            Ok(Some(msg)) => msg,
+            Ok(None) => return Ok(false),
+            Err(_) => continue,
+        };
+        match msg {
+            russh::ChannelMsg::Data { data } => {
+                cursor.offset += data.len() as u64;
+                let text = cursor.decode(&data);
+                emit_data(app, entry, text, cursor.offset);
+            }
+            russh::ChannelMsg::ExtendedData { data, ext: 1 } => {
+                let message = String::from_utf8_lossy(&data);
+                if message.contains("truncated") {
+                    cursor.reset();
+                    emit_status(app, entry, FollowState::Truncated, None);
+                } else if message.contains("has appeared") || message.contains("has been replaced") {
+                    cursor.reset();
+                    emit_status(app, entry, FollowState::Rotated, None);
+                } else if message.contains("inaccessible") || message.contains("No such file") {
+                    emit_status(app, entry, FollowState::Missing, None);
+                }
+            }
+            russh::ChannelMsg::Close => return Ok(false),
+            _ => {}
+        }
+    }
+}
+
+async fn run(
+    app: AppHandle,
+    entry: Arc<FollowEntry>,
+    sftp_arc: Arc<Mutex<SftpSession>>,
+    mut cursor: Cursor,
+    interval: Duration,
+) {
+    let mgr = app.state::<SftpManager>();
+    let mut result = Ok(());
+    if entry.info.mode == FollowMode::Exec {
+        match exec_tail(&app, &mgr, &entry, &mut cursor).await {
+            Ok(true) => {}
+            // tail 意外退出（如被服务器杀掉）时从当前偏移改为轮询，不丢内容
+            Ok(false) => {
+                log::warn!("tail -F exited for {}, falling back to polling", entry.info.path);
+                result = poll(&app, &entry, &sftp_arc, &mut cursor, interval).await;
+            }
+            Err(e) => result = Err(e),
+        }
+    } else {
+        result = poll(&app, &entry, &sftp_arc, &mut cursor, interval).await;
+    }
+    // 出错结束时从列表移除；主动停止时已被移除
+    app.state::<FollowManager>().follows.write().await.remove(&entry.info.follow_id);
+    emit_status(&app, &entry, FollowState::Stopped, result.err());
+}
+
+/// 会话断开时停止该会话的所有跟随
+pub async fn close_session(follows: &FollowManager, session_id: &str) {
+    follows.follows.write().await.retain(|_, e| {
+        let keep = e.info.session_id != session_id;
+        if !keep {
+            e.stop.store(true, Ordering::Relaxed);
+        }
+        keep
+    });
+}
+
+/// 开始跟随远程文件：先返回末尾 `initialLines` 行，之后的追加内容通过 `sftp:follow-data` 推送
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn sftp_follow_start(
+    app: AppHandle,
+    host_map: tauri::State<'_, SessionHostMap>,
+    mgr: tauri::State<'_, SftpManager>,
+    follows: tauri::State<'_, FollowManager>,
+    session_id: String,
+    path: String,
+    initial_lines: Option<usize>,
+    mode: Option<FollowMode>,
+    interval_ms: Option<u64>,
+) -> Result<FollowStartResult, String> {
+    let (sftp_arc, _) = sftp_get_host(&app, &host_map, &mgr, &session_id).await?;
+    let initial = tail_lines(&sftp_arc, &path, initial_lines.unwrap_or(10).max(1)).await?;
+
+    let mode = match mode.unwrap_or(FollowMode::Auto) {
+        FollowMode::Auto => match exec_on_session(&mgr, &session_id, "command -v tail").await {
+            Ok((0, _)) => FollowMode::Exec,
+            _ => FollowMode::Poll,
+        },
+        other => other,
+    };
+    let encoding = Encoding::for_label(initial.encoding.as_bytes()).unwrap_or(encoding_rs::UTF_8);
+    let cursor = Cursor {
+        offset: initial.file_size,
+        encoding,
+        decoder: encoding.new_decoder_without_bom_handling(),
+    };
+
+    let follow_id = uuid::Uuid::new_v4().to_string();
+    let entry = Arc::new(FollowEntry {
+        info: FollowSession {
+            follow_id: follow_id.clone(),
+            session_id,
+            path,
+            mode,
+            started_at: chrono::Utc::now().to_rfc3339(),
+        },
+        stop: AtomicBool::new(false),
+    });
+    follows.follows.write().await.insert(follow_id, entry.clone());
+
+    let interval = Duration::from_millis(interval_ms.unwrap_or(1000).max(200));
+    tauri::async_runtime::spawn(run(app.clone(), entry.clone(), sftp_arc, cursor, interval));
+
+    Ok(FollowStartResult { follow: entry.info.clone(), initial })
+}
+
+#[tauri::command]
+pub async fn sftp_follow_stop(
+    follows: tauri::State<'_, FollowManager>,
+    follow_id: String,
+) -> Result<bool, String> {
+    let Some(entry) = follows.follows.write().await.remove(&follow_id) else {
+        return Ok(false);
+    };
+    entry.stop.store(true, Ordering::Relaxed);
+    Ok(true)
+}
+
+#[tauri::command]
+pub async fn sftp_follow_list(
+    follows: tauri::State<'_, FollowManager>,
+    session_id: Option<String>,
+) -> Result<Vec<FollowSession>, String> {
+    Ok(follows
+        .follows
+        .read()
+        .await
+        .values()
+        .filter(|e| in_session(session_id.as_deref(), &e.info.session_id))
+        .map(|e| e.info.clone())
+        .collect())
+}
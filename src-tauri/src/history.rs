@@ -0,0 +1,277 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use tauri_plugin_store::StoreExt;
+use crate::queue::{QueuedTransfer, TransferDirection, TransferStatus};
+
+/// 历史记录最多保留的条数，超出后丢弃最旧的记录
+const MAX_HISTORY: usize = 5000;
+
+/// 一次已结束的传输
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryEntry {
+    pub id: String,
+    pub transfer_id: String,
+    pub host_id: String,
+    /// 记录时的主机名称与地址，主机被删除后仍可追溯
+    #[serde(default)]
+    pub host_name: String,
+    #[serde(default)]
+    pub host_address: String,
+    pub direction: TransferDirection,
+    pub local_path: String,
+    pub remote_path: String,
+    /// Relay 传输在目标会话中的路径
+    #[serde(default)]
+    pub target_path: Option<String>,
+    pub bytes_transferred: u64,
+    pub total_bytes: u64,
+    pub started_at: String,
+    pub finished_at: String,
+    pub duration_ms: u64,
+    /// 平均速度（字节/秒）
+    pub average_speed: u64,
+    pub result: TransferStatus,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+/// 查询条件，全部可选；时间为 RFC 3339 字符串，按结束时间过滤
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryFilter {
+    pub host_id: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub result: Option<TransferStatus>,
+    pub direction: Option<TransferDirection>,
+    /// 匹配本地或远程路径的子串
+    pub search: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryStats {
+    pub count: usize,
+    pub completed: usize,
+    pub failed: usize,
+    pub cancelled: usize,
+    pub total_bytes: u64,
+    pub total_duration_ms: u64,
+    pub average_speed: u64,
+}
+
+fn load_all(app: &tauri::AppHandle) -> Vec<HistoryEntry> {
+    app.store(PathBuf::from("history.json"))
+        .ok()
+        .and_then(|store| store.get("history"))
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default()
+}
+
+fn save_all(app: &tauri::AppHandle, entries: &[HistoryEntry]) -> Result<(), String> {
+    let store = app.store(PathBuf::from("history.json")).map_err(|e| e.to_string())?;
+    store.set("history", serde_json::to_value(entries).map_err(|e| e.to_string())?);
+    let _ = store.save();
+    Ok(())
+}
+
+/// 从 hosts.json 取主机名称与 user@host:port（无需解密）
+fn host_label(app: &tauri::AppHandle, host_id: &str) -> (String, String) {
+    let hosts: Vec<crate::db::HostConfig> = app
+        .store(PathBuf::from("hosts.json"))
+        .ok()
+        .and_then(|store| store.get("hosts"))
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default();
+    hosts
+        .into_iter()
+        .find(|h| h.id.as_deref() == Some(host_id))
+        .map(|h| {
+            let address = format!("{}@{}:{}", h.username, h.host, h.port.unwrap_or(22));
+            (h.name, address)
+        })
+        .unwrap_or_default()
+}
+
+/// 传输结束时追加一条历史记录
+pub fn record(app: &tauri::AppHandle, item: &QueuedTransfer, duration: std::time::Duration) {
+    let finished = chrono::Utc::now();
+    let started = finished - chrono::Duration::milliseconds(duration.as_millis() as i64);
+    let duration_ms = duration.as_millis() as u64;
+    let (host_name, host_address) = host_label(app, &item.host_id);
+
+    let entry = HistoryEntry {
+        id: uuid::Uuid::new_v4().to_string(),
+        transfer_id: item.id.clone(),
+        host_id: item.host_id.clone(),
+        host_name,
+        host_address,
+        direction: item.direction,
+        local_path: item.local_path.clone(),
+        remote_path: item.remote_path.clone(),
+        target_path: item.target_path.clone(),
+        bytes_transferred: item.bytes_transferred,
+        total_bytes: item.total_bytes,
+        started_at: started.to_rfc3339(),
+        finished_at: finished.to_rfc3339(),
+        duration_ms,
+        average_speed: item.bytes_transferred * 1000 / duration_ms.max(1),
+        result: item.status,
+        error: item.error.clone(),
+    };
+
+    let mut entries = load_all(app);
+    entries.push(entry);
+    if entries.len() > MAX_HISTORY {
+        let excess = entries.len() - MAX_HISTORY;
+        entries.drain(..excess);
+    }
+    if let Err(e) = save_all(app, &entries) {
+        log::warn!("Failed to save transfer history: {}", e);
+    }
+}
+
+fn parse_time(s: &str) -> Result<chrono::DateTime<chrono::FixedOffset>, String> {
+    chrono::DateTime::parse_from_rfc3339(s).map_err(|e| format!("Invalid date {}: {}", s, e))
+}
+
+/// 按条件筛选，结果按结束时间从新到旧排列
+fn query(app: &tauri::AppHandle, filter: &HistoryFilter) -> Result<Vec<HistoryEntry>, String> {
+    let from = filter.from.as_deref().map(parse_time).transpose()?;
+    let to = filter.to.as_deref().map(parse_time).transpose()?;
+    let search = filter.search.as_ref().map(|s| s.to_lowercase());
+
+    let mut entries: Vec<HistoryEntry> = load_all(app)
+        .into_iter()
+        .rev()
+        .filter(|e| filter.host_id.as_ref().is_none_or(|id| *id == e.host_id))
+        .filter(|e| filter.result.is_none_or(|r| r == e.result))
+        .filter(|e| filter.direction.is_none_or(|d| d == e.direction))
+        .filter(|e| {
+            search.as_ref().is_none_or(|s| {
+                e.local_path.to_lowercase().contains(s)
+                    || e.remote_path.to_lowercase().contains(s)
+                    || e.target_path.as_ref().is_some_and(|p| p.to_lowercase().contains(s))
+            })
+        })
+        .filter(|e| {
+            let Ok(finished) = parse_time(&e.finished_at) else {
+                return false;
+            };
+            from.is_none_or(|f| finished >= f) && to.is_none_or(|t| finished <= t)
+        })
+        .collect();
+    if let Some(limit) = filter.limit {
+        entries.truncate(limit);
+    }
+    Ok(entries)
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+fn to_csv(entries: &[HistoryEntry]) -> String {
+    let mut out = String::from(
+        "finishedAt,startedAt,hostName,hostAddress,direction,localPath,remotePath,targetPath,bytesTransferred,totalBytes,durationMs,averageSpeed,result,error\n",
+    );
+    for e in entries {
+        let direction = match e.direction {
+            TransferDirection::Upload => "upload",
+            TransferDirection::Download => "download",
+            TransferDirection::Sync => "sync",
+            TransferDirection::Relay => "relay",
+        };
+        let result = serde_json::to_value(e.result)
+            .ok()
+            .and_then(|v| v.as_str().map(|s| s.to_string()))
+            .unwrap_or_default();
+        let fields = [
+            e.finished_at.clone(),
+            e.started_at.clone(),
+            e.host_name.clone(),
+            e.host_address.clone(),
+            direction.to_string(),
+            e.local_path.clone(),
+            e.remote_path.clone(),
+            e.target_path.clone().unwrap_or_default(),
+            e.bytes_transferred.to_string(),
+            e.total_bytes.to_string(),
+            e.duration_ms.to_string(),
+            e.average_speed.to_string(),
+            result,
+            e.error.clone().unwrap_or_default(),
+        ];
+        let line: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+        out.push_str(&line.join(","));
+        out.push('\n');
+    }
+    out
+}
+
+#[tauri::command]
+pub async fn transfer_history_query(
+    app: tauri::AppHandle,
+    filter: Option<HistoryFilter>,
+) -> Result<Vec<HistoryEntry>, String> {
+    query(&app, &filter.unwrap_or_default())
+}
+
+#[tauri::command]
+pub async fn transfer_history_stats(
+    app: tauri::AppHandle,
+    filter: Option<HistoryFilter>,
+) -> Result<HistoryStats, String> {
+    let mut stats = HistoryStats::default();
+    for e in query(&app, &filter.unwrap_or_default())? {
+        stats.count += 1;
+        match e.result {
+            TransferStatus::Completed => stats.completed += 1,
+            TransferStatus::Failed => stats.failed += 1,
+            TransferStatus::Cancelled => stats.cancelled += 1,
+            _ => {}
+        }
+        stats.total_bytes += e.bytes_transferred;
+        stats.total_duration_ms += e.duration_ms;
+    }
+    stats.average_speed = stats.total_bytes * 1000 / stats.total_duration_ms.max(1);
+    Ok(stats)
+}
+
+/// 导出到本地文件，format 为 csv 或 json；返回导出的条数
+#[tauri::command]
+pub async fn transfer_history_export(
+    app: tauri::AppHandle,
+    path: String,
+    format: String,
+    filter: Option<HistoryFilter>,
+) -> Result<usize, String> {
+    let entries = query(&app, &filter.unwrap_or_default())?;
+    let content = match format.as_str() {
+        "csv" => to_csv(&entries),
+        "json" => serde_json::to_string_pretty(&entries).map_err(|e| e.to_string())?,
+        other => return Err(format!("Unsupported export format: {}", other)),
+    };
+    tokio::fs::write(&path, content).await.map_err(|e| e.to_string())?;
+    Ok(entries.len())
+}
+
+/// 清空历史；指定 hostId 时只清除该主机的记录
+#[tauri::command]
+pub async fn transfer_history_clear(
+    app: tauri::AppHandle,
+    host_id: Option<String>,
+) -> Result<(), String> {
+    let mut entries = load_all(&app);
+    match host_id {
+        Some(id) => entries.retain(|e| e.host_id != id),
+        None => entries.clear(),
+    }
+    save_all(&app, &entries)
+}
@@ -1,7 +1,29 @@
+mod archive;
+mod checksum;
 mod crypto;
 mod db;
+mod diff;
+mod edit;
+mod encoding;
+mod follow;
+mod history;
+mod limits;
+mod queue;
+mod range;
+mod scp;
+mod scrollback;
+mod search;
+mod settings;
+mod shell_integration;
 mod ssh;
 mod sftp;
+mod sudo;
+mod sync;
+mod term_transfer;
+mod triggers;
+mod trzsz;
+mod usage;
+mod zmodem;
 
 #[cfg(target_os = "android")]
 fn init_android_tls_provider() {
@@ -15,43 +37,112 @@ pub fn run() {
 
   tauri::Builder::default()
     .manage(ssh::SshManager::new())
+    .manage(scrollback::ScrollbackManager::new())
     .manage(sftp::SftpManager::new())
     .manage(sftp::SessionHostMap::new())
+    .manage(edit::EditManager::new())
+    .manage(follow::FollowManager::new())
+    .manage(search::SearchManager::new())
+    .manage(sudo::SudoManager::new())
+    .manage(term_transfer::TermTransferManager::new())
     .plugin(tauri_plugin_dialog::init())
     .plugin(tauri_plugin_store::Builder::default().build())
     .plugin(tauri_plugin_os::init())
     .plugin(tauri_plugin_clipboard_manager::init())
     .plugin(tauri_plugin_fs::init())
     .plugin(tauri_plugin_shell::init())
+    .plugin(tauri_plugin_opener::init())
     .invoke_handler(tauri::generate_handler![
         db::get_hosts,
         db::save_host,
         db::delete_host,
         db::get_host,
+        settings::get_app_settings,
+        settings::save_app_settings,
         ssh::ssh_connect,
         ssh::ssh_input,
         ssh::ssh_resize,
         ssh::ssh_disconnect,
         ssh::ssh_test,
+        shell_integration::ssh_shell_state,
+        shell_integration::ssh_shell_integration_script,
+        shell_integration::ssh_inject_shell_integration,
+        scrollback::ssh_scrollback_replay,
+        scrollback::ssh_scrollback_search,
+        scrollback::ssh_scrollback_clear,
+        triggers::get_global_triggers,
+        triggers::save_global_triggers,
         sftp::sftp_connect_session,
         sftp::sftp_realpath,
         sftp::sftp_list,
         sftp::sftp_upload,
         sftp::sftp_download,
+        sftp::sftp_relay,
         sftp::sftp_delete,
         sftp::sftp_rename,
         sftp::sftp_mkdir,
+        sftp::sftp_chmod,
+        sftp::sftp_chown,
+        sftp::sftp_set_times,
         sftp::sftp_get_file,
         sftp::sftp_put_file,
         sftp::sftp_pause,
         sftp::sftp_resume,
         sftp::sftp_cancel,
+        sftp::sftp_resolve_conflict,
+        sftp::sftp_set_bandwidth_limit,
+        sftp::sftp_set_max_concurrent,
+        sftp::sftp_set_transfer_priority,
+        queue::sftp_enqueue,
+        queue::sftp_queue_list,
+        queue::sftp_queue_reorder,
+        queue::sftp_queue_retry,
+        queue::sftp_queue_remove,
+        queue::sftp_queue_clear_completed,
+        sync::sftp_sync_plan,
+        sync::sftp_sync_execute,
+        history::transfer_history_query,
+        history::transfer_history_stats,
+        history::transfer_history_export,
+        history::transfer_history_clear,
         sftp::sftp_move,
         sftp::sftp_stat,
+        sftp::sftp_symlink,
+        sftp::sftp_hardlink,
         sftp::sftp_read_text_file,
+        range::sftp_read_range,
+        range::sftp_read_tail,
+        range::sftp_read_hex_page,
+        follow::sftp_follow_start,
+        follow::sftp_follow_stop,
+        follow::sftp_follow_list,
+        search::sftp_find,
+        search::sftp_find_cancel,
+        archive::sftp_archive_download,
+        archive::sftp_archive_upload,
+        diff::sftp_diff_dirs,
+        diff::sftp_diff_file,
+        usage::sftp_du,
+        usage::sftp_du_cancel,
+        usage::sftp_statvfs,
+        sudo::sftp_sudo_enable,
+        sudo::sftp_sudo_disable,
+        sudo::sftp_sudo_status,
+        sudo::sftp_sudo_answer,
+        scp::sftp_transfer_protocol,
+        term_transfer::ssh_transfer_answer,
+        term_transfer::ssh_transfer_cancel,
+        edit::sftp_edit_open,
+        edit::sftp_edit_list,
+        edit::sftp_edit_resolve,
+        edit::sftp_edit_close,
         sftp::sftp_disconnect
     ])
     .setup(|app| {
+      use tauri::Manager;
+      settings::apply(app.handle(), &settings::load(app.handle()));
+      app.manage(queue::TransferQueue::load(app.handle()));
+      edit::cleanup_workspace();
       if cfg!(debug_assertions) {
         app.handle().plugin(
           tauri_plugin_log::Builder::default()
@@ -0,0 +1,189 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+
+/// 令牌桶限速器，上传与下载循环共享；速率为 0 表示不限速
+pub struct RateLimiter {
+    rate: AtomicU64,
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    tokens: f64,
+    last: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(bytes_per_sec: u64) -> Self {
+        RateLimiter {
+            rate: AtomicU64::new(bytes_per_sec),
+            bucket: Mutex::new(Bucket { tokens: 0.0, last: Instant::now() }),
+        }
+    }
+
+    pub fn rate(&self) -> u64 {
+        self.rate.load(Ordering::Relaxed)
+    }
+
+    /// 运行中调整速率，正在等待的传输会在下一次检查时生效
+    pub fn set_rate(&self, bytes_per_sec: u64) {
+        self.rate.store(bytes_per_sec, Ordering::Relaxed);
+    }
+
+    /// 尝试消耗 `bytes` 个令牌。成功返回 None，否则返回建议的等待时间。
+    /// 桶容量为一秒的流量；允许透支，使大于容量的分块也能通过。
+    pub fn reserve(&self, bytes: u64) -> Option<Duration> {
+        let rate = self.rate();
+        if rate == 0 {
+            return None;
+        }
+        let mut bucket = self.bucket.lock().unwrap();
+        let now = Instant::now();
+        let refill = now.duration_since(bucket.last).as_secs_f64() * rate as f64;
+        bucket.tokens = (bucket.tokens + refill).min(rate as f64);
+        bucket.last = now;
+        if bucket.tokens >= 0.0 {
+            bucket.tokens -= bytes as f64;
+            None
+        } else {
+            Some(Duration::from_secs_f64(-bucket.tokens / rate as f64))
+        }
+    }
+}
+
+/// 每个会话的并发传输槽位，等待者按优先级（高者先）及入队顺序调度
+pub struct SlotQueue {
+    inner: Mutex<SlotState>,
+}
+
+struct SlotState {
+    max: usize,
+    /// 上限由 `set_max` 为该会话单独指定，不再跟随全局设置
+    custom: bool,
+    running: usize,
+    seq: u64,
+    waiting: Vec<Waiter>,
+}
+
+struct Waiter {
+    transfer_id: String,
+    priority: i32,
+    seq: u64,
+    tx: oneshot::Sender<()>,
+}
+
+/// 持有期间占用一个槽位，释放时唤醒下一个等待者
+pub struct SlotGuard {
+    queue: Arc<SlotQueue>,
+}
+
+impl Drop for SlotGuard {
+    fn drop(&mut self) {
+        let mut state = self.queue.inner.lock().unwrap();
+        state.running = state.running.saturating_sub(1);
+        SlotQueue::dispatch(&mut state);
+    }
+}
+
+/// 排队中的传输可以被取消
+pub enum SlotWait {
+    Ready(SlotGuard),
+    Pending(oneshot::Receiver<()>),
+}
+
+impl SlotQueue {
+    pub fn new(max: usize) -> Arc<Self> {
+        Arc::new(SlotQueue {
+            inner: Mutex::new(SlotState { max: max.max(1), custom: false, running: 0, seq: 0, waiting: Vec::new() }),
+        })
+    }
+
+    fn dispatch(state: &mut SlotState) {
+        while state.running < state.max && !state.waiting.is_empty() {
+            let next = state
+                .waiting
+                .iter()
+                .enumerate()
+                .max_by(|(_, a), (_, b)| a.priority.cmp(&b.priority).then(b.seq.cmp(&a.seq)))
+                .map(|(i, _)| i)
+                .unwrap();
+            let waiter = state.waiting.remove(next);
+            if waiter.tx.send(()).is_ok() {
+                state.running += 1;
+            }
+        }
+    }
+
+    /// 有空闲槽位时立即返回，否则加入等待队列
+    pub fn enter(self: &Arc<Self>, transfer_id: &str, priority: i32) -> SlotWait {
+        let mut state = self.inner.lock().unwrap();
+        if state.running < state.max && state.waiting.is_empty() {
+            state.running += 1;
+            return SlotWait::Ready(SlotGuard { queue: self.clone() });
+        }
+        let (tx, rx) = oneshot::channel();
+        state.seq += 1;
+        let seq = state.seq;
+        state.waiting.push(Waiter { transfer_id: transfer_id.to_string(), priority, seq, tx });
+        SlotWait::Pending(rx)
+    }
+
+    /// 等待者收到调度信号后换取槽位守卫
+    pub fn guard(self: &Arc<Self>) -> SlotGuard {
+        SlotGuard { queue: self.clone() }
+    }
+
+    /// 放弃排队；若恰好已被调度则归还槽位
+    pub fn leave(self: &Arc<Self>, transfer_id: &str, mut rx: oneshot::Receiver<()>) {
+        let mut state = self.inner.lock().unwrap();
+        let before = state.waiting.len();
+        state.waiting.retain(|w| w.transfer_id != transfer_id);
+        if state.waiting.len() == before && rx.try_recv().is_ok() {
+            state.running = state.running.saturating_sub(1);
+            SlotQueue::dispatch(&mut state);
+        }
+    }
+
+    pub fn max(&self) -> usize {
+        self.inner.lock().unwrap().max
+    }
+
+    /// 运行中调整并发上限；调大时立即放行等待者
+    pub fn set_max(&self, max: usize) {
+        let mut state = self.inner.lock().unwrap();
+        state.max = max.max(1);
+        state.custom = true;
+        SlotQueue::dispatch(&mut state);
+    }
+
+    /// 全局设置变更时调用；会话单独指定过上限的保持不变
+    pub fn set_default_max(&self, max: usize) {
+        let mut state = self.inner.lock().unwrap();
+        if state.custom {
+            return;
+        }
+        state.max = max.max(1);
+        SlotQueue::dispatch(&mut state);
+    }
+
+    /// 调整排队中传输的优先级，返回是否找到
+    pub fn set_priority(&self, transfer_id: &str, priority: i32) -> bool {
+        let mut state = self.inner.lock().unwrap();
+        match state.waiting.iter_mut().find(|w| w.transfer_id == transfer_id) {
+            Some(w) => {
+                w.priority = priority;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 排队中的传输 id，按调度顺序
+    pub fn waiting(&self) -> Vec<String> {
+        let state = self.inner.lock().unwrap();
+        let mut waiting: Vec<&Waiter> = state.waiting.iter().collect();
+        waiting.sort_by(|a, b| b.priority.cmp(&a.priority).then(a.seq.cmp(&b.seq)));
+        waiting.iter().map(|w| w.transfer_id.clone()).collect()
+    }
+}
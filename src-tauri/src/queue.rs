@@ -0,0 +1,374 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tauri::{AppHandle, Emitter, Manager};
+use tauri_plugin_store::StoreExt;
+use crate::checksum::ChecksumVerdict;
+use crate::sftp::{ConflictPolicy, SessionHostMap, SftpManager};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum TransferStatus {
+    Queued,
+    Running,
+    Paused,
+    Failed,
+    Completed,
+    Cancelled,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum TransferDirection {
+    Upload,
+    Download,
+    /// 目录同步任务，localPath/remotePath 为两端根目录
+    Sync,
+    /// 会话间复制：sessionId/remotePath 为源，targetSessionId/targetPath 为目标，localPath 为空
+    Relay,
+}
+
+/// 单个传输的可选参数，未设置的项使用应用设置中的默认值
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TransferOptions {
+    #[serde(default)]
+    pub conflict_policy: Option<ConflictPolicy>,
+    #[serde(default)]
+    pub preserve: Option<bool>,
+    #[serde(default)]
+    pub verify: Option<bool>,
+    /// 设置后以 tar 流方式传输整个目录
+    #[serde(default)]
+    pub archive: Option<crate::archive::ArchiveOptions>,
+}
+
+/// 队列中的一条传输记录，同时也是 `sftp:transfer-status` 事件的负载
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct QueuedTransfer {
+    pub id: String,
+    pub session_id: String,
+    pub host_id: String,
+    pub direction: TransferDirection,
+    pub local_path: String,
+    pub remote_path: String,
+    /// Relay 传输的目标会话
+    #[serde(default)]
+    pub target_session_id: Option<String>,
+    /// Relay 传输在目标会话中的路径
+    #[serde(default)]
+    pub target_path: Option<String>,
+    #[serde(default)]
+    pub options: TransferOptions,
+    pub status: TransferStatus,
+    #[serde(default)]
+    pub bytes_transferred: u64,
+    #[serde(default)]
+    pub total_bytes: u64,
+    #[serde(default)]
+    pub speed: u64,
+    /// 递归传输时当前正在处理的文件
+    #[serde(default)]
+    pub current_path: Option<String>,
+    #[serde(default)]
+    pub error: Option<String>,
+    #[serde(default)]
+    pub verification: Vec<ChecksumVerdict>,
+    pub created_at: String,
+    #[serde(default)]
+    pub finished_at: Option<String>,
+}
+
+impl QueuedTransfer {
+    pub fn new(
+        id: &str,
+        session_id: &str,
+        host_id: &str,
+        direction: TransferDirection,
+        local_path: &str,
+        remote_path: &str,
+        options: TransferOptions,
+    ) -> Self {
+        QueuedTransfer {
+            id: id.to_string(),
+            session_id: session_id.to_string(),
+            host_id: host_id.to_string(),
+            direction,
+            local_path: local_path.to_string(),
+            remote_path: remote_path.to_string(),
+            target_session_id: None,
+            target_path: None,
+            options,
+            status: TransferStatus::Queued,
+            bytes_transferred: 0,
+            total_bytes: 0,
+            speed: 0,
+            current_path: None,
+            error: None,
+            verification: Vec::new(),
+            created_at: chrono::Utc::now().to_rfc3339(),
+            finished_at: None,
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        matches!(
+            self.status,
+            TransferStatus::Completed | TransferStatus::Failed | TransferStatus::Cancelled
+        )
+    }
+}
+
+/// 后端维护的传输队列，状态变化时持久化到 transfers.json
+pub struct TransferQueue {
+    items: Mutex<Vec<QueuedTransfer>>,
+}
+
+impl TransferQueue {
+    /// 从存储恢复队列；上次退出时未完成的传输已经没有运行中的任务，标记为失败以便重试
+    pub fn load(app: &AppHandle) -> Self {
+        let mut items: Vec<QueuedTransfer> = app
+            .store(PathBuf::from("transfers.json"))
+            .ok()
+            .and_then(|store| store.get("queue"))
+            .and_then(|v| serde_json::from_value(v).ok())
+            .unwrap_or_default();
+        for item in items.iter_mut().filter(|i| !i.is_finished()) {
+            item.status = TransferStatus::Failed;
+            item.speed = 0;
+            item.error = Some("Interrupted".to_string());
+        }
+        TransferQueue { items: Mutex::new(items) }
+    }
+
+    fn persist(&self, app: &AppHandle) {
+        let Ok(store) = app.store(PathBuf::from("transfers.json")) else {
+            return;
+        };
+        let items = self.items.lock().unwrap();
+        if let Ok(value) = serde_json::to_value(&*items) {
+            store.set("queue", value);
+            let _ = store.save();
+        }
+    }
+
+    pub fn list(&self) -> Vec<QueuedTransfer> {
+        self.items.lock().unwrap().clone()
+    }
+
+    pub fn get(&self, id: &str) -> Option<QueuedTransfer> {
+        self.items.lock().unwrap().iter().find(|i| i.id == id).cloned()
+    }
+
+    /// 新增或替换一条记录
+    pub fn upsert(&self, app: &AppHandle, item: QueuedTransfer) {
+        {
+            let mut items = self.items.lock().unwrap();
+            match items.iter_mut().find(|i| i.id == item.id) {
+                Some(existing) => *existing = item.clone(),
+                None => items.push(item.clone()),
+            }
+        }
+        self.persist(app);
+        let _ = app.emit("sftp:transfer-status", item);
+    }
+
+    /// 修改一条记录并广播；状态发生变化时才写盘
+    pub fn update<F: FnOnce(&mut QueuedTransfer)>(&self, app: &AppHandle, id: &str, f: F) {
+        let (snapshot, status_changed) = {
+            let mut items = self.items.lock().unwrap();
+            let Some(item) = items.iter_mut().find(|i| i.id == id) else {
+                return;
+            };
+            let before = item.status;
+            f(item);
+            if item.is_finished() && item.finished_at.is_none() {
+                item.finished_at = Some(chrono::Utc::now().to_rfc3339());
+            }
+            (item.clone(), before != item.status)
+        };
+        if status_changed {
+            self.persist(app);
+        }
+        let _ = app.emit("sftp:transfer-status", snapshot);
+    }
+
+    pub fn set_status(&self, app: &AppHandle, id: &str, status: TransferStatus) {
+        self.update(app, id, |item| item.status = status);
+    }
+
+    pub fn remove(&self, app: &AppHandle, id: &str) -> bool {
+        let removed = {
+            let mut items = self.items.lock().unwrap();
+            let before = items.len();
+            items.retain(|i| i.id != id);
+            items.len() != before
+        };
+        if removed {
+            self.persist(app);
+        }
+        removed
+    }
+
+    /// 按给定 id 顺序重排，未列出的记录保持原有相对顺序排在后面
+    pub fn reorder(&self, app: &AppHandle, ids: &[String]) {
+        {
+            let mut items = self.items.lock().unwrap();
+            items.sort_by_key(|i| ids.iter().position(|id| *id == i.id).unwrap_or(usize::MAX));
+        }
+        self.persist(app);
+    }
+
+    pub fn clear_completed(&self, app: &AppHandle) {
+        self.items.lock().unwrap().retain(|i| i.status != TransferStatus::Completed);
+        self.persist(app);
+    }
+
+    /// 排队中的传输按队列位置映射为调度优先级（越靠前越大）
+    pub fn queued_priorities(&self) -> Vec<(String, String, i32)> {
+        let items = self.items.lock().unwrap();
+        let queued: Vec<&QueuedTransfer> =
+            items.iter().filter(|i| i.status == TransferStatus::Queued).collect();
+        let n = queued.len() as i32;
+        queued
+            .iter()
+            .enumerate()
+            .map(|(idx, i)| (i.session_id.clone(), i.id.clone(), n - idx as i32))
+            .collect()
+    }
+}
+
+/// 在后台执行队列中的一条传输
+fn spawn_transfer(app: &AppHandle, item: &QueuedTransfer, priority: i32) {
+    let app = app.clone();
+    let request = crate::sftp::TransferRequest {
+        session_id: item.session_id.clone(),
+        transfer_id: item.id.clone(),
+        direction: item.direction,
+        local_path: item.local_path.clone(),
+        remote_path: item.remote_path.clone(),
+        target_session_id: item.target_session_id.clone(),
+        target_path: item.target_path.clone(),
+        options: item.options.clone(),
+        priority,
+    };
+    tauri::async_runtime::spawn(async move {
+        let host_map = app.state::<SessionHostMap>();
+        let mgr = app.state::<SftpManager>();
+        let _ = crate::sftp::execute_transfer(&app, &host_map, &mgr, request).await;
+    });
+}
+
+/// 加入后端队列并在槽位空闲时开始，返回传输 id；Relay 需要 targetSessionId 与 targetPath
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn sftp_enqueue(
+    app: AppHandle,
+    host_map: tauri::State<'_, SessionHostMap>,
+    queue: tauri::State<'_, TransferQueue>,
+    session_id: String,
+    direction: TransferDirection,
+    local_path: String,
+    remote_path: String,
+    options: Option<TransferOptions>,
+    target_session_id: Option<String>,
+    target_path: Option<String>,
+) -> Result<String, String> {
+    let host_id = host_map.0.read().await.get(&session_id).cloned().ok_or("Session not found")?;
+    if direction == TransferDirection::Relay && (target_session_id.is_none() || target_path.is_none()) {
+        return Err("Relay transfer requires a target session and path".to_string());
+    }
+    let id = uuid::Uuid::new_v4().to_string();
+    let mut item = QueuedTransfer::new(
+        &id, &session_id, &host_id, direction, &local_path, &remote_path, options.unwrap_or_default(),
+    );
+    item.target_session_id = target_session_id;
+    item.target_path = target_path;
+    queue.upsert(&app, item.clone());
+    spawn_transfer(&app, &item, 0);
+    Ok(id)
+}
+
+#[tauri::command]
+pub async fn sftp_queue_list(queue: tauri::State<'_, TransferQueue>) -> Result<Vec<QueuedTransfer>, String> {
+    Ok(queue.list())
+}
+
+/// 重排队列，并同步排队中传输的调度优先级
+#[tauri::command]
+pub async fn sftp_queue_reorder(
+    app: AppHandle,
+    mgr: tauri::State<'_, SftpManager>,
+    queue: tauri::State<'_, TransferQueue>,
+    ids: Vec<String>,
+) -> Result<(), String> {
+    queue.reorder(&app, &ids);
+    let slots = mgr.slots.read().await;
+    for (session_id, id, priority) in queue.queued_priorities() {
+        if let Some(q) = slots.get(&session_id) {
+            q.set_priority(&id, priority);
+        }
+    }
+    Ok(())
+}
+
+/// 重试失败、取消或因重启中断的传输；可指定新的 sessionId（需为同一主机）
+#[tauri::command]
+pub async fn sftp_queue_retry(
+    app: AppHandle,
+    host_map: tauri::State<'_, SessionHostMap>,
+    mgr: tauri::State<'_, SftpManager>,
+    queue: tauri::State<'_, TransferQueue>,
+    id: String,
+    session_id: Option<String>,
+) -> Result<(), String> {
+    let mut item = queue.get(&id).ok_or("Transfer not found")?;
+    if item.direction == TransferDirection::Sync {
+        return Err("Sync jobs cannot be retried; build a new plan".to_string());
+    }
+    if mgr.transfers.read().await.contains_key(&id) {
+        return Err("Transfer is still active".to_string());
+    }
+    if let Some(session_id) = session_id {
+        let host_id = host_map.0.read().await.get(&session_id).cloned().ok_or("Session not found")?;
+        if host_id != item.host_id {
+            return Err("Session belongs to a different host".to_string());
+        }
+        item.session_id = session_id;
+    }
+
+    item.status = TransferStatus::Queued;
+    item.bytes_transferred = 0;
+    item.speed = 0;
+    item.current_path = None;
+    item.error = None;
+    item.verification.clear();
+    item.finished_at = None;
+    queue.upsert(&app, item.clone());
+    spawn_transfer(&app, &item, 0);
+    Ok(())
+}
+
+/// 移除一条记录；仍在进行的传输会先被取消
+#[tauri::command]
+pub async fn sftp_queue_remove(
+    app: AppHandle,
+    mgr: tauri::State<'_, SftpManager>,
+    queue: tauri::State<'_, TransferQueue>,
+    id: String,
+) -> Result<bool, String> {
+    if let Some(state) = mgr.transfers.read().await.get(&id) {
+        state.store(crate::sftp::TRANSFER_CANCELLED, std::sync::atomic::Ordering::Relaxed);
+    }
+    Ok(queue.remove(&app, &id))
+}
+
+#[tauri::command]
+pub async fn sftp_queue_clear_completed(
+    app: AppHandle,
+    queue: tauri::State<'_, TransferQueue>,
+) -> Result<(), String> {
+    queue.clear_completed(&app);
+    Ok(())
+}
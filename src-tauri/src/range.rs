@@ -0,0 +1,250 @@
+use russh_sftp::client::SftpSession;
+use serde::Serialize;
+use std::io::SeekFrom;
+use std::sync::Arc;
+use tauri::AppHandle;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio::sync::Mutex;
+use crate::sftp::{sftp_get_host, SessionHostMap, SftpManager};
+
+/// 单次请求允许读取的最大字节数
+const MAX_RANGE: u64 = 8 * 1024 * 1024;
+/// 倒序查找行时每次向前读取的块大小
+const TAIL_CHUNK: u64 = 64 * 1024;
+/// 十六进制视图每行字节数
+const HEX_ROW: usize = 16;
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RangeReadResult {
+    /// 实际返回内容的起始偏移（文本模式下可能跳过被截断的多字节字符）
+    pub offset: u64,
+    /// 下一次顺序读取应使用的偏移
+    pub next_offset: u64,
+    pub file_size: u64,
+    pub eof: bool,
+    /// 文本模式为解码后的文本，否则为 base64
+    pub content: String,
+    pub encoding: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TailResult {
+    pub content: String,
+    /// 返回内容在文件中的起始偏移，可配合 `sftp_read_range` 继续向前翻页
+    pub start_offset: u64,
+    pub file_size: u64,
+    pub lines: usize,
+    pub encoding: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HexRow {
+    pub offset: u64,
+    pub hex: String,
+    pub ascii: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HexPage {
+    pub page: u64,
+    pub page_size: u64,
+    pub total_pages: u64,
+    pub file_size: u64,
+    pub rows: Vec<HexRow>,
+}
+
+/// 从 `offset` 起读取至多 `len` 字节；通过 seek 只传输请求的部分
+pub(crate) async fn read_at(
+    sftp_arc: &Arc<Mutex<SftpSession>>,
+    path: &str,
+    offset: u64,
+    len: u64,
+) -> Result<Vec<u8>, String> {
+    // 极短时间持有 sftp 锁：仅打开文件句柄
+    let mut file = {
+        let sftp = sftp_arc.lock().await;
+        sftp.open(path).await.map_err(|e| e.to_string())?
+    };
+    file.seek(SeekFrom::Start(offset)).await.map_err(|e| e.to_string())?;
+
+    let mut data = vec![0u8; len as usize];
+    let mut filled = 0;
+    while filled < data.len() {
+        let n = file.read(&mut data[filled..]).await.map_err(|e| e.to_string())?;
+        if n == 0 {
+            break;
+        }
+        filled += n;
+    }
+    data.truncate(filled);
+    Ok(data)
+}
+
+pub(crate) async fn file_size(sftp_arc: &Arc<Mutex<SftpSession>>, path: &str) -> Result<u64, String> {
+    let sftp = sftp_arc.lock().await;
+    let meta = sftp.metadata(path).await.map_err(|e| e.to_string())?;
+    Ok(meta.size.unwrap_or(0))
+}
+
+/// 去掉 UTF-8 分块两端被截断的字符，返回 (跳过的前导字节数, 保留的字节数)
+fn utf8_bounds(data: &[u8], at_eof: bool) -> (usize, usize) {
+    let start = data.iter().take(3).take_while(|&&b| b & 0xC0 == 0x80).count();
+    let body = &data[start..];
+    let end = match std::str::from_utf8(body) {
+        Err(e) if e.error_len().is_none() && !at_eof => e.valid_up_to(),
+        _ => body.len(),
+    };
+    (start, end)
+}
+
+/// 读取 `offset` 处的 `length` 字节；`asText` 时按指定或检测到的编码解码
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn sftp_read_range(
+    app: AppHandle,
+    host_map: tauri::State<'_, SessionHostMap>,
+    mgr: tauri::State<'_, SftpManager>,
+    session_id: String,
+    path: String,
+    offset: u64,
+    length: u64,
+    as_text: Option<bool>,
+    encoding: Option<String>,
+) -> Result<RangeReadResult, String> {
+    let (sftp_arc, _) = sftp_get_host(&app, &host_map, &mgr, &session_id).await?;
+    let size = file_size(&sftp_arc, &path).await?;
+    let data = read_at(&sftp_arc, &path, offset, length.min(MAX_RANGE)).await?;
+    let end = offset + data.len() as u64;
+    let eof = end >= size;
+
+    if !as_text.unwrap_or(false) {
+        use base64::{Engine as _, engine::general_purpose};
+        return Ok(RangeReadResult {
+            offset,
+            next_offset: end,
+            file_size: size,
+            eof,
+            content: general_purpose::STANDARD.encode(&data),
+            encoding: None,
+        });
+    }
+
+    let encoding = match encoding {
+        Some(label) => encoding_rs::Encoding::for_label(label.as_bytes())
+            .ok_or_else(|| format!("Unknown encoding: {}", label))?,
+        None => encoding_rs::Encoding::for_label(crate::encoding::decode(&data).encoding.as_bytes())
+            .unwrap_or(encoding_rs::UTF_8),
+    };
+    // 只有 UTF-8 需要处理分块边界；其他多字节编码无法可靠地同步，交给替换字符
+    let (skip, keep) = if encoding == encoding_rs::UTF_8 { utf8_bounds(&data, eof) } else { (0, data.len()) };
+    let (text, _) = encoding.decode_without_bom_handling(&data[skip..skip + keep]);
+    Ok(RangeReadResult {
+        offset: offset + skip as u64,
+        next_offset: offset + (skip + keep) as u64,
+        file_size: size,
+        eof: eof && skip + keep == data.len(),
+        content: text.into_owned(),
+        encoding: Some(encoding.name().to_string()),
+    })
+}
+
+/// 读取文件末尾 `lines` 行：从末尾按块向前读，直到找到足够的换行或达到上限
+pub(crate) async fn tail_lines(
+    sftp_arc: &Arc<Mutex<SftpSession>>,
+    path: &str,
+    lines: usize,
+) -> Result<TailResult, String> {
+    let size = file_size(sftp_arc, path).await?;
+    let mut start = size;
+    let mut buf: Vec<u8> = Vec::new();
+    loop {
+        // 末尾的换行不算作一行的开始
+        let body = buf.strip_suffix(b"\n").unwrap_or(&buf);
+        let newlines = body.iter().filter(|&&b| b == b'\n').count();
+        if newlines >= lines || start == 0 || buf.len() as u64 >= MAX_RANGE {
+            break;
+        }
+        let chunk = TAIL_CHUNK.min(start);
+        start -= chunk;
+        let mut data = read_at(sftp_arc, path, start, chunk).await?;
+        data.extend_from_slice(&buf);
+        buf = data;
+    }
+
+    // 截掉多读的行
+    let body_len = buf.strip_suffix(b"\n").map(|b| b.len()).unwrap_or(buf.len());
+    let mut cut = 0;
+    let mut seen = 0;
+    for (i, &b) in buf[..body_len].iter().enumerate().rev() {
+        if b == b'\n' {
+            seen += 1;
+            if seen == lines {
+                cut = i + 1;
+                break;
+            }
+        }
+    }
+    let decoded = crate::encoding::decode(&buf[cut..]);
+    Ok(TailResult {
+        lines: decoded.text.lines().count(),
+        content: decoded.text,
+        start_offset: start + cut as u64,
+        file_size: size,
+        encoding: decoded.encoding.to_string(),
+    })
+}
+
+#[tauri::command]
+pub async fn sftp_read_tail(
+    app: AppHandle,
+    host_map: tauri::State<'_, SessionHostMap>,
+    mgr: tauri::State<'_, SftpManager>,
+    session_id: String,
+    path: String,
+    lines: usize,
+) -> Result<TailResult, String> {
+    let (sftp_arc, _) = sftp_get_host(&app, &host_map, &mgr, &session_id).await?;
+    tail_lines(&sftp_arc, &path, lines.max(1)).await
+}
+
+/// 十六进制视图的一页，每行 16 字节
+#[tauri::command]
+pub async fn sftp_read_hex_page(
+    app: AppHandle,
+    host_map: tauri::State<'_, SessionHostMap>,
+    mgr: tauri::State<'_, SftpManager>,
+    session_id: String,
+    path: String,
+    page: u64,
+    page_size: Option<u64>,
+) -> Result<HexPage, String> {
+    let page_size = page_size.unwrap_or(4096).clamp(HEX_ROW as u64, 1024 * 1024);
+    let (sftp_arc, _) = sftp_get_host(&app, &host_map, &mgr, &session_id).await?;
+    let size = file_size(&sftp_arc, &path).await?;
+    let offset = page.saturating_mul(page_size);
+    let data = if offset < size { read_at(&sftp_arc, &path, offset, page_size).await? } else { Vec::new() };
+
+    let rows = data
+        .chunks(HEX_ROW)
+        .enumerate()
+        .map(|(i, chunk)| HexRow {
+            offset: offset + (i * HEX_ROW) as u64,
+            hex: chunk.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" "),
+            ascii: chunk
+                .iter()
+                .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' })
+                .collect(),
+        })
+        .collect();
+    Ok(HexPage {
+        page,
+        page_size,
+        total_pages: size.div_ceil(page_size),
+        file_size: size,
+        rows,
+    })
+}
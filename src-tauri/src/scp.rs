@@ -0,0 +1,470 @@
+use std::path::Path;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use crate::sftp::{
+    collect_local_tree, exec_on_session, join_local, join_remote, local_stamp, open_exec_channel, resolve_dest,
+    shell_quote, system_time_secs, ConflictPolicy, FileStamp, SftpManager, TransferCtx, TransferSide,
+};
+
+/// 上传时每次发送的块大小
+const CHUNK: usize = 64 * 1024;
+
+/// T 记录中的 (mtime, atime)
+type Times = (u64, u64);
+
+/// exec 通道上的 SCP 协议流：缓冲 stdout，收集 stderr 与退出码
+struct ScpChannel {
+    channel: russh::Channel<russh::client::Msg>,
+    buf: Vec<u8>,
+    pos: usize,
+    stderr: String,
+    exit: Option<u32>,
+    closed: bool,
+}
+
+impl ScpChannel {
+    async fn open(mgr: &SftpManager, session_id: &str, command: &str) -> Result<Self, String> {
+        let channel = open_exec_channel(mgr, session_id, command).await?;
+        Ok(ScpChannel { channel, buf: Vec::new(), pos: 0, stderr: String::new(), exit: None, closed: false })
+    }
+
+    /// 等到缓冲区有数据；返回 false 表示远端已结束输出
+    async fn fill(&mut self, ctx: &TransferCtx) -> Result<bool, String> {
+        while self.pos == self.buf.len() {
+            if self.closed {
+                return Ok(false);
+            }
+            ctx.checkpoint().await?;
+            // 定时醒来检查暂停/取消
+            let msg = match tokio::time::timeout(Duration::from_millis(500), self.channel.wait()).await {
+                Ok(Some(msg)) => msg,
+                Ok(None) => {
+                    self.closed = true;
+                    continue;
+                }
+                Err(_) => continue,
+            };
+            match msg {
+                russh::ChannelMsg::Data { data } => {
+                    self.buf.clear();
+                    self.buf.extend_from_slice(&data);
+                    self.pos = 0;
+                }
+                russh::ChannelMsg::ExtendedData { data, ext: 1 } => self.stderr.push_str(&String::from_utf8_lossy(&data)),
+                russh::ChannelMsg::ExitStatus { exit_status } => self.exit = Some(exit_status),
+                // 退出码在 Eof 之后才到，等到 Close 再结束
+                russh::ChannelMsg::Close => self.closed = true,
+                _ => {}
+            }
+        }
+        Ok(true)
+    }
+
+    fn unexpected_end(&self) -> String {
+        match self.stderr.trim() {
+            "" => "scp: unexpected end of stream".to_string(),
+            message => format!("scp: {}", message),
+        }
+    }
+
+    /// 读取一个字节；流结束时返回 None
+    async fn next_byte(&mut self, ctx: &TransferCtx) -> Result<Option<u8>, String> {
+        if !self.fill(ctx).await? {
+            return Ok(None);
+        }
+        self.pos += 1;
+        Ok(Some(self.buf[self.pos - 1]))
+    }
+
+    async fn read_line(&mut self, ctx: &TransferCtx) -> Result<String, String> {
+        let mut line = Vec::new();
+        loop {
+            match self.next_byte(ctx).await? {
+                Some(b'\n') => return Ok(String::from_utf8_lossy(&line).to_string()),
+                Some(b) => line.push(b),
+                None => return Err(self.unexpected_end()),
+            }
+        }
+    }
+
+    /// 读取不超过 max 字节的文件内容
+    async fn read_chunk(&mut self, ctx: &TransferCtx, max: u64) -> Result<&[u8], String> {
+        if !self.fill(ctx).await? {
+            return Err(self.unexpected_end());
+        }
+        let n = (self.buf.len() - self.pos).min(max as usize);
+        self.pos += n;
+        Ok(&self.buf[self.pos - n..self.pos])
+    }
+
+    /// 等待对端确认：0 为成功，1/2 后跟一行错误信息
+    async fn ack(&mut self, ctx: &TransferCtx) -> Result<(), String> {
+        match self.next_byte(ctx).await? {
+            Some(0) => Ok(()),
+            Some(1) | Some(2) => Err(format!("scp: {}", self.read_line(ctx).await?)),
+            Some(b) => Err(format!("scp: unexpected response 0x{:02x}", b)),
+            None => Err(self.unexpected_end()),
+        }
+    }
+
+    async fn send(&mut self, data: &[u8]) -> Result<(), String> {
+        self.channel.data(data).await.map_err(|e| e.to_string())
+    }
+
+    /// 发送 EOF 并等待远端退出
+    async fn finish(mut self, ctx: &TransferCtx) -> Result<(), String> {
+        let _ = self.channel.eof().await;
+        while self.fill(ctx).await? {
+            self.pos = self.buf.len();
+        }
+        match self.exit {
+            Some(0) | None => Ok(()),
+            Some(code) => Err(match self.stderr.trim() {
+                "" => format!("scp exited with status {}", code),
+                message => format!("scp: {}", message),
+            }),
+        }
+    }
+}
+
+/// 解析 `C0644 1234 name` / `D0755 0 name`（不含首字母）
+fn parse_record(line: &str) -> Result<(u32, u64, String), String> {
+    let mut parts = line.splitn(3, ' ');
+    let mode = parts.next().and_then(|m| u32::from_str_radix(m, 8).ok());
+    let size = parts.next().and_then(|s| s.parse().ok());
+    let name = parts.next();
+    match (mode, size, name) {
+        // 不接受会跳出目标目录的名字
+        (Some(mode), Some(size), Some(name)) if !name.is_empty() && name != ".." && !name.contains('/') => {
+            Ok((mode, size, name.to_string()))
+        }
+        _ => Err(format!("scp: invalid record {:?}", line)),
+    }
+}
+
+/// 解析 `T<mtime> 0 <atime> 0`（不含首字母），返回 (mtime, atime)
+fn parse_times(line: &str) -> Result<Times, String> {
+    let fields: Vec<u64> = line.split(' ').filter_map(|f| f.parse().ok()).collect();
+    match fields.as_slice() {
+        [mtime, _, atime, _] => Ok((*mtime, *atime)),
+        _ => Err(format!("scp: invalid time record {:?}", line)),
+    }
+}
+
+fn local_mode(meta: &std::fs::Metadata, default: u32) -> u32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let _ = default;
+        meta.permissions().mode() & 0o7777
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = meta;
+        default
+    }
+}
+
+/// 把 SCP 记录中的权限与时间戳应用到本地文件或目录
+fn apply_local_attrs(path: &str, mode: u32, times: Option<Times>) -> Result<(), String> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode & 0o7777)).map_err(|e| e.to_string())?;
+    }
+    #[cfg(not(unix))]
+    let _ = mode;
+    if let Some((mtime, atime)) = times {
+        let mtime = filetime::FileTime::from_unix_time(mtime as i64, 0);
+        let atime = filetime::FileTime::from_unix_time(atime as i64, 0);
+        filetime::set_file_times(path, atime, mtime).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+fn remote_name(path: &str) -> &str {
+    path.trim_end_matches('/').rsplit('/').next().unwrap_or(path)
+}
+
+/// 以 SCP source 模式（`scp -f`）下载文件或目录，目标路径语义与 SFTP 下载相同
+pub(crate) async fn run_download(
+    mgr: &SftpManager,
+    session_id: &str,
+    ctx: &mut TransferCtx,
+    remote_path: &str,
+    local_path: &str,
+) -> Result<(), String> {
+    // 目录总大小只能估算，收到的文件超出估算时再调大
+    let du = format!("du -sk {}", shell_quote(remote_path));
+    if let Ok((0, stdout)) = exec_on_session(mgr, session_id, &du).await {
+        let kb = String::from_utf8_lossy(&stdout).split_whitespace().next().and_then(|s| s.parse::<u64>().ok());
+        ctx.total = kb.unwrap_or(0) * 1024;
+    }
+
+    let flags = if ctx.preserve { "-r -p" } else { "-r" };
+    let mut scp = ScpChannel::open(mgr, session_id, &format!("scp {} -f {}", flags, shell_quote(remote_path))).await?;
+    scp.send(&[0]).await?;
+
+    // (远程目录, 本地目录, 权限, 时间戳)，E 记录时出栈并设置属性
+    let mut dirs: Vec<(String, String, u32, Option<Times>)> = Vec::new();
+    let mut times = None;
+    let mut warnings = Vec::new();
+    while let Some(kind) = scp.next_byte(ctx).await? {
+        let line = scp.read_line(ctx).await?;
+        match kind {
+            // 单个文件出错（如无权限）时 source 发出警告后继续
+            1 => {
+                warnings.push(line);
+                continue;
+            }
+            2 => return Err(format!("scp: {}", line)),
+            b'T' => times = Some(parse_times(&line)?),
+            b'D' => {
+                let (mode, _, name) = parse_record(&line)?;
+                let (remote, local) = match dirs.last() {
+                    Some((remote, local, _, _)) => (join_remote(remote, &name), join_local(local, &name)),
+                    None => (remote_path.to_string(), local_path.to_string()),
+                };
+                tokio::fs::create_dir_all(&local).await.map_err(|e| e.to_string())?;
+                dirs.push((remote, local, mode, times.take()));
+            }
+            b'E' => {
+                let (_, local, mode, dir_times) = dirs.pop().ok_or("scp: unbalanced directory end")?;
+                // 目录时间戳在子项写完后设置
+                if ctx.preserve {
+                    apply_local_attrs(&local, mode, dir_times)?;
+                }
+            }
+            b'C' => {
+                let (mode, size, name) = parse_record(&line)?;
+                let (remote, local) = match dirs.last() {
+                    Some((remote, local, _, _)) => (join_remote(remote, &name), join_local(local, &name)),
+                    None => (remote_path.to_string(), local_path.to_string()),
+                };
+                let file_times = times.take();
+                ctx.total = ctx.total.max(ctx.done + size);
+                let source = FileStamp { size, mtime: file_times.map(|t| t.0).unwrap_or(0) };
+                let target = resolve_dest(mgr, ctx, TransferSide::Local, &remote, local, source).await?;
+                scp.send(&[0]).await?;
+
+                // 跳过的文件仍需从流中读走
+                let mut file = match &target {
+                    Some(path) => Some(tokio::fs::File::create(path).await.map_err(|e| e.to_string())?),
+                    None => None,
+                };
+                let result: Result<(), String> = async {
+                    let mut remaining = size;
+                    while remaining > 0 {
+                        let chunk = scp.read_chunk(ctx, remaining).await?;
+                        let n = chunk.len() as u64;
+                        if let Some(file) = file.as_mut() {
+                            file.write_all(chunk).await.map_err(|e| e.to_string())?;
+                        }
+                        ctx.throttle(n).await?;
+                        ctx.advance(n, &remote);
+                        remaining -= n;
+                    }
+                    if let Some(file) = file.as_mut() {
+                        file.flush().await.map_err(|e| e.to_string())?;
+                    }
+                    scp.ack(ctx).await
+                }
+                .await;
+                drop(file);
+                if let Err(e) = result {
+                    if let Some(path) = &target {
+                        let _ = tokio::fs::remove_file(path).await;
+                    }
+                    return Err(e);
+                }
+
+                if let Some(path) = &target {
+                    if ctx.preserve {
+                        apply_local_attrs(path, mode, file_times)?;
+                    }
+                    if ctx.verify {
+                        ctx.verify_file(mgr, &remote, path).await;
+                    }
+                }
+            }
+            other => return Err(format!("scp: unexpected record 0x{:02x}", other)),
+        }
+        scp.send(&[0]).await?;
+    }
+
+    scp.finish(ctx).await.or_else(|e| if warnings.is_empty() { Err(e) } else { Ok(()) })?;
+    if !warnings.is_empty() {
+        return Err(format!("scp: {}", warnings.join("; ")));
+    }
+    Ok(())
+}
+
+/// 发送一个文件（T/C 记录与内容），name 为目标目录中的文件名
+async fn send_file(
+    scp: &mut ScpChannel,
+    ctx: &mut TransferCtx,
+    local_path: &str,
+    name: &str,
+    remote_path: &str,
+) -> Result<(), String> {
+    if name.contains('\n') {
+        return Err(format!("scp cannot transfer file names containing newlines: {}", local_path));
+    }
+    let meta = tokio::fs::metadata(local_path).await.map_err(|e| e.to_string())?;
+    if ctx.preserve {
+        let times = format!("T{} 0 {} 0\n", system_time_secs(meta.modified()), system_time_secs(meta.accessed()));
+        scp.send(times.as_bytes()).await?;
+        scp.ack(ctx).await?;
+    }
+    let size = meta.len();
+    scp.send(format!("C{:04o} {} {}\n", local_mode(&meta, 0o644), size, name).as_bytes()).await?;
+    scp.ack(ctx).await?;
+
+    // 只发送声明的长度，传输过程中文件变大也不破坏协议流
+    let file = tokio::fs::File::open(local_path).await.map_err(|e| e.to_string())?;
+    let mut reader = file.take(size);
+    let mut buf = vec![0u8; CHUNK];
+    let mut sent = 0u64;
+    loop {
+        ctx.checkpoint().await?;
+        let n = reader.read(&mut buf).await.map_err(|e| e.to_string())?;
+        if n == 0 {
+            break;
+        }
+        ctx.throttle(n as u64).await?;
+        scp.send(&buf[..n]).await?;
+        sent += n as u64;
+        ctx.advance(n as u64, remote_path);
+    }
+    if sent < size {
+        return Err(format!("{} shrank during upload", local_path));
+    }
+    scp.send(&[0]).await?;
+    scp.ack(ctx).await
+}
+
+/// 按冲突策略确定远程路径；覆盖策略下不必先查询远程文件
+async fn resolve_upload(
+    mgr: &SftpManager,
+    session_id: &str,
+    ctx: &mut TransferCtx,
+    local_path: &str,
+    dest: String,
+) -> Result<Option<String>, String> {
+    if ctx.policy == ConflictPolicy::Overwrite {
+        return Ok(Some(dest));
+    }
+    let source = local_stamp(local_path).await.ok_or_else(|| format!("Cannot read {}", local_path))?;
+    resolve_dest(mgr, ctx, TransferSide::Shell(mgr, session_id), local_path, dest, source).await
+}
+
+enum Step {
+    Enter { local: String, remote: String },
+    Leave,
+}
+
+/// 以 SCP sink 模式（`scp -t`）上传文件或目录，目标路径语义与 SFTP 上传相同
+pub(crate) async fn run_upload(
+    mgr: &SftpManager,
+    session_id: &str,
+    ctx: &mut TransferCtx,
+    local_path: &str,
+    remote_path: &str,
+) -> Result<(), String> {
+    let meta = tokio::fs::metadata(local_path).await.map_err(|e| e.to_string())?;
+    let filename = Path::new(local_path).file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("upload");
+    let dest = if remote_path.ends_with('/') {
+        format!("{}{}", remote_path, filename)
+    } else {
+        remote_path.to_string()
+    };
+    let preserve = if ctx.preserve { " -p" } else { "" };
+
+    if !meta.is_dir() {
+        ctx.total = meta.len();
+        let Some(dest) = resolve_upload(mgr, session_id, ctx, local_path, dest).await? else {
+            ctx.advance(meta.len(), local_path);
+            return Ok(());
+        };
+        let mut scp = ScpChannel::open(mgr, session_id, &format!("scp{} -t {}", preserve, shell_quote(&dest))).await?;
+        scp.ack(ctx).await?;
+        send_file(&mut scp, ctx, local_path, remote_name(&dest), &dest).await?;
+        scp.finish(ctx).await?;
+        if ctx.verify {
+            ctx.verify_file(mgr, &dest, local_path).await;
+        }
+        return Ok(());
+    }
+
+    let tree = collect_local_tree(local_path, &dest).await?;
+    ctx.total = tree.iter().map(|e| e.3).sum();
+    let parent = match dest.trim_end_matches('/').rfind('/') {
+        Some(0) => "/".to_string(),
+        Some(pos) => dest[..pos].to_string(),
+        None => ".".to_string(),
+    };
+    let mut scp = ScpChannel::open(mgr, session_id, &format!("scp -r{} -t {}", preserve, shell_quote(&parent))).await?;
+    scp.ack(ctx).await?;
+
+    // 进入目录时先发送其中的文件，子目录压栈在 Leave 之上，保证 D/E 正确嵌套
+    let mut verify = Vec::new();
+    let mut stack = vec![Step::Enter { local: local_path.to_string(), remote: dest.trim_end_matches('/').to_string() }];
+    while let Some(step) = stack.pop() {
+        ctx.checkpoint().await?;
+        let (local, remote) = match step {
+            Step::Leave => {
+                scp.send(b"E\n").await?;
+                scp.ack(ctx).await?;
+                continue;
+            }
+            Step::Enter { local, remote } => (local, remote),
+        };
+        let meta = tokio::fs::metadata(&local).await.map_err(|e| e.to_string())?;
+        if ctx.preserve {
+            let times = format!("T{} 0 {} 0\n", system_time_secs(meta.modified()), system_time_secs(meta.accessed()));
+            scp.send(times.as_bytes()).await?;
+            scp.ack(ctx).await?;
+        }
+        scp.send(format!("D{:04o} 0 {}\n", local_mode(&meta, 0o755), remote_name(&remote)).as_bytes()).await?;
+        scp.ack(ctx).await?;
+        stack.push(Step::Leave);
+
+        let mut rd = tokio::fs::read_dir(&local).await.map_err(|e| e.to_string())?;
+        while let Some(entry) = rd.next_entry().await.map_err(|e| e.to_string())? {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let child_local = entry.path().to_string_lossy().to_string();
+            let child_remote = join_remote(&remote, &name);
+            if entry.metadata().await.map_err(|e| e.to_string())?.is_dir() {
+                stack.push(Step::Enter { local: child_local, remote: child_remote });
+                continue;
+            }
+            let Some(target) = resolve_upload(mgr, session_id, ctx, &child_local, child_remote).await? else {
+                let size = tokio::fs::metadata(&child_local).await.map(|m| m.len()).unwrap_or(0);
+                ctx.advance(size, &child_local);
+                continue;
+            };
+            send_file(&mut scp, ctx, &child_local, remote_name(&target), &target).await?;
+            if ctx.verify {
+                verify.push((target, child_local));
+            }
+        }
+    }
+    scp.finish(ctx).await?;
+
+    for (remote, local) in verify {
+        ctx.verify_file(mgr, &remote, &local).await;
+    }
+    Ok(())
+}
+
+/// 会话使用的传输协议：`sftp`，或服务器禁用 SFTP 子系统时的 `scp`
+#[tauri::command]
+pub async fn sftp_transfer_protocol(
+    mgr: tauri::State<'_, SftpManager>,
+    session_id: String,
+) -> Result<String, String> {
+    let scp_only = mgr.scp_only.read().await.contains(&session_id);
+    Ok(if scp_only { "scp" } else { "sftp" }.to_string())
+}
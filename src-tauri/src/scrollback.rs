@@ -0,0 +1,216 @@
+use regex::RegexBuilder;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::ssh::SshManager;
+
+/// 裁剪时最多向后查找这么多字节寻找换行，尽量从整行开始保留
+const LINE_SEARCH: usize = 4096;
+/// 默认最多返回的匹配行数
+const DEFAULT_MAX_RESULTS: usize = 500;
+
+/// 每个会话终端输出的环形缓冲区。offset 与 shell 集成中命令记录的 offset 是同一个字节流位置
+pub struct Scrollback {
+    data: VecDeque<u8>,
+    /// data[0] 在输出流中的位置
+    start: u64,
+}
+
+impl Scrollback {
+    pub fn new() -> Self {
+        Scrollback { data: VecDeque::new(), start: 0 }
+    }
+
+    fn end(&self) -> u64 {
+        self.start + self.data.len() as u64
+    }
+
+    /// 追加输出并把缓冲区裁剪到 limit 字节以内
+    pub fn push(&mut self, bytes: &[u8], limit: usize) {
+        if limit == 0 {
+            self.start = self.end() + bytes.len() as u64;
+            self.data.clear();
+            return;
+        }
+        self.data.extend(bytes);
+        if self.data.len() <= limit {
+            return;
+        }
+        let mut cut = self.data.len() - limit;
+        // 从下一行开头保留，避免回放时从半个字符或转义序列开始
+        if let Some(pos) = self.data.range(cut..).take(LINE_SEARCH).position(|b| *b == b'\n') {
+            cut += pos + 1;
+        }
+        self.data.drain(..cut);
+        self.start += cut as u64;
+    }
+
+    fn clear(&mut self) {
+        self.start = self.end();
+        self.data.clear();
+    }
+}
+
+/// 回放范围内的输出
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ScrollbackChunk {
+    pub data: String,
+    /// 返回数据的起止位置；start 大于请求的 since 说明中间部分已被裁剪
+    pub start: u64,
+    pub end: u64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ScrollbackMatch {
+    pub session_id: String,
+    /// 所在行在输出流中的位置
+    pub offset: u64,
+    /// 去掉控制序列后的行内容
+    pub line: String,
+    /// 行内匹配的 [起, 止) 字符位置
+    pub ranges: Vec<(usize, usize)>,
+}
+
+pub struct ScrollbackManager {
+    /// 每个会话缓冲区的字节上限，0 为不保留
+    limit: AtomicUsize,
+}
+
+impl ScrollbackManager {
+    pub fn new() -> Self {
+        ScrollbackManager { limit: AtomicUsize::new(crate::settings::AppSettings::default().scrollback_limit) }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit.load(Ordering::Relaxed)
+    }
+
+    /// 新上限在各会话下一次收到输出时生效
+    pub fn set_limit(&self, limit: usize) {
+        self.limit.store(limit, Ordering::Relaxed);
+    }
+}
+
+/// 去掉 CSI / OSC 等转义序列和控制字符，得到终端上可见的文本
+pub(crate) fn strip_ansi(line: &[u8]) -> String {
+    let mut out = Vec::with_capacity(line.len());
+    let mut i = 0;
+    while i < line.len() {
+        match line[i] {
+            0x1b => {
+                i += 1;
+                match line.get(i) {
+                    Some(b'[') => {
+                        i += 1;
+                        while i < line.len() && !(0x40..=0x7e).contains(&line[i]) {
+                            i += 1;
+                        }
+                    }
+                    Some(b']') | Some(b'P') | Some(b'_') => {
+                        while i < line.len() && line[i] != 0x07 && !(line[i] == b'\\' && line[i - 1] == 0x1b) {
+                            i += 1;
+                        }
+                    }
+                    _ => {}
+                }
+                i += 1;
+            }
+            b'\t' => {
+                out.push(b'\t');
+                i += 1;
+            }
+            b if b < 0x20 || b == 0x7f => i += 1,
+            b => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).to_string()
+}
+
+/// 把会话缓冲区中 since 之后的输出取出，用于在新的终端视图中回放
+#[tauri::command]
+pub async fn ssh_scrollback_replay(
+    manager: tauri::State<'_, SshManager>,
+    session_id: String,
+    since: Option<u64>,
+) -> Result<ScrollbackChunk, String> {
+    let session = manager.0.read().await.get(&session_id).cloned()
+        .ok_or_else(|| format!("Session not found: {}", session_id))?;
+    let buffer = session.scrollback.read().await;
+    let start = since.unwrap_or(0).clamp(buffer.start, buffer.end());
+    let bytes: Vec<u8> = buffer.data.range((start - buffer.start) as usize..).copied().collect();
+    Ok(ScrollbackChunk {
+        data: String::from_utf8_lossy(&bytes).to_string(),
+        start,
+        end: buffer.end(),
+    })
+}
+
+/// 在一个或多个会话（为空时所有会话）的缓冲区中按行搜索
+#[tauri::command]
+pub async fn ssh_scrollback_search(
+    manager: tauri::State<'_, SshManager>,
+    session_ids: Option<Vec<String>>,
+    pattern: String,
+    is_regex: bool,
+    case_sensitive: bool,
+    max_results: Option<usize>,
+) -> Result<Vec<ScrollbackMatch>, String> {
+    let pattern = if is_regex { pattern } else { regex::escape(&pattern) };
+    let re = RegexBuilder::new(&pattern)
+        .case_insensitive(!case_sensitive)
+        .build()
+        .map_err(|e| e.to_string())?;
+    let max_results = max_results.unwrap_or(DEFAULT_MAX_RESULTS);
+
+    let sessions: Vec<_> = {
+        let map = manager.0.read().await;
+        match session_ids {
+            Some(ids) => ids.iter().filter_map(|id| map.get(id).map(|s| (id.clone(), s.clone()))).collect(),
+            None => map.iter().map(|(id, s)| (id.clone(), s.clone())).collect(),
+        }
+    };
+
+    let mut results = Vec::new();
+    for (session_id, session) in sessions {
+        // 只在复制时持有读锁，匹配期间不阻塞输出写入
+        let (start, data) = {
+            let buffer = session.scrollback.read().await;
+            let (front, back) = buffer.data.as_slices();
+            (buffer.start, [front, back].concat())
+        };
+        let mut offset = start;
+        for raw in data.split(|b| *b == b'\n') {
+            let line_offset = offset;
+            offset += raw.len() as u64 + 1;
+            let line = strip_ansi(raw);
+            let ranges: Vec<(usize, usize)> = re
+                .find_iter(&line)
+                .map(|m| (line[..m.start()].chars().count(), line[..m.end()].chars().count()))
+                .collect();
+            if ranges.is_empty() {
+                continue;
+            }
+            results.push(ScrollbackMatch { session_id: session_id.clone(), offset: line_offset, line, ranges });
+            if results.len() >= max_results {
+                return Ok(results);
+            }
+        }
+    }
+    Ok(results)
+}
+
+#[tauri::command]
+pub async fn ssh_scrollback_clear(
+    manager: tauri::State<'_, SshManager>,
+    session_id: String,
+) -> Result<(), String> {
+    let session = manager.0.read().await.get(&session_id).cloned()
+        .ok_or_else(|| format!("Session not found: {}", session_id))?;
+    session.scrollback.write().await.clear();
+    Ok(())
+}
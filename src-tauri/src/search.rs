@@ -0,0 +1,496 @@
+use regex::{Regex, RegexBuilder};
+use russh_sftp::client::SftpSession;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter};
+use tokio::sync::{Mutex, RwLock};
+use crate::sftp::{exec_on_session, join_remote, open_exec_channel, sftp_get_host, shell_quote, SessionHostMap, SftpManager};
+
+/// 默认最多返回的结果数
+const DEFAULT_MAX_RESULTS: usize = 1000;
+/// SFTP 回退搜索内容时每个文件最多读取的字节数
+const CONTENT_LIMIT: u64 = 32 * 1024 * 1024;
+/// exec 模式下每次交给 grep 的文件数
+const GREP_BATCH: usize = 64;
+/// 返回的匹配行最多保留的字符数
+const MAX_LINE_CHARS: usize = 500;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum NameMatch {
+    /// `*`、`?`、`[abc]` 通配符，匹配整个文件名
+    #[default]
+    Glob,
+    /// 正则表达式，匹配文件名的任意部分
+    Regex,
+}
+
+/// 搜索条件，全部可选；时间为 Unix 秒
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct FindQuery {
+    pub name: Option<String>,
+    pub name_match: NameMatch,
+    pub case_sensitive: bool,
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    pub modified_after: Option<u64>,
+    pub modified_before: Option<u64>,
+    /// 文件内容包含的文本；`contentRegex` 为 true 时按正则处理
+    /// （exec 模式交给 `grep -E`，与 Rust 正则语法略有差异）
+    pub content: Option<String>,
+    pub content_regex: bool,
+    /// 目录是否也作为结果（设置了大小或内容条件时忽略）
+    pub include_dirs: bool,
+    pub max_depth: Option<usize>,
+    pub max_results: Option<usize>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FindMatch {
+    pub path: String,
+    pub name: String,
+    pub is_dir: bool,
+    pub size: u64,
+    pub mtime: u64,
+    /// 内容搜索时第一处匹配的行号与内容
+    pub line_number: Option<u64>,
+    pub line: Option<String>,
+}
+
+/// `sftp:find-results` 事件负载，结果分批推送
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FindResultsEvent {
+    pub search_id: String,
+    pub matches: Vec<FindMatch>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FindSummary {
+    pub search_id: String,
+    /// `exec` 或 `sftp`
+    pub method: String,
+    pub matched: usize,
+    pub scanned: usize,
+    /// 达到 maxResults 后提前结束
+    pub truncated: bool,
+    pub cancelled: bool,
+}
+
+/// searchId -> 取消标记；磁盘用量分析（scanId）也登记在这里
+pub struct SearchManager(pub RwLock<HashMap<String, Arc<AtomicBool>>>);
+
+impl SearchManager {
+    pub fn new() -> Self {
+        SearchManager(RwLock::new(HashMap::new()))
+    }
+}
+
+/// 把通配符转换成锚定的正则
+fn glob_to_regex(glob: &str) -> String {
+    let mut out = String::from("^");
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => out.push_str("[^/]*"),
+            '?' => out.push_str("[^/]"),
+            '[' => {
+                out.push('[');
+                if chars.peek() == Some(&'!') {
+                    chars.next();
+                    out.push('^');
+                }
+                for c in chars.by_ref() {
+                    if c == ']' {
+                        break;
+                    }
+                    if c == '\\' || c == '[' {
+                        out.push('\\');
+                    }
+                    out.push(c);
+                }
+                out.push(']');
+            }
+            c => out.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    out.push('$');
+    out
+}
+
+fn build_regex(pattern: &str, case_sensitive: bool) -> Result<Regex, String> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(!case_sensitive)
+        .build()
+        .map_err(|e| format!("Invalid pattern {}: {}", pattern, e))
+}
+
+/// 编译后的搜索条件
+struct Filter {
+    name: Option<Regex>,
+    content: Option<Regex>,
+    query: FindQuery,
+}
+
+impl Filter {
+    fn new(query: FindQuery) -> Result<Self, String> {
+        let name = match query.name.as_deref().filter(|n| !n.is_empty()) {
+            Some(n) if query.name_match == NameMatch::Glob => Some(build_regex(&glob_to_regex(n), query.case_sensitive)?),
+            Some(n) => Some(build_regex(n, query.case_sensitive)?),
+            None => None,
+        };
+        let content = match query.content.as_deref().filter(|c| !c.is_empty()) {
+            Some(c) if query.content_regex => Some(build_regex(c, query.case_sensitive)?),
+            Some(c) => Some(build_regex(&regex::escape(c), query.case_sensitive)?),
+            None => None,
+        };
+        Ok(Filter { name, content, query })
+    }
+
+    /// 按名称、大小和时间判断；内容条件另行检查
+    fn matches(&self, name: &str, is_dir: bool, size: u64, mtime: u64) -> bool {
+        let q = &self.query;
+        if is_dir && (!q.include_dirs || self.content.is_some() || q.min_size.is_some() || q.max_size.is_some()) {
+            return false;
+        }
+        self.name.as_ref().is_none_or(|re| re.is_match(name))
+            && q.min_size.is_none_or(|min| size >= min)
+            && q.max_size.is_none_or(|max| size <= max)
+            && q.modified_after.is_none_or(|t| mtime >= t)
+            && q.modified_before.is_none_or(|t| mtime <= t)
+    }
+}
+
+fn clip_line(line: &str) -> String {
+    line.trim_end_matches(['\r', '\n']).chars().take(MAX_LINE_CHARS).collect()
+}
+
+fn base_name(path: &str) -> String {
+    path.rsplit('/').next().unwrap_or(path).to_string()
+}
+
+/// 分批推送结果：攒够 50 条或距上次推送超过 300ms
+struct ResultStream<'a> {
+    app: &'a AppHandle,
+    search_id: &'a str,
+    pending: Vec<FindMatch>,
+    last_flush: Instant,
+    matched: usize,
+    scanned: usize,
+    limit: usize,
+}
+
+impl ResultStream<'_> {
+    fn push(&mut self, m: FindMatch) {
+        self.matched += 1;
+        self.pending.push(m);
+        if self.pending.len() >= 50 || self.last_flush.elapsed() >= Duration::from_millis(300) {
+            self.flush();
+        }
+    }
+
+    fn flush(&mut self) {
+        self.last_flush = Instant::now();
+        if self.pending.is_empty() {
+            return;
+        }
+        let _ = self.app.emit("sftp:find-results", FindResultsEvent {
+            search_id: self.search_id.to_string(),
+            matches: std::mem::take(&mut self.pending),
+        });
+    }
+
+    fn full(&self) -> bool {
+        self.matched >= self.limit
+    }
+}
+
+/// 远程是否有 GNU find（`-printf`）；有内容条件时还要求 GNU grep（`-Z`、`-m`）
+async fn exec_available(mgr: &SftpManager, session_id: &str, need_grep: bool) -> bool {
+    let mut script = String::from("find / -maxdepth 0 -printf '' >/dev/null 2>&1 || exit 2;");
+    if need_grep {
+        script.push_str(" grep -q -Z -m 1 x /dev/null 2>/dev/null; [ $? -le 1 ]");
+    }
+    let command = format!("sh -c {}", shell_quote(&script));
+    matches!(exec_on_session(mgr, session_id, &command).await, Ok((0, _)))
+}
+
+/// 对一批候选文件运行 grep，输出形如 `path\0行号:内容`
+async fn grep_batch(
+    mgr: &SftpManager,
+    session_id: &str,
+    filter: &Filter,
+    batch: &mut Vec<FindMatch>,
+    cancel: &AtomicBool,
+    stream: &mut ResultStream<'_>,
+) -> Result<(), String> {
+    if batch.is_empty() || stream.full() {
+        return Ok(());
+    }
+    let q = &filter.query;
+    let mut command = String::from("grep -I -n -m 1 -H -Z");
+    if !q.case_sensitive {
+        command.push_str(" -i");
+    }
+    command.push_str(if q.content_regex { " -E" } else { " -F" });
+    command.push_str(&format!(" -e {} --", shell_quote(q.content.as_deref().unwrap_or_default())));
+    for m in batch.iter() {
+        command.push(' ');
+        command.push_str(&shell_quote(&m.path));
+    }
+    // grep 没有匹配时退出码为 1，读不了的文件为 2，都照常解析输出
+    let mut channel = open_exec_channel(mgr, session_id, &command).await?;
+    let mut out = Vec::new();
+    loop {
+        if cancel.load(Ordering::Relaxed) {
+            let _ = channel.close().await;
+            return Ok(());
+        }
+        // 大文件上 grep 可能长时间没有输出，定时醒来检查取消
+        let msg = match tokio::time::timeout(Duration::from_millis(500), channel.wait()).await {
+            Ok(Some(msg)) => msg,
+            Ok(None) => break,
+            Err(_) => continue,
+        };
+        match msg {
+            russh::ChannelMsg::Data { data } => out.extend_from_slice(&data),
+            russh::ChannelMsg::Close => break,
+            _ => {}
+        }
+    }
+    let mut candidates: HashMap<String, FindMatch> = batch.drain(..).map(|m| (m.path.clone(), m)).collect();
+    for line in String::from_utf8_lossy(&out).lines() {
+        let Some((path, rest)) = line.split_once('\0') else {
+            continue;
+        };
+        let Some(mut m) = candidates.remove(path) else {
+            continue;
+        };
+        if let Some((num, text)) = rest.split_once(':') {
+            m.line_number = num.parse().ok();
+            m.line = Some(clip_line(text));
+        }
+        stream.push(m);
+        if stream.full() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// exec 模式：`find -printf` 流式列出条目，名称/大小/时间在本地过滤，内容交给 grep
+async fn find_exec(
+    mgr: &SftpManager,
+    session_id: &str,
+    root: &str,
+    filter: &Filter,
+    cancel: &AtomicBool,
+    stream: &mut ResultStream<'_>,
+) -> Result<(), String> {
+    let mut command = format!("find {}", shell_quote(root));
+    if let Some(depth) = filter.query.max_depth {
+        command.push_str(&format!(" -maxdepth {}", depth));
+    }
+    command.push_str(" -mindepth 1 -printf '%y\\t%s\\t%T@\\t%p\\0' 2>/dev/null");
+    let mut channel = open_exec_channel(mgr, session_id, &command).await?;
+
+    let mut buf: Vec<u8> = Vec::new();
+    let mut batch: Vec<FindMatch> = Vec::new();
+    loop {
+        if cancel.load(Ordering::Relaxed) || stream.full() {
+            let _ = channel.close().await;
+            return Ok(());
+        }
+        // find 在没有匹配的大目录树中可能长时间没有输出，定时醒来检查取消
+        let msg = match tokio::time::timeout(Duration::from_millis(500), channel.wait()).await {
+            Ok(Some(msg)) => msg,
+            Ok(None) => break,
+            Err(_) => continue,
+        };
+        let data = match msg {
+            russh::ChannelMsg::Data { data } => data,
+            russh::ChannelMsg::Close => break,
+            _ => continue,
+        };
+        buf.extend_from_slice(&data);
+        while let Some(pos) = buf.iter().position(|&b| b == 0) {
+            let record: Vec<u8> = buf.drain(..=pos).collect();
+            let record = String::from_utf8_lossy(&record[..pos]).to_string();
+            let mut fields = record.splitn(4, '\t');
+            let (Some(kind), Some(size), Some(mtime), Some(path)) = (fields.next(), fields.next(), fields.next(), fields.next()) else {
+                continue;
+            };
+            stream.scanned += 1;
+            // 只处理普通文件和目录，与 SFTP 回退一致
+            let is_dir = match kind {
+                "d" => true,
+                "f" => false,
+                _ => continue,
+            };
+            let size: u64 = size.parse().unwrap_or(0);
+            let mtime = mtime.parse::<f64>().unwrap_or(0.0) as u64;
+            let name = base_name(path);
+            if !filter.matches(&name, is_dir, size, mtime) {
+                continue;
+            }
+            let m = FindMatch { path: path.to_string(), name, is_dir, size, mtime, line_number: None, line: None };
+            if filter.content.is_none() {
+                stream.push(m);
+                continue;
+            }
+            batch.push(m);
+            if batch.len() >= GREP_BATCH {
+                grep_batch(mgr, session_id, filter, &mut batch, cancel, stream).await?;
+            }
+        }
+    }
+    if !cancel.load(Ordering::Relaxed) && !stream.full() {
+        grep_batch(mgr, session_id, filter, &mut batch, cancel, stream).await?;
+    }
+    Ok(())
+}
+
+/// 在文件开头最多 CONTENT_LIMIT 字节中查找第一处匹配；含 NUL 的文件视为二进制跳过
+async fn grep_sftp(
+    sftp_arc: &Arc<Mutex<SftpSession>>,
+    path: &str,
+    size: u64,
+    re: &Regex,
+) -> Option<(u64, String)> {
+    let data = crate::range::read_at(sftp_arc, path, 0, size.min(CONTENT_LIMIT)).await.ok()?;
+    if data.iter().take(8192).any(|&b| b == 0) {
+        return None;
+    }
+    let text = String::from_utf8_lossy(&data);
+    text.lines()
+        .enumerate()
+        .find(|(_, line)| re.is_match(line))
+        .map(|(i, line)| (i as u64 + 1, clip_line(line)))
+}
+
+/// 回退方案：通过 SFTP 逐层遍历目录，不跟随符号链接
+async fn find_sftp(
+    sftp_arc: &Arc<Mutex<SftpSession>>,
+    root: &str,
+    filter: &Filter,
+    cancel: &AtomicBool,
+    stream: &mut ResultStream<'_>,
+) -> Result<(), String> {
+    let mut stack = vec![(root.to_string(), 1usize)];
+    while let Some((dir, depth)) = stack.pop() {
+        let entries = {
+            let sftp = sftp_arc.lock().await;
+            match sftp.read_dir(dir.as_str()).await {
+                Ok(entries) => entries,
+                // 根目录读取失败直接报错，子目录无权限时跳过
+                Err(e) if dir == root => return Err(e.to_string()),
+                Err(_) => continue,
+            }
+        };
+        for entry in entries {
+            if cancel.load(Ordering::Relaxed) || stream.full() {
+                return Ok(());
+            }
+            stream.scanned += 1;
+            let metadata = entry.metadata();
+            let name = entry.file_name();
+            let path = join_remote(&dir, &name);
+            let file_type = metadata.file_type();
+            let is_dir = file_type.is_dir();
+            if !is_dir && !file_type.is_file() {
+                continue;
+            }
+            if is_dir && filter.query.max_depth.is_none_or(|max| depth < max) {
+                stack.push((path.clone(), depth + 1));
+            }
+            let size = metadata.size.unwrap_or(0);
+            let mtime = metadata.mtime.unwrap_or(0) as u64;
+            if !filter.matches(&name, is_dir, size, mtime) {
+                continue;
+            }
+            let mut m = FindMatch { path, name, is_dir, size, mtime, line_number: None, line: None };
+            if let Some(re) = &filter.content {
+                let Some((line_number, line)) = grep_sftp(sftp_arc, &m.path, size, re).await else {
+                    continue;
+                };
+                m.line_number = Some(line_number);
+                m.line = Some(line);
+            }
+            stream.push(m);
+        }
+    }
+    Ok(())
+}
+
+/// 递归搜索远程目录；结果通过 `sftp:find-results` 分批推送，结束后返回汇总
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn sftp_find(
+    app: AppHandle,
+    host_map: tauri::State<'_, SessionHostMap>,
+    mgr: tauri::State<'_, SftpManager>,
+    searches: tauri::State<'_, SearchManager>,
+    session_id: String,
+    search_id: String,
+    root: String,
+    query: FindQuery,
+) -> Result<FindSummary, String> {
+    let filter = Filter::new(query)?;
+    let (sftp_arc, _) = sftp_get_host(&app, &host_map, &mgr, &session_id).await?;
+    let cancel = Arc::new(AtomicBool::new(false));
+    searches.0.write().await.insert(search_id.clone(), cancel.clone());
+
+    let mut stream = ResultStream {
+        app: &app,
+        search_id: &search_id,
+        pending: Vec::new(),
+        last_flush: Instant::now(),
+        matched: 0,
+        scanned: 0,
+        limit: filter.query.max_results.unwrap_or(DEFAULT_MAX_RESULTS),
+    };
+    let exec_result = if exec_available(&mgr, &session_id, filter.content.is_some()).await {
+        Some(find_exec(&mgr, &session_id, &root, &filter, &cancel, &mut stream).await)
+    } else {
+        None
+    };
+    // exec 不可用，或还没产生任何结果就失败时改用 SFTP 遍历
+    let (method, result) = match exec_result {
+        Some(Err(e)) if stream.matched == 0 => {
+            log::warn!("find over exec failed, falling back to SFTP walk: {}", e);
+            ("sftp", find_sftp(&sftp_arc, &root, &filter, &cancel, &mut stream).await)
+        }
+        Some(result) => ("exec", result),
+        None => ("sftp", find_sftp(&sftp_arc, &root, &filter, &cancel, &mut stream).await),
+    };
+    stream.flush();
+    searches.0.write().await.remove(&search_id);
+    result?;
+
+    Ok(FindSummary {
+        search_id: search_id.clone(),
+        method: method.to_string(),
+        matched: stream.matched,
+        scanned: stream.scanned,
+        truncated: stream.full(),
+        cancelled: cancel.load(Ordering::Relaxed),
+    })
+}
+
+#[tauri::command]
+pub async fn sftp_find_cancel(
+    searches: tauri::State<'_, SearchManager>,
+    search_id: String,
+) -> Result<bool, String> {
+    let Some(cancel) = searches.0.read().await.get(&search_id).cloned() else {
+        return Ok(false);
+    };
+    cancel.store(true, Ordering::Relaxed);
+    Ok(true)
+}
@@ -0,0 +1,86 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use tauri::Manager;
+use tauri_plugin_store::StoreExt;
+
+/// 后端可见的应用设置（保存在 settings.json）
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AppSettings {
+    /// 传输时默认保留权限与时间戳（类似 `scp -p`）
+    #[serde(default)]
+    pub preserve_attributes: bool,
+    /// 全局传输限速（字节/秒），0 为不限速
+    #[serde(default)]
+    pub bandwidth_limit: u64,
+    /// 每个会话同时进行的传输数量
+    #[serde(default = "default_max_concurrent")]
+    pub max_concurrent_transfers: usize,
+    /// 外部编辑器命令（如 `code --wait`），为空时使用系统默认程序
+    #[serde(default)]
+    pub external_editor: Option<String>,
+    /// 每个终端会话在后端保留的输出字节数，0 为不保留
+    #[serde(default = "default_scrollback_limit")]
+    pub scrollback_limit: usize,
+}
+
+fn default_max_concurrent() -> usize {
+    3
+}
+
+fn default_scrollback_limit() -> usize {
+    4 * 1024 * 1024
+}
+
+impl Default for AppSettings {
+    fn default() -> Self {
+        AppSettings {
+            preserve_attributes: false,
+            bandwidth_limit: 0,
+            max_concurrent_transfers: default_max_concurrent(),
+            external_editor: None,
+            scrollback_limit: default_scrollback_limit(),
+        }
+    }
+}
+
+/// 读取设置，缺失或损坏时返回默认值
+pub fn load(app: &tauri::AppHandle) -> AppSettings {
+    let store = match app.store(PathBuf::from("settings.json")) {
+        Ok(s) => s,
+        Err(_) => return AppSettings::default(),
+    };
+    store
+        .get("settings")
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default()
+}
+
+/// 把需要在运行时生效的设置同步到各管理器
+pub fn apply(app: &tauri::AppHandle, settings: &AppSettings) {
+    app.state::<crate::sftp::SftpManager>().global_limit.set_rate(settings.bandwidth_limit);
+    app.state::<crate::scrollback::ScrollbackManager>().set_limit(settings.scrollback_limit);
+    // 已打开会话的并发上限立即跟随新设置
+    let app = app.clone();
+    let max = settings.max_concurrent_transfers;
+    tauri::async_runtime::spawn(async move {
+        let mgr = app.state::<crate::sftp::SftpManager>();
+        for queue in mgr.slots.read().await.values() {
+            queue.set_default_max(max);
+        }
+    });
+}
+
+#[tauri::command]
+pub async fn get_app_settings(app: tauri::AppHandle) -> Result<AppSettings, String> {
+    Ok(load(&app))
+}
+
+#[tauri::command]
+pub async fn save_app_settings(app: tauri::AppHandle, settings: AppSettings) -> Result<(), String> {
+    let store = app.store(PathBuf::from("settings.json")).map_err(|e| e.to_string())?;
+    store.set("settings", serde_json::to_value(&settings).map_err(|e| e.to_string())?);
+    let _ = store.save();
+    apply(&app, &settings);
+    Ok(())
+}
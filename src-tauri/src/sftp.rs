@@ -1,14 +1,16 @@
 use russh::client;
-use russh_sftp::client::SftpSession;
-use std::collections::HashMap;
+use russh_sftp::client::{RawSftpSession, SftpSession};
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicU8, Ordering};
 use tokio::sync::{Mutex, RwLock};
 use tokio::io::AsyncWriteExt;
-use tauri::{AppHandle, Emitter};
+use tauri::{AppHandle, Emitter, Manager};
 use serde::{Deserialize, Serialize};
 use async_trait::async_trait;
+use crate::limits::{RateLimiter, SlotGuard, SlotQueue, SlotWait};
+use crate::queue::{QueuedTransfer, TransferDirection, TransferOptions, TransferQueue, TransferStatus};
 
 struct SftpClientHandler;
 
@@ -24,9 +26,9 @@ impl client::Handler for SftpClientHandler {
 }
 
 // 传输状态常量（使用 AtomicU8 无锁通信，彻底避免死锁）
-const TRANSFER_RUNNING: u8 = 0;
-const TRANSFER_PAUSED: u8 = 1;
-const TRANSFER_CANCELLED: u8 = 2;
+pub(crate) const TRANSFER_RUNNING: u8 = 0;
+pub(crate) const TRANSFER_PAUSED: u8 = 1;
+pub(crate) const TRANSFER_CANCELLED: u8 = 2;
 
 pub struct SftpManager {
     /// sessionId -> SftpSession
@@ -36,6 +38,22 @@ pub struct SftpManager {
     pub handles: RwLock<HashMap<String, client::Handle<SftpClientHandler>>>,
     /// transferId -> AtomicU8 状态标记
     pub transfers: RwLock<HashMap<String, Arc<AtomicU8>>>,
+    /// conflictId -> 等待前端答复的冲突询问
+    pub conflicts: RwLock<HashMap<String, tokio::sync::oneshot::Sender<ConflictAnswer>>>,
+    /// sessionId -> uid/gid 名称缓存
+    pub owner_names: RwLock<HashMap<String, Arc<OwnerNames>>>,
+    /// sessionId -> 服务器在 SSH_FXP_VERSION 中声明的扩展
+    pub extensions: RwLock<HashMap<String, Arc<HashMap<String, String>>>>,
+    /// 所有会话共享的全局限速
+    pub global_limit: Arc<RateLimiter>,
+    /// sessionId -> 会话级限速
+    pub session_limits: RwLock<HashMap<String, Arc<RateLimiter>>>,
+    /// sessionId -> 并发传输槽位
+    pub slots: RwLock<HashMap<String, Arc<SlotQueue>>>,
+    /// 服务器禁用了 SFTP 子系统的会话，上传/下载改用 SCP
+    pub scp_only: RwLock<HashSet<String>>,
+    /// sessionId -> sudo 模式下以 root 运行的原始 SFTP 会话，扩展请求（posix-rename、check-file 等）走这里
+    pub elevated_raw: RwLock<HashMap<String, Arc<RawSftpSession>>>,
 }
 
 impl SftpManager {
@@ -44,35 +62,125 @@ impl SftpManager {
             sessions: RwLock::new(HashMap::new()),
             handles: RwLock::new(HashMap::new()),
             transfers: RwLock::new(HashMap::new()),
+            conflicts: RwLock::new(HashMap::new()),
+            owner_names: RwLock::new(HashMap::new()),
+            extensions: RwLock::new(HashMap::new()),
+            global_limit: Arc::new(RateLimiter::new(0)),
+            session_limits: RwLock::new(HashMap::new()),
+            slots: RwLock::new(HashMap::new()),
+            scp_only: RwLock::new(HashSet::new()),
+            elevated_raw: RwLock::new(HashMap::new()),
         }
     }
 }
 
-#[derive(Serialize, Clone)]
-struct TransferProgressEvent {
-    #[serde(rename = "transferId")]
-    transfer_id: String,
-    #[serde(rename = "sessionId")]
-    session_id: String,
-    #[serde(rename = "remotePath")]
-    remote_path: String,
-    #[serde(rename = "bytesTransferred")]
-    bytes_transferred: u64,
-    #[serde(rename = "totalBytes")]
-    total_bytes: u64,
-    speed: u64,
-}
-
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct FileEntry {
     pub name: String,
     pub size: u64,
+    /// directory / file / symlink / socket / fifo / char / block
     #[serde(rename = "type")]
     pub entry_type: String,
     pub mode: u32,
     pub mtime: u64,
+    #[serde(default)]
+    pub atime: u64,
+    /// 符号链接指向的路径
+    #[serde(rename = "linkTarget", default, skip_serializing_if = "Option::is_none")]
+    pub link_target: Option<String>,
+    /// 符号链接的目标是否为目录（断链为 false）
+    #[serde(rename = "targetIsDir", default)]
+    pub target_is_dir: bool,
+    #[serde(default)]
+    pub uid: Option<u32>,
+    #[serde(default)]
+    pub gid: Option<u32>,
+    #[serde(default)]
+    pub owner: Option<String>,
+    #[serde(default)]
+    pub group: Option<String>,
+}
+
+/// 远程主机上 uid/gid 到名称的映射（读取 /etc/passwd 与 /etc/group）
+#[derive(Default)]
+pub struct OwnerNames {
+    users: HashMap<u32, String>,
+    groups: HashMap<u32, String>,
+}
+
+fn parse_id_file(data: &[u8]) -> HashMap<u32, String> {
+    String::from_utf8_lossy(data)
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split(':');
+            let name = fields.next()?;
+            let id = fields.nth(1)?.parse().ok()?;
+            Some((id, name.to_string()))
+        })
+        .collect()
+}
+
+/// 获取（并缓存）会话的属主名称映射；读不到时返回空映射
+async fn owner_names(mgr: &SftpManager, session_id: &str, sftp: &SftpSession) -> Arc<OwnerNames> {
+    if let Some(names) = mgr.owner_names.read().await.get(session_id) {
+        return names.clone();
+    }
+    let names = Arc::new(OwnerNames {
+        users: sftp.read("/etc/passwd").await.map(|d| parse_id_file(&d)).unwrap_or_default(),
+        groups: sftp.read("/etc/group").await.map(|d| parse_id_file(&d)).unwrap_or_default(),
+    });
+    mgr.owner_names.write().await.insert(session_id.to_string(), names.clone());
+    names
 }
 
+fn entry_type_name(permissions: Option<u32>) -> &'static str {
+    match permissions.unwrap_or(0) & 0o170000 {
+        0o040000 => "directory",
+        0o120000 => "symlink",
+        0o140000 => "socket",
+        0o010000 => "fifo",
+        0o020000 => "char",
+        0o060000 => "block",
+        _ => "file",
+    }
+}
+
+/// 由 lstat 得到的元数据构造 FileEntry；符号链接会额外解析目标
+async fn build_file_entry(
+    sftp: &SftpSession,
+    names: &OwnerNames,
+    path: &str,
+    name: String,
+    metadata: &russh_sftp::client::fs::Metadata,
+) -> FileEntry {
+    let entry_type = entry_type_name(metadata.permissions);
+    let (link_target, target_is_dir) = if entry_type == "symlink" {
+        let target = sftp.read_link(path).await.ok();
+        let is_dir = sftp.metadata(path).await.map(|m| m.file_type().is_dir()).unwrap_or(false);
+        (target, is_dir)
+    } else {
+        (None, entry_type == "directory")
+    };
+
+    FileEntry {
+        name,
+        size: metadata.size.unwrap_or(0),
+        entry_type: entry_type.to_string(),
+        mode: metadata.permissions.unwrap_or(0),
+        mtime: metadata.mtime.unwrap_or(0) as u64 * 1000,
+        atime: metadata.atime.unwrap_or(0) as u64 * 1000,
+        link_target,
+        target_is_dir,
+        uid: metadata.uid,
+        gid: metadata.gid,
+        owner: metadata.user.clone().or_else(|| metadata.uid.and_then(|id| names.users.get(&id).cloned())),
+        group: metadata.group.clone().or_else(|| metadata.gid.and_then(|id| names.groups.get(&id).cloned())),
+    }
+}
+
+/// 服务器没有 SFTP 子系统时 `get_or_create_sftp` 返回的错误，传输据此改用 SCP
+pub(crate) const SFTP_UNAVAILABLE: &str = "SFTP subsystem unavailable";
+
 /// Connect or reuse an SFTP session for the given sessionId (matches the SSH session)
 /// We keep a separate SSH handle for SFTP (because SFTP needs a separate channel)
 async fn get_or_create_sftp(
@@ -86,6 +194,10 @@ async fn get_or_create_sftp(
             return Ok(s.clone());
         }
     }
+    // 连接仍在，只是没有 SFTP，不要重新连接
+    if mgr.scp_only.read().await.contains(session_id) {
+        return Err(SFTP_UNAVAILABLE.to_string());
+    }
 
     // Create a new connection dedicated to SFTP
     let config = Arc::new(client::Config::default());
@@ -112,8 +224,20 @@ async fn get_or_create_sftp(
 
     // Open SFTP channel
     let channel = handle.channel_open_session().await.map_err(|e| e.to_string())?;
-    channel.request_subsystem(true, "sftp").await.map_err(|e| e.to_string())?;
-    let sftp = SftpSession::new(channel.into_stream()).await.map_err(|e| e.to_string())?;
+    let opened = match channel.request_subsystem(true, "sftp").await {
+        Ok(()) => SftpSession::new(channel.into_stream()).await.map_err(|e| e.to_string()),
+        Err(e) => Err(e.to_string()),
+    };
+    let sftp = match opened {
+        Ok(sftp) => sftp,
+        Err(e) => {
+            // 保留连接供 exec/SCP 使用
+            log::warn!("SFTP subsystem unavailable for {}: {}, falling back to SCP", session_id, e);
+            mgr.handles.write().await.insert(session_id.to_string(), handle);
+            mgr.scp_only.write().await.insert(session_id.to_string());
+            return Err(SFTP_UNAVAILABLE.to_string());
+        }
+    };
 
     let sftp_arc = Arc::new(Mutex::new(sftp));
 
@@ -123,6 +247,60 @@ async fn get_or_create_sftp(
     Ok(sftp_arc)
 }
 
+/// 在已有连接上重新打开以登录用户运行的 SFTP 子系统
+pub(crate) async fn open_subsystem_sftp(mgr: &SftpManager, session_id: &str) -> Result<SftpSession, String> {
+    let channel = {
+        let handles = mgr.handles.read().await;
+        let handle = handles.get(session_id).ok_or("Session not found")?;
+        handle.channel_open_session().await.map_err(|e| e.to_string())?
+    };
+    channel.request_subsystem(true, "sftp").await.map_err(|e| e.to_string())?;
+    SftpSession::new(channel.into_stream()).await.map_err(|e| e.to_string())
+}
+
+/// 打开一个独立的原始 SFTP 通道，用于 SftpSession 没有封装的扩展请求。
+/// sudo 模式下返回以 root 运行的会话，否则操作会以登录用户的权限执行
+pub(crate) async fn open_raw_sftp(
+    mgr: &SftpManager,
+    session_id: &str,
+) -> Result<Arc<RawSftpSession>, String> {
+    if let Some(raw) = mgr.elevated_raw.read().await.get(session_id) {
+        return Ok(raw.clone());
+    }
+    let (raw, _) = open_subsystem_raw(mgr, session_id).await?;
+    Ok(Arc::new(raw))
+}
+
+async fn open_subsystem_raw(
+    mgr: &SftpManager,
+    session_id: &str,
+) -> Result<(RawSftpSession, russh_sftp::protocol::Version), String> {
+    let channel = {
+        let handles = mgr.handles.read().await;
+        let handle = handles.get(session_id).ok_or("Session not found")?;
+        handle.channel_open_session().await.map_err(|e| e.to_string())?
+    };
+    channel.request_subsystem(true, "sftp").await.map_err(|e| e.to_string())?;
+    let raw = RawSftpSession::new(channel.into_stream());
+    let version = raw.init().await.map_err(|e| e.to_string())?;
+    Ok((raw, version))
+}
+
+/// 服务器支持的 SFTP 扩展（按会话缓存；sudo 模式下由 sudo.rs 写入 root 会话声明的扩展）
+pub(crate) async fn server_extensions(
+    mgr: &SftpManager,
+    session_id: &str,
+) -> Result<Arc<HashMap<String, String>>, String> {
+    if let Some(ext) = mgr.extensions.read().await.get(session_id) {
+        return Ok(ext.clone());
+    }
+    let (raw, version) = open_subsystem_raw(mgr, session_id).await?;
+    let _ = raw.close_session();
+    let ext = Arc::new(version.extensions);
+    mgr.extensions.write().await.insert(session_id.to_string(), ext.clone());
+    Ok(ext)
+}
+
 #[tauri::command]
 pub async fn sftp_realpath(
     app: AppHandle,
@@ -146,92 +324,988 @@ pub async fn sftp_list(
 ) -> Result<Vec<FileEntry>, String> {
     let (sftp_arc, _) = sftp_get_host(&app, &host_map, &mgr, &session_id).await?;
     let sftp = sftp_arc.lock().await;
+    let names = owner_names(&mgr, &session_id, &sftp).await;
 
     let entries = sftp.read_dir(&path).await.map_err(|e| e.to_string())?;
     let mut result = Vec::new();
     for entry in entries {
+        // readdir 返回的是 lstat 属性，符号链接不会被跟随
         let metadata = entry.metadata();
-        let is_dir = metadata.is_dir();
-        result.push(FileEntry {
-            name: entry.file_name(),
-            size: metadata.size.unwrap_or(0),
-            entry_type: if is_dir { "directory".to_string() } else { "file".to_string() },
-            mode: metadata.permissions.unwrap_or(0),
-            mtime: metadata.mtime.unwrap_or(0) as u64 * 1000,
-        });
+        let name = entry.file_name();
+        let entry_path = join_remote(&path, &name);
+        result.push(build_file_entry(&sftp, &names, &entry_path, name, &metadata).await);
     }
     Ok(result)
 }
 
-#[tauri::command]
-pub async fn sftp_upload(
+/// 目标文件已存在时的处理策略
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum ConflictPolicy {
+    /// 直接覆盖（默认，与旧行为一致）
+    #[default]
+    Overwrite,
+    /// 跳过已存在的文件
+    Skip,
+    /// 自动重命名为 `file (1).txt`
+    Rename,
+    /// 仅当源文件比目标文件新时覆盖
+    Newer,
+    /// 仅当大小不同时覆盖
+    SizeDiffers,
+    /// 通过 `sftp:transfer-conflict` 事件询问前端
+    Ask,
+}
+
+#[derive(Serialize, Clone)]
+struct TransferConflictEvent {
+    #[serde(rename = "conflictId")]
+    conflict_id: String,
+    #[serde(rename = "transferId")]
+    transfer_id: String,
+    #[serde(rename = "sessionId")]
+    session_id: String,
+    #[serde(rename = "sourcePath")]
+    source_path: String,
+    #[serde(rename = "destPath")]
+    dest_path: String,
+    #[serde(rename = "sourceSize")]
+    source_size: u64,
+    #[serde(rename = "sourceMtime")]
+    source_mtime: u64,
+    #[serde(rename = "destSize")]
+    dest_size: u64,
+    #[serde(rename = "destMtime")]
+    dest_mtime: u64,
+}
+
+/// 前端对冲突询问的答复
+#[derive(Debug)]
+pub struct ConflictAnswer {
+    policy: ConflictPolicy,
+    apply_to_all: bool,
+}
+
+/// 文件的大小与修改时间（秒）
+#[derive(Clone, Copy)]
+pub(crate) struct FileStamp {
+    pub(crate) size: u64,
+    pub(crate) mtime: u64,
+}
+
+/// 传输目标所在的一侧，用于查找自动重命名后的可用路径
+pub(crate) enum TransferSide<'a> {
+    Local,
+    Remote(&'a Arc<Mutex<SftpSession>>),
+    /// 没有 SFTP 的会话，通过 exec 运行 `stat` 查询
+    Shell(&'a SftpManager, &'a str),
+}
+
+/// 单个传输任务（可能包含多个文件）的共享上下文
+pub(crate) struct TransferCtx {
     app: AppHandle,
-    host_map: tauri::State<'_, SessionHostMap>,
-    mgr: tauri::State<'_, SftpManager>,
     session_id: String,
     transfer_id: String,
+    state: Arc<AtomicU8>,
+    pub(crate) policy: ConflictPolicy,
+    /// 传输后复制权限与 atime/mtime
+    pub(crate) preserve: bool,
+    /// 传输后比较两端哈希
+    pub(crate) verify: bool,
+    verdicts: Vec<crate::checksum::ChecksumVerdict>,
+    /// 依次生效的限速器（全局、会话）
+    limiters: Vec<Arc<RateLimiter>>,
+    pub(crate) total: u64,
+    pub(crate) done: u64,
+    start: std::time::Instant,
+}
+
+impl TransferCtx {
+    fn new(
+        app: &AppHandle,
+        session_id: &str,
+        transfer_id: &str,
+        state: Arc<AtomicU8>,
+        policy: ConflictPolicy,
+    ) -> Self {
+        TransferCtx {
+            app: app.clone(),
+            session_id: session_id.to_string(),
+            transfer_id: transfer_id.to_string(),
+            state,
+            policy,
+            preserve: false,
+            verify: false,
+            verdicts: Vec::new(),
+            limiters: Vec::new(),
+            total: 0,
+            done: 0,
+            start: std::time::Instant::now(),
+        }
+    }
+
+    /// 检查取消/暂停；暂停时在此等待
+    pub(crate) async fn checkpoint(&self) -> Result<(), String> {
+        loop {
+            match self.state.load(Ordering::Relaxed) {
+                TRANSFER_CANCELLED => return Err("Cancelled".to_string()),
+                TRANSFER_PAUSED => tokio::time::sleep(std::time::Duration::from_millis(200)).await,
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    /// 按限速器消耗 `bytes` 个令牌，等待期间仍响应暂停/取消
+    pub(crate) async fn throttle(&self, bytes: u64) -> Result<(), String> {
+        for limiter in &self.limiters {
+            while let Some(wait) = limiter.reserve(bytes) {
+                tokio::time::sleep(wait.min(std::time::Duration::from_millis(200))).await;
+                self.checkpoint().await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 校验一个已传输完成的文件，结果记录在上下文中
+    pub(crate) async fn verify_file(&mut self, mgr: &SftpManager, remote_path: &str, local_path: &str) {
+        let verdict = crate::checksum::verify(mgr, &self.session_id, remote_path, local_path)
+            .await
+            .unwrap_or_else(|e| crate::checksum::ChecksumVerdict::unavailable(remote_path, e));
+        self.verdicts.push(verdict);
+    }
+
+    /// 把最终结果写回传输队列；校验不一致时把结果转成错误
+    fn finish(self, result: Result<(), String>) -> Result<bool, String> {
+        let result = result.and_then(|_| {
+            match self.verdicts.iter().find(|v| v.error.is_none() && !v.matched) {
+                Some(v) => Err(format!("Checksum mismatch: {}", v.path)),
+                None => Ok(()),
+            }
+        });
+        if let Some(queue) = self.app.try_state::<TransferQueue>() {
+            let error = result.as_ref().err().cloned();
+            let verdicts = self.verdicts;
+            queue.update(&self.app, &self.transfer_id, |item| {
+                item.status = match error.as_deref() {
+                    None => TransferStatus::Completed,
+                    Some("Cancelled") => TransferStatus::Cancelled,
+                    Some(_) => TransferStatus::Failed,
+                };
+                item.speed = 0;
+                item.error = error;
+                item.verification = verdicts;
+            });
+            if let Some(item) = queue.get(&self.transfer_id) {
+                crate::history::record(&self.app, &item, self.start.elapsed());
+            }
+        }
+        result.map(|_| true)
+    }
+
+    pub(crate) fn advance(&mut self, bytes: u64, remote_path: &str) {
+        self.done += bytes;
+        let elapsed = self.start.elapsed().as_secs().max(1);
+        let (done, total, speed) = (self.done, self.total, self.done / elapsed);
+        if let Some(queue) = self.app.try_state::<TransferQueue>() {
+            queue.update(&self.app, &self.transfer_id, |item| {
+                item.bytes_transferred = done;
+                item.total_bytes = total;
+                item.speed = speed;
+                item.current_path = Some(remote_path.to_string());
+            });
+        }
+    }
+}
+
+pub(crate) fn system_time_secs(t: std::io::Result<std::time::SystemTime>) -> u64 {
+    t.ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+pub(crate) async fn local_stamp(path: &str) -> Option<FileStamp> {
+    let meta = tokio::fs::metadata(path).await.ok()?;
+    Some(FileStamp { size: meta.len(), mtime: system_time_secs(meta.modified()) })
+}
+
+async fn remote_stamp(sftp_arc: &Arc<Mutex<SftpSession>>, path: &str) -> Option<FileStamp> {
+    let sftp = sftp_arc.lock().await;
+    let meta = sftp.metadata(path).await.ok()?;
+    Some(FileStamp { size: meta.size.unwrap_or(0), mtime: meta.mtime.unwrap_or(0) as u64 })
+}
+
+/// 通过 exec 查询文件是否存在及其大小和修改时间。先用 `test` 判断是否存在，
+/// 再依次尝试 GNU 与 BSD 的 stat；存在但取不到属性时大小和时间记为 0。
+/// 无法确定是否存在（命令失败或输出无法识别）时返回错误，不能当作不存在去覆盖
+async fn shell_stamp(mgr: &SftpManager, session_id: &str, path: &str) -> Result<Option<FileStamp>, String> {
+    let quoted = shell_quote(path);
+    let command = format!(
+        "if [ -e {p} ] || [ -L {p} ]; then stat -c '%s %Y' -- {p} 2>/dev/null || stat -f '%z %m' -- {p} 2>/dev/null || echo exists; else echo missing; fi",
+        p = quoted
+    );
+    let (code, stdout) = exec_on_session(mgr, session_id, &command).await?;
+    let text = String::from_utf8_lossy(&stdout);
+    let mut parts = text.split_whitespace();
+    match (code, parts.next(), parts.next()) {
+        (0, Some("missing"), None) => Ok(None),
+        (0, Some("exists"), None) => Ok(Some(FileStamp { size: 0, mtime: 0 })),
+        (0, Some(size), Some(mtime)) => match (size.parse(), mtime.parse()) {
+            (Ok(size), Ok(mtime)) => Ok(Some(FileStamp { size, mtime })),
+            _ => Err(format!("Cannot check whether {} exists: unexpected output {:?}", path, text.trim())),
+        },
+        _ => Err(format!("Cannot check whether {} exists (exit code {})", path, code)),
+    }
+}
+
+pub(crate) fn join_remote(dir: &str, name: &str) -> String {
+    if dir.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", dir.trim_end_matches('/'), name)
+    }
+}
+
+/// `report.txt` -> `report (n).txt`，隐藏文件与无扩展名文件整体加后缀
+fn numbered_name(name: &str, n: u32) -> String {
+    match name.rfind('.') {
+        Some(pos) if pos > 0 => format!("{} ({}){}", &name[..pos], n, &name[pos..]),
+        _ => format!("{} ({})", name, n),
+    }
+}
+
+/// 把本地文件的权限与时间戳写到远程文件（SFTP setstat）
+async fn copy_attrs_to_remote(
+    sftp_arc: &Arc<Mutex<SftpSession>>,
+    local_path: &str,
+    remote_path: &str,
+) -> Result<(), String> {
+    let meta = tokio::fs::metadata(local_path).await.map_err(|e| e.to_string())?;
+    let mut attrs = russh_sftp::protocol::FileAttributes::empty();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        attrs.permissions = Some(meta.permissions().mode() & 0o7777);
+    }
+    attrs.atime = Some(system_time_secs(meta.accessed()) as u32);
+    attrs.mtime = Some(system_time_secs(meta.modified()) as u32);
+
+    let sftp = sftp_arc.lock().await;
+    sftp.set_metadata(remote_path, attrs).await.map_err(|e| e.to_string())
+}
+
+/// 把远程文件的权限与时间戳应用到本地文件
+async fn copy_attrs_to_local(
+    sftp_arc: &Arc<Mutex<SftpSession>>,
+    remote_path: &str,
+    local_path: &str,
+) -> Result<(), String> {
+    let meta = {
+        let sftp = sftp_arc.lock().await;
+        sftp.metadata(remote_path).await.map_err(|e| e.to_string())?
+    };
+    #[cfg(unix)]
+    if let Some(mode) = meta.permissions {
+        use std::os::unix::fs::PermissionsExt;
+        tokio::fs::set_permissions(local_path, std::fs::Permissions::from_mode(mode & 0o7777))
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    if let Some(mtime) = meta.mtime {
+        let mtime = filetime::FileTime::from_unix_time(mtime as i64, 0);
+        let atime = meta.atime.map(|t| filetime::FileTime::from_unix_time(t as i64, 0)).unwrap_or(mtime);
+        filetime::set_file_times(local_path, atime, mtime).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// 为自动重命名策略找到一个尚不存在的路径
+pub(crate) async fn unique_path(side: &TransferSide<'_>, path: &str) -> Result<String, String> {
+    let (parent, name) = match path.rfind(['/', '\\']) {
+        Some(pos) => (&path[..=pos], &path[pos + 1..]),
+        None => ("", path),
+    };
+    for n in 1..10000 {
+        let candidate = format!("{}{}", parent, numbered_name(name, n));
+        let exists = match side {
+            TransferSide::Local => tokio::fs::try_exists(&candidate).await.unwrap_or(false),
+            TransferSide::Remote(sftp_arc) => {
+                let sftp = sftp_arc.lock().await;
+                sftp.try_exists(candidate.as_str()).await.map_err(|e| e.to_string())?
+            }
+            TransferSide::Shell(mgr, session_id) => shell_stamp(mgr, session_id, &candidate).await?.is_some(),
+        };
+        if !exists {
+            return Ok(candidate);
+        }
+    }
+    Err(format!("No free name for {}", path))
+}
+
+/// 向前端询问冲突处理方式，等待 `sftp_resolve_conflict` 回复
+async fn ask_conflict(
+    mgr: &SftpManager,
+    ctx: &mut TransferCtx,
+    source_path: &str,
+    dest_path: &str,
+    source: FileStamp,
+    dest: FileStamp,
+) -> Result<ConflictPolicy, String> {
+    let conflict_id = uuid::Uuid::new_v4().to_string();
+    let (tx, mut rx) = tokio::sync::oneshot::channel();
+    mgr.conflicts.write().await.insert(conflict_id.clone(), tx);
+
+    let _ = ctx.app.emit("sftp:transfer-conflict", TransferConflictEvent {
+        conflict_id: conflict_id.clone(),
+        transfer_id: ctx.transfer_id.clone(),
+        session_id: ctx.session_id.clone(),
+        source_path: source_path.to_string(),
+        dest_path: dest_path.to_string(),
+        source_size: source.size,
+        source_mtime: source.mtime * 1000,
+        dest_size: dest.size,
+        dest_mtime: dest.mtime * 1000,
+    });
+
+    let answer = loop {
+        if ctx.state.load(Ordering::Relaxed) == TRANSFER_CANCELLED {
+            mgr.conflicts.write().await.remove(&conflict_id);
+            return Err("Cancelled".to_string());
+        }
+        match tokio::time::timeout(std::time::Duration::from_millis(200), &mut rx).await {
+            Ok(Ok(answer)) => break answer,
+            Ok(Err(_)) => return Err("Conflict prompt dropped".to_string()),
+            Err(_) => continue,
+        }
+    };
+
+    // 询问的结果不能再是询问，否则会死循环
+    let policy = if answer.policy == ConflictPolicy::Ask { ConflictPolicy::Skip } else { answer.policy };
+    if answer.apply_to_all {
+        ctx.policy = policy;
+    }
+    Ok(policy)
+}
+
+/// 按冲突策略决定最终写入路径；返回 None 表示跳过该文件
+pub(crate) async fn resolve_dest(
+    mgr: &SftpManager,
+    ctx: &mut TransferCtx,
+    side: TransferSide<'_>,
+    source_path: &str,
+    dest_path: String,
+    source: FileStamp,
+) -> Result<Option<String>, String> {
+    let existing = match &side {
+        TransferSide::Local => local_stamp(&dest_path).await,
+        TransferSide::Remote(sftp_arc) => remote_stamp(sftp_arc, &dest_path).await,
+        TransferSide::Shell(mgr, session_id) => shell_stamp(mgr, session_id, &dest_path).await?,
+    };
+    let Some(dest) = existing else {
+        return Ok(Some(dest_path));
+    };
+
+    let mut policy = ctx.policy;
+    if policy == ConflictPolicy::Ask {
+        policy = ask_conflict(mgr, ctx, source_path, &dest_path, source, dest).await?;
+    }
+
+    match policy {
+        ConflictPolicy::Overwrite | ConflictPolicy::Ask => Ok(Some(dest_path)),
+        ConflictPolicy::Skip => Ok(None),
+        ConflictPolicy::Rename => unique_path(&side, &dest_path).await.map(Some),
+        ConflictPolicy::Newer => Ok((source.mtime > dest.mtime).then_some(dest_path)),
+        ConflictPolicy::SizeDiffers => Ok((source.size != dest.size).then_some(dest_path)),
+    }
+}
+
+/// 递归收集本地目录：返回 (本地路径, 远程路径, 是否目录, 大小)
+pub(crate) async fn collect_local_tree(local_root: &str, remote_root: &str) -> Result<Vec<(String, String, bool, u64)>, String> {
+    let mut result = Vec::new();
+    let mut stack = vec![(local_root.to_string(), remote_root.to_string())];
+    while let Some((local_dir, remote_dir)) = stack.pop() {
+        result.push((local_dir.clone(), remote_dir.clone(), true, 0));
+        let mut rd = tokio::fs::read_dir(&local_dir).await.map_err(|e| e.to_string())?;
+        while let Some(entry) = rd.next_entry().await.map_err(|e| e.to_string())? {
+            let meta = entry.metadata().await.map_err(|e| e.to_string())?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            let local = entry.path().to_string_lossy().to_string();
+            let remote = join_remote(&remote_dir, &name);
+            if meta.is_dir() {
+                stack.push((local, remote));
+            } else {
+                result.push((local, remote, false, meta.len()));
+            }
+        }
+    }
+    Ok(result)
+}
+
+pub(crate) fn join_local(dir: &str, name: &str) -> String {
+    Path::new(dir).join(name).to_string_lossy().to_string()
+}
+
+/// 递归收集远程目录：返回 (远程路径, 目标路径, 是否目录, 大小)；目标可以是本地或另一会话中的路径
+async fn collect_remote_tree(
+    sftp_arc: &Arc<Mutex<SftpSession>>,
+    remote_root: &str,
+    dest_root: &str,
+    join_dest: fn(&str, &str) -> String,
+) -> Result<Vec<(String, String, bool, u64)>, String> {
+    let mut result = Vec::new();
+    let mut stack = vec![(remote_root.to_string(), dest_root.to_string())];
+    while let Some((remote_dir, dest_dir)) = stack.pop() {
+        result.push((remote_dir.clone(), dest_dir.clone(), true, 0));
+        let entries = {
+            let sftp = sftp_arc.lock().await;
+            sftp.read_dir(remote_dir.as_str()).await.map_err(|e| e.to_string())?
+        };
+        for entry in entries {
+            let name = entry.file_name();
+            let metadata = entry.metadata();
+            let remote = join_remote(&remote_dir, &name);
+            let dest = join_dest(&dest_dir, &name);
+            if metadata.file_type().is_dir() {
+                stack.push((remote, dest));
+            } else {
+                result.push((remote, dest, false, metadata.size.unwrap_or(0)));
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// 上传单个文件（流式读取，带冲突处理）
+pub(crate) async fn upload_file(
+    mgr: &SftpManager,
+    sftp_arc: &Arc<Mutex<SftpSession>>,
+    ctx: &mut TransferCtx,
+    local_path: &str,
+    dest: String,
+) -> Result<(), String> {
+    use tokio::io::AsyncReadExt;
+
+    let source = local_stamp(local_path).await.ok_or_else(|| format!("Cannot read {}", local_path))?;
+    let Some(dest) = resolve_dest(mgr, ctx, TransferSide::Remote(sftp_arc), local_path, dest, source).await? else {
+        ctx.advance(source.size, local_path);
+        return Ok(());
+    };
+
+    let mut local_file = tokio::fs::File::open(local_path).await.map_err(|e| e.to_string())?;
+    let mut remote_file = {
+        let sftp = sftp_arc.lock().await;
+        sftp.create(dest.as_str()).await.map_err(|e| e.to_string())?
+    };
+
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        ctx.checkpoint().await?;
+        let n = local_file.read(&mut buf).await.map_err(|e| e.to_string())?;
+        if n == 0 {
+            break;
+        }
+        ctx.throttle(n as u64).await?;
+        remote_file.write_all(&buf[..n]).await.map_err(|e| e.to_string())?;
+        ctx.advance(n as u64, &dest);
+    }
+    remote_file.shutdown().await.map_err(|e| e.to_string())?;
+
+    if ctx.preserve {
+        copy_attrs_to_remote(sftp_arc, local_path, &dest).await?;
+    }
+    if ctx.verify {
+        ctx.verify_file(mgr, &dest, local_path).await;
+    }
+    Ok(())
+}
+
+/// 下载单个文件（流式写入，带冲突处理；失败或取消时删除不完整的本地文件）
+pub(crate) async fn download_file(
+    mgr: &SftpManager,
+    sftp_arc: &Arc<Mutex<SftpSession>>,
+    ctx: &mut TransferCtx,
+    remote_path: &str,
     local_path: String,
-    remote_path: String,
-) -> Result<bool, String> {
-    let (sftp_arc, _) = sftp_get_host(&app, &host_map, &mgr, &session_id).await?;
+) -> Result<(), String> {
+    use tokio::io::AsyncReadExt;
 
-    // AtomicU8 无锁状态标记
-    let state = Arc::new(AtomicU8::new(TRANSFER_RUNNING));
-    mgr.transfers.write().await.insert(transfer_id.clone(), state.clone());
+    let source = remote_stamp(sftp_arc, remote_path).await.unwrap_or(FileStamp { size: 0, mtime: 0 });
+    let Some(local_path) = resolve_dest(mgr, ctx, TransferSide::Local, remote_path, local_path, source).await? else {
+        ctx.advance(source.size, remote_path);
+        return Ok(());
+    };
+
+    // 极短时间持有 sftp 锁：仅打开文件句柄
+    let mut remote_file = {
+        let sftp = sftp_arc.lock().await;
+        sftp.open(remote_path).await.map_err(|e| e.to_string())?
+    };
+
+    let mut file = tokio::fs::File::create(&local_path).await.map_err(|e| e.to_string())?;
+    let mut buf = vec![0u8; 128 * 1024];
+
+    let result: Result<(), String> = async {
+        loop {
+            ctx.checkpoint().await?;
+            // 流式分块读取
+            let n = remote_file.read(&mut buf).await.map_err(|e| e.to_string())?;
+            if n == 0 {
+                break;
+            }
+            ctx.throttle(n as u64).await?;
+            file.write_all(&buf[..n]).await.map_err(|e| e.to_string())?;
+            ctx.advance(n as u64, remote_path);
+        }
+        file.flush().await.map_err(|e| e.to_string())
+    }
+    .await;
+
+    if result.is_err() {
+        drop(remote_file); // 先释放文件句柄
+        drop(file);
+        let _ = tokio::fs::remove_file(&local_path).await;
+        return result;
+    }
+
+    drop(file);
+    if ctx.preserve {
+        copy_attrs_to_local(sftp_arc, remote_path, &local_path).await?;
+    }
+    if ctx.verify {
+        ctx.verify_file(mgr, remote_path, &local_path).await;
+    }
+    Ok(())
+}
 
-    let file_content = tokio::fs::read(&local_path).await.map_err(|e| e.to_string())?;
-    let total = file_content.len() as u64;
-    
-    let filename = Path::new(&local_path).file_name()
+async fn run_upload(
+    mgr: &SftpManager,
+    sftp_arc: &Arc<Mutex<SftpSession>>,
+    ctx: &mut TransferCtx,
+    local_path: &str,
+    remote_path: &str,
+) -> Result<(), String> {
+    let meta = tokio::fs::metadata(local_path).await.map_err(|e| e.to_string())?;
+    let filename = Path::new(local_path).file_name()
         .and_then(|n| n.to_str())
         .unwrap_or("upload");
     let dest = if remote_path.ends_with('/') {
         format!("{}{}", remote_path, filename)
     } else {
-        remote_path.clone()
+        remote_path.to_string()
     };
 
-    let mut remote_file = {
+    if !meta.is_dir() {
+        ctx.total = meta.len();
+        return upload_file(mgr, sftp_arc, ctx, local_path, dest).await;
+    }
+
+    let tree = collect_local_tree(local_path, &dest).await?;
+    ctx.total = tree.iter().map(|e| e.3).sum();
+    for (local, remote, is_dir, _) in &tree {
+        ctx.checkpoint().await?;
+        if *is_dir {
+            let sftp = sftp_arc.lock().await;
+            if !sftp.try_exists(remote.as_str()).await.map_err(|e| e.to_string())? {
+                sftp.create_dir(remote.as_str()).await.map_err(|e| e.to_string())?;
+            }
+        } else {
+            upload_file(mgr, sftp_arc, ctx, local, remote.clone()).await?;
+        }
+    }
+
+    // 目录时间戳最后设置（由深到浅），避免被写入子项时刷新
+    if ctx.preserve {
+        for (local, remote, _, _) in tree.iter().rev().filter(|e| e.2) {
+            copy_attrs_to_remote(sftp_arc, local, remote).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn run_download(
+    mgr: &SftpManager,
+    sftp_arc: &Arc<Mutex<SftpSession>>,
+    ctx: &mut TransferCtx,
+    remote_path: &str,
+    local_path: &str,
+) -> Result<(), String> {
+    let is_dir = {
         let sftp = sftp_arc.lock().await;
-        sftp.create(&dest).await.map_err(|e| e.to_string())?
+        sftp.metadata(remote_path).await.map(|m| m.is_dir()).unwrap_or(false)
+    };
+
+    if !is_dir {
+        ctx.total = remote_stamp(sftp_arc, remote_path).await.map(|s| s.size).unwrap_or(0);
+        return download_file(mgr, sftp_arc, ctx, remote_path, local_path.to_string()).await;
+    }
+
+    let tree = collect_remote_tree(sftp_arc, remote_path, local_path, join_local).await?;
+    ctx.total = tree.iter().map(|e| e.3).sum();
+    for (remote, local, is_dir, _) in &tree {
+        ctx.checkpoint().await?;
+        if *is_dir {
+            tokio::fs::create_dir_all(local).await.map_err(|e| e.to_string())?;
+        } else {
+            download_file(mgr, sftp_arc, ctx, remote, local.clone()).await?;
+        }
+    }
+
+    // 目录时间戳最后设置（由深到浅），避免被写入子项时刷新
+    if ctx.preserve {
+        for (remote, local, _, _) in tree.iter().rev().filter(|e| e.2) {
+            copy_attrs_to_local(sftp_arc, remote, local).await?;
+        }
+    }
+    Ok(())
+}
+
+/// 把一个会话中文件的权限与时间戳复制到另一个会话中的文件
+async fn copy_attrs_between(
+    source: &Arc<Mutex<SftpSession>>,
+    source_path: &str,
+    target: &Arc<Mutex<SftpSession>>,
+    target_path: &str,
+) -> Result<(), String> {
+    let meta = {
+        let sftp = source.lock().await;
+        sftp.metadata(source_path).await.map_err(|e| e.to_string())?
+    };
+    let mut attrs = russh_sftp::protocol::FileAttributes::empty();
+    attrs.permissions = meta.permissions.map(|mode| mode & 0o7777);
+    attrs.atime = meta.atime;
+    attrs.mtime = meta.mtime;
+    let sftp = target.lock().await;
+    sftp.set_metadata(target_path, attrs).await.map_err(|e| e.to_string())
+}
+
+/// 在两个会话之间复制单个文件，数据只经过内存缓冲区（失败或取消时删除不完整的目标文件）
+async fn relay_file(
+    mgr: &SftpManager,
+    source: &Arc<Mutex<SftpSession>>,
+    target: &Arc<Mutex<SftpSession>>,
+    ctx: &mut TransferCtx,
+    target_session: &str,
+    source_path: &str,
+    dest: String,
+) -> Result<(), String> {
+    use tokio::io::AsyncReadExt;
+
+    let stamp = remote_stamp(source, source_path).await.ok_or_else(|| format!("Cannot read {}", source_path))?;
+    let Some(dest) = resolve_dest(mgr, ctx, TransferSide::Remote(target), source_path, dest, stamp).await? else {
+        ctx.advance(stamp.size, source_path);
+        return Ok(());
+    };
+
+    // 两个会话各自只在打开句柄时短暂持锁
+    let mut reader = {
+        let sftp = source.lock().await;
+        sftp.open(source_path).await.map_err(|e| e.to_string())?
     };
-    
-    let chunk_size: usize = 64 * 1024;
-    let mut offset: usize = 0;
-    let start = std::time::Instant::now();
+    let mut writer = {
+        let sftp = target.lock().await;
+        sftp.create(dest.as_str()).await.map_err(|e| e.to_string())?
+    };
+
+    let mut buf = vec![0u8; 128 * 1024];
+    let result: Result<(), String> = async {
+        loop {
+            ctx.checkpoint().await?;
+            let n = reader.read(&mut buf).await.map_err(|e| e.to_string())?;
+            if n == 0 {
+                break;
+            }
+            ctx.throttle(n as u64).await?;
+            writer.write_all(&buf[..n]).await.map_err(|e| e.to_string())?;
+            ctx.advance(n as u64, source_path);
+        }
+        writer.shutdown().await.map_err(|e| e.to_string())
+    }
+    .await;
+
+    if result.is_err() {
+        drop(writer);
+        let sftp = target.lock().await;
+        let _ = sftp.remove_file(dest.as_str()).await;
+        return result;
+    }
+
+    if ctx.preserve {
+        copy_attrs_between(source, source_path, target, &dest).await?;
+    }
+    if ctx.verify {
+        let verdict = crate::checksum::verify_relay(mgr, &ctx.session_id, source_path, target_session, &dest)
+            .await
+            .unwrap_or_else(|e| crate::checksum::ChecksumVerdict::unavailable(&dest, e));
+        ctx.verdicts.push(verdict);
+    }
+    Ok(())
+}
+
+/// 会话间复制文件或目录；targetPath 以 `/` 结尾时复制到其下的同名项
+async fn run_relay(
+    mgr: &SftpManager,
+    source: &Arc<Mutex<SftpSession>>,
+    target: &Arc<Mutex<SftpSession>>,
+    ctx: &mut TransferCtx,
+    target_session: &str,
+    source_path: &str,
+    target_path: &str,
+) -> Result<(), String> {
+    let is_dir = {
+        let sftp = source.lock().await;
+        sftp.metadata(source_path).await.map_err(|e| e.to_string())?.file_type().is_dir()
+    };
+    let dest = if target_path.ends_with('/') {
+        let name = source_path.trim_end_matches('/').rsplit('/').next().unwrap_or("copy");
+        format!("{}{}", target_path, name)
+    } else {
+        target_path.to_string()
+    };
+
+    if !is_dir {
+        ctx.total = remote_stamp(source, source_path).await.map(|s| s.size).unwrap_or(0);
+        return relay_file(mgr, source, target, ctx, target_session, source_path, dest).await;
+    }
+
+    let tree = collect_remote_tree(source, source_path, &dest, join_remote).await?;
+    ctx.total = tree.iter().map(|e| e.3).sum();
+    for (src, dst, is_dir, _) in &tree {
+        ctx.checkpoint().await?;
+        if *is_dir {
+            let sftp = target.lock().await;
+            if !sftp.try_exists(dst.as_str()).await.map_err(|e| e.to_string())? {
+                sftp.create_dir(dst.as_str()).await.map_err(|e| e.to_string())?;
+            }
+        } else {
+            relay_file(mgr, source, target, ctx, target_session, src, dst.clone()).await?;
+        }
+    }
+
+    if ctx.preserve {
+        for (src, dst, _, _) in tree.iter().rev().filter(|e| e.2) {
+            copy_attrs_between(source, src, target, dst).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn session_limiter(mgr: &SftpManager, session_id: &str) -> Arc<RateLimiter> {
+    mgr.session_limits
+        .write()
+        .await
+        .entry(session_id.to_string())
+        .or_insert_with(|| Arc::new(RateLimiter::new(0)))
+        .clone()
+}
 
-    while offset < file_content.len() {
-        let s = state.load(Ordering::Relaxed);
-        if s == TRANSFER_CANCELLED {
-            mgr.transfers.write().await.remove(&transfer_id);
+async fn session_slots(mgr: &SftpManager, session_id: &str, default_max: usize) -> Arc<SlotQueue> {
+    mgr.slots
+        .write()
+        .await
+        .entry(session_id.to_string())
+        .or_insert_with(|| SlotQueue::new(default_max))
+        .clone()
+}
+
+/// 等待会话的并发槽位；排队期间可被取消
+async fn wait_for_slot(
+    app: &AppHandle,
+    mgr: &SftpManager,
+    session_id: &str,
+    transfer_id: &str,
+    priority: i32,
+    state: &AtomicU8,
+) -> Result<SlotGuard, String> {
+    let default_max = crate::settings::load(app).max_concurrent_transfers;
+    let queue = session_slots(mgr, session_id, default_max).await;
+    let mut rx = match queue.enter(transfer_id, priority) {
+        SlotWait::Ready(guard) => return Ok(guard),
+        SlotWait::Pending(rx) => rx,
+    };
+    loop {
+        if state.load(Ordering::Relaxed) == TRANSFER_CANCELLED {
+            queue.leave(transfer_id, rx);
             return Err("Cancelled".to_string());
         }
-        if s == TRANSFER_PAUSED {
-            tokio::time::sleep(std::time::Duration::from_millis(200)).await;
-            continue;
+        match tokio::time::timeout(std::time::Duration::from_millis(200), &mut rx).await {
+            Ok(Ok(())) => return Ok(queue.guard()),
+            Ok(Err(_)) => return Err("Transfer queue closed".to_string()),
+            Err(_) => continue,
         }
+    }
+}
 
-        let end = (offset + chunk_size).min(file_content.len());
-        remote_file.write_all(&file_content[offset..end]).await.map_err(|e| e.to_string())?;
-        offset = end;
+/// 一次上传或下载的参数，供直接调用和后端队列共用
+pub(crate) struct TransferRequest {
+    pub session_id: String,
+    pub transfer_id: String,
+    pub direction: TransferDirection,
+    pub local_path: String,
+    pub remote_path: String,
+    /// Relay 传输的目标会话与其中的路径
+    pub target_session_id: Option<String>,
+    pub target_path: Option<String>,
+    pub options: TransferOptions,
+    pub priority: i32,
+}
 
-        let elapsed = start.elapsed().as_secs().max(1);
-        let speed = offset as u64 / elapsed;
-        let _ = app.emit("sftp:upload-progress", TransferProgressEvent {
-            transfer_id: transfer_id.clone(),
-            session_id: session_id.clone(),
-            remote_path: dest.clone(),
-            bytes_transferred: offset as u64,
-            total_bytes: total,
-            speed,
-        });
+/// 登记传输状态并创建上下文；结束时必须交给 `end_transfer`
+pub(crate) async fn begin_transfer(
+    app: &AppHandle,
+    mgr: &SftpManager,
+    session_id: &str,
+    transfer_id: &str,
+    options: &TransferOptions,
+) -> TransferCtx {
+    // AtomicU8 无锁状态标记
+    let state = Arc::new(AtomicU8::new(TRANSFER_RUNNING));
+    mgr.transfers.write().await.insert(transfer_id.to_string(), state.clone());
+
+    let mut ctx = TransferCtx::new(
+        app, session_id, transfer_id, state,
+        options.conflict_policy.unwrap_or_default(),
+    );
+    ctx.preserve = options.preserve.unwrap_or_else(|| crate::settings::load(app).preserve_attributes);
+    ctx.verify = options.verify.unwrap_or(false);
+    ctx.limiters = vec![mgr.global_limit.clone(), session_limiter(mgr, session_id).await];
+    ctx
+}
+
+/// 等待并发槽位，拿到后把队列条目标记为运行中
+pub(crate) async fn acquire_slot(
+    app: &AppHandle,
+    mgr: &SftpManager,
+    ctx: &mut TransferCtx,
+    priority: i32,
+) -> Result<SlotGuard, String> {
+    let slot = wait_for_slot(app, mgr, &ctx.session_id, &ctx.transfer_id, priority, &ctx.state).await?;
+    // 耗时与速度从真正开始传输时计算，不含排队时间
+    ctx.start = std::time::Instant::now();
+    if let Some(queue) = app.try_state::<TransferQueue>() {
+        let status = match ctx.state.load(Ordering::Relaxed) {
+            TRANSFER_PAUSED => TransferStatus::Paused,
+            _ => TransferStatus::Running,
+        };
+        queue.set_status(app, &ctx.transfer_id, status);
     }
+    Ok(slot)
+}
 
-    mgr.transfers.write().await.remove(&transfer_id);
-    Ok(true)
+/// 注销传输状态并写回最终结果
+pub(crate) async fn end_transfer(
+    mgr: &SftpManager,
+    ctx: TransferCtx,
+    result: Result<(), String>,
+) -> Result<bool, String> {
+    mgr.transfers.write().await.remove(&ctx.transfer_id);
+    ctx.finish(result)
+}
+
+/// 执行一条已登记在队列中的传输，状态变化通过 `sftp:transfer-status` 广播
+pub(crate) async fn execute_transfer(
+    app: &AppHandle,
+    host_map: &SessionHostMap,
+    mgr: &SftpManager,
+    req: TransferRequest,
+) -> Result<bool, String> {
+    let mut ctx = begin_transfer(app, mgr, &req.session_id, &req.transfer_id, &req.options).await;
+    let result = async {
+        // 服务器没有 SFTP 子系统时上传/下载改用 SCP
+        let sftp_arc = match sftp_get_host(app, host_map, mgr, &req.session_id).await {
+            Ok((sftp_arc, _)) => Some(sftp_arc),
+            Err(e) if e == SFTP_UNAVAILABLE => None,
+            Err(e) => return Err(e),
+        };
+        let _slot = acquire_slot(app, mgr, &mut ctx, req.priority).await?;
+        match (req.direction, req.options.archive) {
+            (TransferDirection::Upload, Some(archive)) => {
+                crate::archive::run_upload(mgr, &req.session_id, &mut ctx, &req.local_path, &req.remote_path, archive).await
+            }
+            (TransferDirection::Download, Some(archive)) => {
+                crate::archive::run_download(mgr, &req.session_id, &mut ctx, &req.remote_path, &req.local_path, archive).await
+            }
+            (TransferDirection::Upload, None) => match &sftp_arc {
+                Some(sftp_arc) => run_upload(mgr, sftp_arc, &mut ctx, &req.local_path, &req.remote_path).await,
+                None => crate::scp::run_upload(mgr, &req.session_id, &mut ctx, &req.local_path, &req.remote_path).await,
+            },
+            (TransferDirection::Download, None) => match &sftp_arc {
+                Some(sftp_arc) => run_download(mgr, sftp_arc, &mut ctx, &req.remote_path, &req.local_path).await,
+                None => crate::scp::run_download(mgr, &req.session_id, &mut ctx, &req.remote_path, &req.local_path).await,
+            },
+            (TransferDirection::Relay, _) => {
+                let sftp_arc = sftp_arc.ok_or(SFTP_UNAVAILABLE)?;
+                let target = req.target_session_id.as_deref().ok_or("Relay transfer has no target session")?;
+                let target_path = req.target_path.as_deref().ok_or("Relay transfer has no target path")?;
+                let (target_arc, _) = sftp_get_host(app, host_map, mgr, target).await?;
+                ctx.limiters.push(session_limiter(mgr, target).await);
+                run_relay(mgr, &sftp_arc, &target_arc, &mut ctx, target, &req.remote_path, target_path).await
+            }
+            (TransferDirection::Sync, _) => Err("Sync jobs cannot be retried; build a new plan".to_string()),
+        }
+    }
+    .await;
+    end_transfer(mgr, ctx, result).await
+}
+
+/// 直接发起的传输同样登记到队列，以便统一展示与重试
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn register_and_run(
+    app: &AppHandle,
+    host_map: &SessionHostMap,
+    mgr: &SftpManager,
+    direction: TransferDirection,
+    session_id: String,
+    transfer_id: String,
+    local_path: String,
+    remote_path: String,
+    options: TransferOptions,
+    priority: i32,
+) -> Result<bool, String> {
+    if let Some(queue) = app.try_state::<TransferQueue>() {
+        let host_id = host_map.0.read().await.get(&session_id).cloned().unwrap_or_default();
+        queue.upsert(app, QueuedTransfer::new(
+            &transfer_id, &session_id, &host_id, direction, &local_path, &remote_path, options.clone(),
+        ));
+    }
+    execute_transfer(app, host_map, mgr, TransferRequest {
+        session_id,
+        transfer_id,
+        direction,
+        local_path,
+        remote_path,
+        target_session_id: None,
+        target_path: None,
+        options,
+        priority,
+    })
+    .await
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn sftp_upload(
+    app: AppHandle,
+    host_map: tauri::State<'_, SessionHostMap>,
+    mgr: tauri::State<'_, SftpManager>,
+    session_id: String,
+    transfer_id: String,
+    local_path: String,
+    remote_path: String,
+    conflict_policy: Option<ConflictPolicy>,
+    preserve: Option<bool>,
+    verify: Option<bool>,
+    priority: Option<i32>,
+) -> Result<bool, String> {
+    let options = TransferOptions { conflict_policy, preserve, verify, archive: None };
+    register_and_run(
+        &app, &host_map, &mgr, TransferDirection::Upload,
+        session_id, transfer_id, local_path, remote_path, options, priority.unwrap_or(0),
+    )
+    .await
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn sftp_download(
     app: AppHandle,
     host_map: tauri::State<'_, SessionHostMap>,
@@ -240,75 +1314,110 @@ pub async fn sftp_download(
     transfer_id: String,
     remote_path: String,
     local_path: String,
+    conflict_policy: Option<ConflictPolicy>,
+    preserve: Option<bool>,
+    verify: Option<bool>,
+    priority: Option<i32>,
 ) -> Result<bool, String> {
-    let (sftp_arc, _) = sftp_get_host(&app, &host_map, &mgr, &session_id).await?;
-
-    // AtomicU8 无锁状态标记
-    let state = Arc::new(AtomicU8::new(TRANSFER_RUNNING));
-    mgr.transfers.write().await.insert(transfer_id.clone(), state.clone());
-
-    // 极短时间持有 sftp 锁：仅获取文件大小和打开文件句柄
-    let (total, mut remote_file) = {
-        let sftp = sftp_arc.lock().await;
-        let total = match sftp.metadata(&remote_path).await {
-            Ok(meta) => meta.size.unwrap_or(0),
-            Err(_) => 0,
-        };
-        let remote_file = sftp.open(&remote_path).await.map_err(|e| e.to_string())?;
-        (total, remote_file)
-    };
-
-    let mut file = tokio::fs::File::create(&local_path).await.map_err(|e| e.to_string())?;
-    let mut buf = vec![0u8; 128 * 1024];
-    let mut offset: u64 = 0;
-    let start = std::time::Instant::now();
-
-    use tokio::io::AsyncReadExt;
+    let options = TransferOptions { conflict_policy, preserve, verify, archive: None };
+    register_and_run(
+        &app, &host_map, &mgr, TransferDirection::Download,
+        session_id, transfer_id, local_path, remote_path, options, priority.unwrap_or(0),
+    )
+    .await
+}
 
-    loop {
-        // 检查取消/暂停（无锁，Relaxed 足够）
-        let s = state.load(Ordering::Relaxed);
-        if s == TRANSFER_CANCELLED {
-            drop(remote_file); // 先释放文件句柄
-            let _ = tokio::fs::remove_file(&local_path).await;
-            mgr.transfers.write().await.remove(&transfer_id);
-            return Err("Cancelled".to_string());
-        }
-        if s == TRANSFER_PAUSED {
-            tokio::time::sleep(std::time::Duration::from_millis(200)).await;
-            continue;
-        }
+/// 从一个会话复制文件或目录到另一个会话，数据经由本应用中转、不落地
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn sftp_relay(
+    app: AppHandle,
+    host_map: tauri::State<'_, SessionHostMap>,
+    mgr: tauri::State<'_, SftpManager>,
+    session_id: String,
+    target_session_id: String,
+    transfer_id: String,
+    source_path: String,
+    target_path: String,
+    conflict_policy: Option<ConflictPolicy>,
+    preserve: Option<bool>,
+    verify: Option<bool>,
+    priority: Option<i32>,
+) -> Result<bool, String> {
+    let options = TransferOptions { conflict_policy, preserve, verify, archive: None };
+    if let Some(queue) = app.try_state::<TransferQueue>() {
+        let host_id = host_map.0.read().await.get(&session_id).cloned().unwrap_or_default();
+        let mut item = QueuedTransfer::new(
+            &transfer_id, &session_id, &host_id, TransferDirection::Relay, "", &source_path, options.clone(),
+        );
+        item.target_session_id = Some(target_session_id.clone());
+        item.target_path = Some(target_path.clone());
+        queue.upsert(&app, item);
+    }
+    execute_transfer(&app, &host_map, &mgr, TransferRequest {
+        session_id,
+        transfer_id,
+        direction: TransferDirection::Relay,
+        local_path: String::new(),
+        remote_path: source_path,
+        target_session_id: Some(target_session_id),
+        target_path: Some(target_path),
+        options,
+        priority: priority.unwrap_or(0),
+    })
+    .await
+}
 
-        // 流式分块读取
-        let n = remote_file.read(&mut buf).await.map_err(|e| {
-            let _ = std::fs::remove_file(&local_path); // 同步清理
-            e.to_string()
-        })?;
+/// 调整限速（字节/秒，0 为不限速）；不传 sessionId 时修改全局限速
+#[tauri::command]
+pub async fn sftp_set_bandwidth_limit(
+    mgr: tauri::State<'_, SftpManager>,
+    session_id: Option<String>,
+    bytes_per_sec: u64,
+) -> Result<(), String> {
+    match session_id {
+        Some(id) => session_limiter(&mgr, &id).await.set_rate(bytes_per_sec),
+        None => mgr.global_limit.set_rate(bytes_per_sec),
+    }
+    Ok(())
+}
 
-        if n == 0 {
-            break;
-        }
+/// 调整会话的最大并发传输数，运行中立即生效
+#[tauri::command]
+pub async fn sftp_set_max_concurrent(
+    app: AppHandle,
+    mgr: tauri::State<'_, SftpManager>,
+    session_id: String,
+    max: usize,
+) -> Result<(), String> {
+    let default_max = crate::settings::load(&app).max_concurrent_transfers;
+    session_slots(&mgr, &session_id, default_max).await.set_max(max);
+    Ok(())
+}
 
-        file.write_all(&buf[..n]).await.map_err(|e| {
-            let _ = std::fs::remove_file(&local_path);
-            e.to_string()
-        })?;
-
-        offset += n as u64;
-        let elapsed = start.elapsed().as_secs().max(1);
-        let _ = app.emit("sftp:download-progress", TransferProgressEvent {
-            transfer_id: transfer_id.clone(),
-            session_id: session_id.clone(),
-            remote_path: remote_path.clone(),
-            bytes_transferred: offset,
-            total_bytes: total,
-            speed: offset / elapsed,
-        });
-    }
+/// 调整排队中传输的优先级（数值越大越先执行）
+#[tauri::command]
+pub async fn sftp_set_transfer_priority(
+    mgr: tauri::State<'_, SftpManager>,
+    session_id: String,
+    transfer_id: String,
+    priority: i32,
+) -> Result<bool, String> {
+    let slots = mgr.slots.read().await;
+    Ok(slots.get(&session_id).map(|q| q.set_priority(&transfer_id, priority)).unwrap_or(false))
+}
 
-    file.flush().await.map_err(|e| e.to_string())?;
-    mgr.transfers.write().await.remove(&transfer_id);
-    Ok(true)
+/// 回复 `sftp:transfer-conflict` 询问
+#[tauri::command]
+pub async fn sftp_resolve_conflict(
+    mgr: tauri::State<'_, SftpManager>,
+    conflict_id: String,
+    policy: ConflictPolicy,
+    apply_to_all: Option<bool>,
+) -> Result<(), String> {
+    let tx = mgr.conflicts.write().await.remove(&conflict_id).ok_or("Conflict not found")?;
+    let _ = tx.send(ConflictAnswer { policy, apply_to_all: apply_to_all.unwrap_or(false) });
+    Ok(())
 }
 
 /// A helper to hold session->host_id mapping
@@ -330,11 +1439,15 @@ pub async fn sftp_connect_session(
 ) -> Result<bool, String> {
     host_map.0.write().await.insert(session_id.clone(), host_id.clone());
     let host = crate::db::get_host(app.clone(), host_id).await?.ok_or("Host not found")?;
-    get_or_create_sftp(&mgr, &session_id, &host).await?;
-    Ok(true)
+    match get_or_create_sftp(&mgr, &session_id, &host).await {
+        // 没有 SFTP 时连接仍然可用于 SCP 传输与 exec 命令
+        Ok(_) => Ok(true),
+        Err(e) if e == SFTP_UNAVAILABLE => Ok(true),
+        Err(e) => Err(e),
+    }
 }
 
-async fn sftp_get_host(
+pub(crate) async fn sftp_get_host(
     app: &AppHandle,
     host_map: &SessionHostMap,
     mgr: &SftpManager,
@@ -400,6 +1513,211 @@ pub async fn sftp_mkdir(
     Ok(true)
 }
 
+/// 列表类命令的会话过滤：未指定会话时全部保留
+pub(crate) fn in_session(filter: Option<&str>, session_id: &str) -> bool {
+    filter.is_none_or(|id| id == session_id)
+}
+
+/// 单引号转义，用于拼接远程 shell 命令
+pub(crate) fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// 在 SFTP 使用的 SSH 连接上打开 exec 通道并启动命令，由调用方读取输出
+pub(crate) async fn open_exec_channel(
+    mgr: &SftpManager,
+    session_id: &str,
+    command: &str,
+) -> Result<russh::Channel<client::Msg>, String> {
+    let channel = {
+        let handles = mgr.handles.read().await;
+        let handle = handles.get(session_id).ok_or("Session not found")?;
+        handle.channel_open_session().await.map_err(|e| e.to_string())?
+    };
+    channel.exec(true, command).await.map_err(|e| e.to_string())?;
+    Ok(channel)
+}
+
+/// 在 SFTP 使用的 SSH 连接上打开 exec 通道执行命令，返回 (退出码, stdout)
+pub(crate) async fn exec_on_session(
+    mgr: &SftpManager,
+    session_id: &str,
+    command: &str,
+) -> Result<(u32, Vec<u8>), String> {
+    let mut channel = open_exec_channel(mgr, session_id, command).await?;
+
+    let mut stdout = Vec::new();
+    let mut exit_code = 0;
+    while let Some(msg) = channel.wait().await {
+        match msg {
+            russh::ChannelMsg::Data { data } => stdout.extend_from_slice(&data),
+            russh::ChannelMsg::ExitStatus { exit_status } => exit_code = exit_status,
+            russh::ChannelMsg::Close => break,
+            _ => {}
+        }
+    }
+    Ok((exit_code, stdout))
+}
+
+/// 通过 `getent` 把用户名/组名解析成数字 id（纯数字直接返回）
+async fn resolve_owner_id(
+    mgr: &SftpManager,
+    session_id: &str,
+    database: &str,
+    name: &str,
+) -> Result<u32, String> {
+    if let Ok(id) = name.parse::<u32>() {
+        return Ok(id);
+    }
+    let (code, out) = exec_on_session(
+        mgr,
+        session_id,
+        &format!("getent {} {}", database, shell_quote(name)),
+    )
+    .await?;
+    let line = String::from_utf8_lossy(&out);
+    // passwd/group 条目的第三个字段是 id
+    line.split(':')
+        .nth(2)
+        .filter(|_| code == 0)
+        .and_then(|id| id.trim().parse().ok())
+        .ok_or_else(|| format!("Unknown {}: {}", if database == "passwd" { "user" } else { "group" }, name))
+}
+
+/// 列出路径本身及（递归时）其下所有条目，符号链接不跟随
+async fn walk_remote(
+    sftp_arc: &Arc<Mutex<SftpSession>>,
+    root: &str,
+    recursive: bool,
+) -> Result<Vec<(String, russh_sftp::client::fs::Metadata)>, String> {
+    let sftp = sftp_arc.lock().await;
+    let root_meta = sftp.metadata(root).await.map_err(|e| e.to_string())?;
+    let mut result = vec![(root.to_string(), root_meta.clone())];
+    if !recursive || !root_meta.file_type().is_dir() {
+        return Ok(result);
+    }
+
+    let mut stack = vec![root.to_string()];
+    while let Some(dir) = stack.pop() {
+        for entry in sftp.read_dir(dir.as_str()).await.map_err(|e| e.to_string())? {
+            let path = join_remote(&dir, &entry.file_name());
+            let metadata = entry.metadata();
+            if metadata.file_type().is_dir() {
+                stack.push(path.clone());
+            }
+            result.push((path, metadata));
+        }
+    }
+    Ok(result)
+}
+
+#[tauri::command]
+pub async fn sftp_chmod(
+    app: AppHandle,
+    host_map: tauri::State<'_, SessionHostMap>,
+    mgr: tauri::State<'_, SftpManager>,
+    session_id: String,
+    path: String,
+    mode: u32,
+    recursive: Option<bool>,
+) -> Result<bool, String> {
+    let (sftp_arc, _) = sftp_get_host(&app, &host_map, &mgr, &session_id).await?;
+    let entries = walk_remote(&sftp_arc, &path, recursive.unwrap_or(false)).await?;
+
+    let sftp = sftp_arc.lock().await;
+    for (entry_path, metadata) in entries {
+        // 符号链接本身的权限没有意义，跳过
+        if metadata.file_type().is_symlink() {
+            continue;
+        }
+        let mut attrs = russh_sftp::protocol::FileAttributes::empty();
+        attrs.permissions = Some(mode & 0o7777);
+        sftp.set_metadata(entry_path.as_str(), attrs).await.map_err(|e| e.to_string())?;
+    }
+    Ok(true)
+}
+
+/// 修改属主；`user`/`group` 可以是名称或数字 id，省略的一方保持不变
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn sftp_chown(
+    app: AppHandle,
+    host_map: tauri::State<'_, SessionHostMap>,
+    mgr: tauri::State<'_, SftpManager>,
+    session_id: String,
+    path: String,
+    user: Option<String>,
+    group: Option<String>,
+    recursive: Option<bool>,
+) -> Result<bool, String> {
+    let (sftp_arc, _) = sftp_get_host(&app, &host_map, &mgr, &session_id).await?;
+    let uid = match &user {
+        Some(u) => Some(resolve_owner_id(&mgr, &session_id, "passwd", u).await?),
+        None => None,
+    };
+    let gid = match &group {
+        Some(g) => Some(resolve_owner_id(&mgr, &session_id, "group", g).await?),
+        None => None,
+    };
+    if uid.is_none() && gid.is_none() {
+        return Err("Nothing to change".to_string());
+    }
+
+    let entries = walk_remote(&sftp_arc, &path, recursive.unwrap_or(false)).await?;
+    let sftp = sftp_arc.lock().await;
+    for (entry_path, metadata) in entries {
+        if metadata.file_type().is_symlink() {
+            continue;
+        }
+        // SFTP v3 的 UIDGID 必须同时携带 uid 和 gid
+        let mut attrs = russh_sftp::protocol::FileAttributes::empty();
+        attrs.uid = uid.or(metadata.uid);
+        attrs.gid = gid.or(metadata.gid);
+        sftp.set_metadata(entry_path.as_str(), attrs).await.map_err(|e| e.to_string())?;
+    }
+    Ok(true)
+}
+
+/// 设置访问/修改时间（毫秒，与 FileEntry 一致）；类似 touch，文件不存在时创建空文件
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn sftp_set_times(
+    app: AppHandle,
+    host_map: tauri::State<'_, SessionHostMap>,
+    mgr: tauri::State<'_, SftpManager>,
+    session_id: String,
+    path: String,
+    mtime: Option<u64>,
+    atime: Option<u64>,
+    recursive: Option<bool>,
+) -> Result<bool, String> {
+    let (sftp_arc, _) = sftp_get_host(&app, &host_map, &mgr, &session_id).await?;
+    {
+        let sftp = sftp_arc.lock().await;
+        if !sftp.try_exists(path.as_str()).await.map_err(|e| e.to_string())? {
+            let mut file = sftp.create(path.as_str()).await.map_err(|e| e.to_string())?;
+            file.shutdown().await.map_err(|e| e.to_string())?;
+        }
+    }
+
+    let now = chrono::Utc::now().timestamp_millis() as u64;
+    let mtime = (mtime.unwrap_or(now) / 1000) as u32;
+    let atime = atime.map(|t| (t / 1000) as u32).unwrap_or(mtime);
+
+    let entries = walk_remote(&sftp_arc, &path, recursive.unwrap_or(false)).await?;
+    let sftp = sftp_arc.lock().await;
+    for (entry_path, metadata) in entries {
+        if metadata.file_type().is_symlink() {
+            continue;
+        }
+        let mut attrs = russh_sftp::protocol::FileAttributes::empty();
+        attrs.atime = Some(atime);
+        attrs.mtime = Some(mtime);
+        sftp.set_metadata(entry_path.as_str(), attrs).await.map_err(|e| e.to_string())?;
+    }
+    Ok(true)
+}
+
 #[derive(Serialize)]
 pub struct FilePreviewResult {
     #[serde(rename = "isText")]
@@ -409,6 +1727,17 @@ pub struct FilePreviewResult {
     content: Option<String>,
     #[serde(rename = "mimeType")]
     mime_type: Option<String>,
+    /// 读取时的大小与修改时间（毫秒），保存时回传用于并发检查
+    size: u64,
+    mtime: u64,
+    /// 文本文件检测到的编码、BOM 与换行符，保存时原样传回即可保持不变
+    encoding: Option<String>,
+    #[serde(rename = "hasBom")]
+    has_bom: bool,
+    #[serde(rename = "lineEnding")]
+    line_ending: Option<String>,
+    #[serde(rename = "mixedLineEndings")]
+    mixed_line_endings: bool,
 }
 
 fn detect_mime_type(data: &[u8], path: &str) -> (bool, bool, Option<String>) {
@@ -429,9 +1758,10 @@ fn detect_mime_type(data: &[u8], path: &str) -> (bool, bool, Option<String>) {
         (false, None)
     };
 
-    // Check if it's text (no null bytes in first 8KB)
+    // Check if it's text (no null bytes in first 8KB); UTF-16 with BOM is text too
     let check_len = std::cmp::min(data.len(), 8192);
-    let is_text = !is_image && data[..check_len].iter().all(|&b| b != 0);
+    let utf16_bom = data.starts_with(&[0xFF, 0xFE]) || data.starts_with(&[0xFE, 0xFF]);
+    let is_text = !is_image && (utf16_bom || data[..check_len].iter().all(|&b| b != 0));
 
     // Detect text mime type from extension
     let text_mime = if is_text {
@@ -463,12 +1793,16 @@ pub async fn sftp_get_file(
 ) -> Result<FilePreviewResult, String> {
     let (sftp_arc, _) = sftp_get_host(&app, &host_map, &mgr, &session_id).await?;
     let sftp = sftp_arc.lock().await;
-    let data = sftp.read(&path).await.map_err(|e| e.to_string())?;
+    // 先取属性再读内容：期间被修改时宁可在保存时误报冲突
+    let metadata = sftp.metadata(&path).await.map_err(|e| e.to_string())?;
+    let size = metadata.size.unwrap_or(0);
+    let mtime = metadata.mtime.unwrap_or(0) as u64 * 1000;
 
-    // Limit file size to 2MB for preview
-    if data.len() > 2 * 1024 * 1024 {
+    // Limit file size to 2MB for preview; larger files use sftp_read_range / sftp_read_tail
+    if size > 2 * 1024 * 1024 {
         return Err("File too large for preview (max 2MB)".to_string());
     }
+    let data = sftp.read(&path).await.map_err(|e| e.to_string())?;
 
     let (is_text, is_image, mime_type) = detect_mime_type(&data, &path);
 
@@ -479,13 +1813,26 @@ pub async fn sftp_get_file(
             is_image: true,
             content: Some(general_purpose::STANDARD.encode(&data)),
             mime_type,
+            size,
+            mtime,
+            encoding: None,
+            has_bom: false,
+            line_ending: None,
+            mixed_line_endings: false,
         })
     } else if is_text {
+        let decoded = crate::encoding::decode(&data);
         Ok(FilePreviewResult {
             is_text: true,
             is_image: false,
-            content: Some(String::from_utf8_lossy(&data).to_string()),
+            content: Some(decoded.text),
             mime_type,
+            size,
+            mtime,
+            encoding: Some(decoded.encoding.to_string()),
+            has_bom: decoded.bom,
+            line_ending: decoded.line_ending.map(|s| s.to_string()),
+            mixed_line_endings: decoded.mixed_line_endings,
         })
     } else {
         Ok(FilePreviewResult {
@@ -493,11 +1840,232 @@ pub async fn sftp_get_file(
             is_image: false,
             content: None,
             mime_type: None,
+            size,
+            mtime,
+            encoding: None,
+            has_bom: false,
+            line_ending: None,
+            mixed_line_endings: false,
         })
     }
 }
 
+/// 保存后的远程文件状态（毫秒），作为下一次保存的并发检查基准
+#[derive(Serialize, Clone, Debug)]
+pub struct SaveResult {
+    pub size: u64,
+    pub mtime: u64,
+    /// 是否经临时文件原子替换；false 表示按 allow_in_place 直接覆盖了目标
+    pub atomic: bool,
+}
+
+/// 原子保存的可选项
+#[derive(Default)]
+pub(crate) struct SaveOptions {
+    /// 覆盖前保留一份 `<name>.bak`
+    pub backup: bool,
+    /// 打开文件时的大小与修改时间（毫秒），与当前不一致时拒绝保存
+    pub expected_size: Option<u64>,
+    pub expected_mtime: Option<u64>,
+    /// 无法使用临时文件（目录不可写、无法改回属主）时允许直接覆盖目标，否则返回错误
+    pub allow_in_place: bool,
+}
+
+/// 用临时文件替换目标；优先 posix-rename@openssh.com（可直接覆盖）
+async fn replace_file(
+    mgr: &SftpManager,
+    session_id: &str,
+    sftp_arc: &Arc<Mutex<SftpSession>>,
+    from: &str,
+    to: &str,
+) -> Result<(), String> {
+    let posix_rename = server_extensions(mgr, session_id)
+        .await
+        .map(|ext| ext.contains_key("posix-rename@openssh.com"))
+        .unwrap_or(false);
+    if posix_rename {
+        use russh_sftp::protocol::{Packet, StatusCode};
+        let raw = open_raw_sftp(mgr, session_id).await?;
+        let mut data = Vec::new();
+        crate::checksum::put_string(&mut data, from);
+        crate::checksum::put_string(&mut data, to);
+        return match raw.extended("posix-rename@openssh.com", data).await.map_err(|e| e.to_string())? {
+            Packet::Status(status) if status.status_code == StatusCode::Ok => Ok(()),
+            Packet::Status(status) => Err(status.error_message),
+            _ => Err("Unexpected reply to posix-rename".to_string()),
+        };
+    }
+
+    // SFTP v3 的 rename 不会覆盖已有文件：先把目标移开，失败时移回
+    let sftp = sftp_arc.lock().await;
+    if !sftp.try_exists(to).await.map_err(|e| e.to_string())? {
+        return sftp.rename(from, to).await.map_err(|e| e.to_string());
+    }
+    let displaced = format!("{}.old", from);
+    sftp.rename(to, displaced.as_str()).await.map_err(|e| e.to_string())?;
+    if let Err(e) = sftp.rename(from, to).await {
+        let _ = sftp.rename(displaced.as_str(), to).await;
+        return Err(e.to_string());
+    }
+    let _ = sftp.remove_file(displaced.as_str()).await;
+    Ok(())
+}
+
+/// 把内容写入临时文件并恢复原文件的权限与属主。
+/// 返回 Some(原因) 表示无法使用临时文件（目录不可写，或属主无法改回原来的用户），临时文件已删除
+async fn stage_temp(
+    sftp: &SftpSession,
+    temp: &str,
+    data: &[u8],
+    existing: Option<&russh_sftp::client::fs::Metadata>,
+) -> Result<Option<String>, String> {
+    let mut file = match sftp.create(temp).await {
+        Ok(file) => file,
+        Err(e) if existing.is_some() => return Ok(Some(format!("cannot create a temporary file: {}", e))),
+        Err(e) => return Err(e.to_string()),
+    };
+    file.write_all(data).await.map_err(|e| e.to_string())?;
+    file.sync_all().await.map_err(|e| e.to_string())?;
+    file.shutdown().await.map_err(|e| e.to_string())?;
+
+    let Some(meta) = existing else { return Ok(None) };
+    let mut attrs = russh_sftp::protocol::FileAttributes::empty();
+    attrs.permissions = meta.permissions.map(|p| p & 0o7777);
+    sftp.set_metadata(temp, attrs).await.map_err(|e| e.to_string())?;
+
+    let current = sftp.metadata(temp).await.map_err(|e| e.to_string())?;
+    if (current.uid, current.gid) == (meta.uid, meta.gid) {
+        return Ok(None);
+    }
+    // 普通用户通常无法把属主改成别人（如保存组可写的文件），不能悄悄改变属主
+    let mut attrs = russh_sftp::protocol::FileAttributes::empty();
+    attrs.uid = meta.uid;
+    attrs.gid = meta.gid;
+    let restored = sftp.set_metadata(temp, attrs).await.is_ok()
+        && sftp
+            .metadata(temp)
+            .await
+            .is_ok_and(|m| (m.uid, m.gid) == (meta.uid, meta.gid));
+    if restored {
+        return Ok(None);
+    }
+    let _ = sftp.remove_file(temp).await;
+    Ok(Some("cannot keep the file's owner".to_string()))
+}
+
+/// 保留一份 `<target>.bak`。allow_link 时优先用硬链接保留原 inode（之后用重命名替换目标才安全），
+/// 原地写入会修改同一个 inode，只能复制
+async fn write_backup(sftp: &SftpSession, target: &str, allow_link: bool) -> Result<(), String> {
+    let backup = format!("{}.bak", target);
+    let _ = sftp.remove_file(backup.as_str()).await;
+    if allow_link && sftp.hardlink(target, backup.as_str()).await.unwrap_or(false) {
+        return Ok(());
+    }
+    let original = sftp.read(target).await.map_err(|e| e.to_string())?;
+    let mut file = sftp
+        .create(backup.as_str())
+        .await
+        .map_err(|e| format!("Cannot create backup {}: {}", backup, e))?;
+    file.write_all(&original).await.map_err(|e| e.to_string())?;
+    file.shutdown().await.map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// 先写同目录下的临时文件再重命名覆盖目标，连接中断不会留下写了一半的文件。
+/// 保留原文件的权限与属主，做不到时（目录不可写、无法改回属主）返回错误，
+/// 除非 allow_in_place 允许直接覆盖（结果中 atomic 为 false）；目标是符号链接时写入其指向的文件。
+pub(crate) async fn atomic_write(
+    mgr: &SftpManager,
+    session_id: &str,
+    sftp_arc: &Arc<Mutex<SftpSession>>,
+    path: &str,
+    data: &[u8],
+    options: &SaveOptions,
+) -> Result<SaveResult, String> {
+    let (target, existing) = {
+        let sftp = sftp_arc.lock().await;
+        match sftp.symlink_metadata(path).await {
+            Ok(meta) if meta.file_type().is_symlink() => {
+                let real = sftp.canonicalize(path).await.map_err(|e| e.to_string())?;
+                let meta = sftp.metadata(real.as_str()).await.map_err(|e| e.to_string())?;
+                (real, Some(meta))
+            }
+            Ok(meta) => (path.to_string(), Some(meta)),
+            Err(_) => (path.to_string(), None),
+        }
+    };
+
+    if options.expected_size.is_some() || options.expected_mtime.is_some() {
+        let current = existing.as_ref().map(|m| (m.size.unwrap_or(0), m.mtime.unwrap_or(0) as u64));
+        let unchanged = current.is_some_and(|(size, mtime)| {
+            options.expected_size.is_none_or(|s| s == size)
+                && options.expected_mtime.is_none_or(|t| t / 1000 == mtime)
+        });
+        if !unchanged {
+            return Err("Remote file was modified since it was opened".to_string());
+        }
+    }
+
+    let (dir, name) = match target.rfind('/') {
+        Some(pos) => (&target[..=pos], &target[pos + 1..]),
+        None => ("", target.as_str()),
+    };
+    let temp = format!("{}.{}.{}.tmp", dir, name, &uuid::Uuid::new_v4().simple().to_string()[..8]);
+
+    let unstaged = {
+        let sftp = sftp_arc.lock().await;
+        match stage_temp(&sftp, &temp, data, existing.as_ref()).await {
+            Ok(unstaged) => unstaged,
+            Err(e) => {
+                let _ = sftp.remove_file(temp.as_str()).await;
+                return Err(e);
+            }
+        }
+    };
+
+    if let Some(reason) = &unstaged {
+        if !options.allow_in_place {
+            return Err(format!("Cannot save {} atomically: {}", target, reason));
+        }
+        // 调用方允许时原地覆盖，文件本身的 inode、权限与属主保持不变，但中断会留下写了一半的文件
+        log::info!("Saving {} in place: {}", target, reason);
+        let sftp = sftp_arc.lock().await;
+        if options.backup {
+            write_backup(&sftp, &target, false).await?;
+        }
+        let mut file = sftp.create(target.as_str()).await.map_err(|e| e.to_string())?;
+        file.write_all(data).await.map_err(|e| e.to_string())?;
+        file.sync_all().await.map_err(|e| e.to_string())?;
+        file.shutdown().await.map_err(|e| e.to_string())?;
+    } else {
+        let result: Result<(), String> = async {
+            if options.backup && existing.is_some() {
+                write_backup(&*sftp_arc.lock().await, &target, true).await?;
+            }
+            replace_file(mgr, session_id, sftp_arc, &temp, &target).await
+        }
+        .await;
+        if let Err(e) = result {
+            let _ = sftp_arc.lock().await.remove_file(temp.as_str()).await;
+            return Err(e);
+        }
+    }
+
+    let sftp = sftp_arc.lock().await;
+    let meta = sftp.metadata(target.as_str()).await.map_err(|e| e.to_string())?;
+    Ok(SaveResult {
+        size: meta.size.unwrap_or(0),
+        mtime: meta.mtime.unwrap_or(0) as u64 * 1000,
+        atomic: unstaged.is_none(),
+    })
+}
+
+/// 原子保存文本文件；传入打开时的 size/mtime 可在他人修改后拒绝覆盖。
+/// encoding/bom/lineEnding 通常回传 `sftp_get_file` 的检测结果，修改它们即转换编码或换行符；
+/// 省略 encoding 时按 UTF-8 保存，省略 lineEnding 时保持内容中的换行不变。
+/// 无法原子保存时返回错误；allowInPlace 为 true 时改为直接覆盖，并在结果中以 atomic: false 告知。
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn sftp_put_file(
     app: AppHandle,
     host_map: tauri::State<'_, SessionHostMap>,
@@ -505,12 +2073,28 @@ pub async fn sftp_put_file(
     session_id: String,
     path: String,
     content: String,
-) -> Result<bool, String> {
+    expected_size: Option<u64>,
+    expected_mtime: Option<u64>,
+    backup: Option<bool>,
+    encoding: Option<String>,
+    bom: Option<bool>,
+    line_ending: Option<String>,
+    allow_in_place: Option<bool>,
+) -> Result<SaveResult, String> {
+    let data = crate::encoding::encode(
+        &content,
+        encoding.as_deref().unwrap_or("utf-8"),
+        bom.unwrap_or(false),
+        line_ending.as_deref(),
+    )?;
     let (sftp_arc, _) = sftp_get_host(&app, &host_map, &mgr, &session_id).await?;
-    let sftp = sftp_arc.lock().await;
-    let mut file = sftp.create(&path).await.map_err(|e| e.to_string())?;
-    file.write_all(content.as_bytes()).await.map_err(|e| e.to_string())?;
-    Ok(true)
+    let options = SaveOptions {
+        backup: backup.unwrap_or(false),
+        expected_size,
+        expected_mtime,
+        allow_in_place: allow_in_place.unwrap_or(false),
+    };
+    atomic_write(&mgr, &session_id, &sftp_arc, &path, &data, &options).await
 }
 
 #[tauri::command]
@@ -539,42 +2123,91 @@ pub async fn sftp_stat(
 ) -> Result<FileEntry, String> {
     let (sftp_arc, _) = sftp_get_host(&app, &host_map, &mgr, &session_id).await?;
     let sftp = sftp_arc.lock().await;
-    
-    let metadata = sftp.metadata(&path).await.map_err(|e| e.to_string())?;
-    let is_dir = metadata.is_dir();
-    
+    let names = owner_names(&mgr, &session_id, &sftp).await;
+
+    let metadata = sftp.symlink_metadata(&path).await.map_err(|e| e.to_string())?;
+
     // For stat, we just get name from the path 
     let name = Path::new(&path).file_name().and_then(|n| n.to_str()).unwrap_or("").to_string();
-    
-    Ok(FileEntry {
-        name,
-        size: metadata.size.unwrap_or(0),
-        entry_type: if is_dir { "directory".to_string() } else { "file".to_string() },
-        mode: metadata.permissions.unwrap_or(0),
-        mtime: metadata.mtime.unwrap_or(0) as u64 * 1000,
-    })
+
+    Ok(build_file_entry(&sftp, &names, &path, name, &metadata).await)
+}
+
+/// 创建符号链接 `link_path -> target`
+#[tauri::command]
+pub async fn sftp_symlink(
+    app: AppHandle,
+    host_map: tauri::State<'_, SessionHostMap>,
+    mgr: tauri::State<'_, SftpManager>,
+    session_id: String,
+    target: String,
+    link_path: String,
+) -> Result<bool, String> {
+    let (sftp_arc, _) = sftp_get_host(&app, &host_map, &mgr, &session_id).await?;
+    let sftp = sftp_arc.lock().await;
+    // OpenSSH 的 sftp-server 把 SSH_FXP_SYMLINK 的两个参数按相反顺序解析，
+    // 因此这里先传 target 再传链接路径
+    sftp.symlink(target, link_path).await.map_err(|e| e.to_string())?;
+    Ok(true)
+}
+
+/// 创建硬链接（需要服务器支持 `hardlink@openssh.com`）
+#[tauri::command]
+pub async fn sftp_hardlink(
+    app: AppHandle,
+    host_map: tauri::State<'_, SessionHostMap>,
+    mgr: tauri::State<'_, SftpManager>,
+    session_id: String,
+    target: String,
+    link_path: String,
+) -> Result<bool, String> {
+    let (sftp_arc, _) = sftp_get_host(&app, &host_map, &mgr, &session_id).await?;
+    let sftp = sftp_arc.lock().await;
+    if !sftp.hardlink(target, link_path).await.map_err(|e| e.to_string())? {
+        return Err("Server does not support hardlink@openssh.com".to_string());
+    }
+    Ok(true)
 }
 
 #[tauri::command]
 pub async fn sftp_pause(
+    app: AppHandle,
     mgr: tauri::State<'_, SftpManager>,
     transfer_id: String,
 ) -> Result<(), String> {
     let transfers = mgr.transfers.read().await;
     if let Some(state) = transfers.get(&transfer_id) {
         state.store(TRANSFER_PAUSED, Ordering::Relaxed);
+        if let Some(queue) = app.try_state::<TransferQueue>() {
+            queue.update(&app, &transfer_id, |item| {
+                item.status = TransferStatus::Paused;
+                item.speed = 0;
+            });
+        }
     }
     Ok(())
 }
 
 #[tauri::command]
 pub async fn sftp_resume(
+    app: AppHandle,
     mgr: tauri::State<'_, SftpManager>,
     transfer_id: String,
 ) -> Result<(), String> {
     let transfers = mgr.transfers.read().await;
     if let Some(state) = transfers.get(&transfer_id) {
         state.store(TRANSFER_RUNNING, Ordering::Relaxed);
+        if let Some(queue) = app.try_state::<TransferQueue>() {
+            // 仍在等待槽位的传输回到排队状态
+            let waiting = mgr
+                .slots
+                .read()
+                .await
+                .values()
+                .any(|q| q.waiting().contains(&transfer_id));
+            let status = if waiting { TransferStatus::Queued } else { TransferStatus::Running };
+            queue.set_status(&app, &transfer_id, status);
+        }
     }
     Ok(())
 }
@@ -596,6 +2229,9 @@ pub async fn sftp_cancel(
 pub async fn sftp_disconnect(
     host_map: tauri::State<'_, SessionHostMap>,
     mgr: tauri::State<'_, SftpManager>,
+    edits: tauri::State<'_, crate::edit::EditManager>,
+    follows: tauri::State<'_, crate::follow::FollowManager>,
+    sudo: tauri::State<'_, crate::sudo::SudoManager>,
     session_id: String,
 ) -> Result<(), String> {
     // 从 host_map 中移除
@@ -606,6 +2242,17 @@ pub async fn sftp_disconnect(
 
     // 从 handles 中移除（SSH 连接会自动断开）
     mgr.handles.write().await.remove(&session_id);
+    mgr.owner_names.write().await.remove(&session_id);
+    mgr.extensions.write().await.remove(&session_id);
+    mgr.session_limits.write().await.remove(&session_id);
+    mgr.slots.write().await.remove(&session_id);
+    mgr.scp_only.write().await.remove(&session_id);
+    mgr.elevated_raw.write().await.remove(&session_id);
+
+    // 停止外部编辑同步并删除临时副本
+    crate::edit::close_session(&edits, &session_id).await;
+    crate::follow::close_session(&follows, &session_id).await;
+    crate::sudo::close_session(&sudo, &session_id).await;
 
     Ok(())
 }
@@ -620,15 +2267,11 @@ pub async fn sftp_read_text_file(
     path: String,
 ) -> Result<String, String> {
     let (sftp_arc, _) = sftp_get_host(&app, &host_map, &mgr, &session_id).await?;
-    let sftp = sftp_arc.lock().await;
-    let data = sftp.read(&path).await.map_err(|e| e.to_string())?;
-
-    // 最多读取 2MB
-    if data.len() > 2 * 1024 * 1024 {
-        // 大文件只取最后 2MB（历史记录最新的在文件末尾）
-        let tail = &data[data.len() - 2 * 1024 * 1024..];
-        return Ok(String::from_utf8_lossy(tail).to_string());
-    }
 
-    Ok(String::from_utf8_lossy(&data).to_string())
+    // 最多读取 2MB：大文件只取最后 2MB（历史记录最新的在文件末尾），只传输需要的部分
+    const LIMIT: u64 = 2 * 1024 * 1024;
+    let size = crate::range::file_size(&sftp_arc, &path).await?;
+    let offset = size.saturating_sub(LIMIT);
+    let data = crate::range::read_at(&sftp_arc, &path, offset, LIMIT).await?;
+    Ok(crate::encoding::decode(&data).text)
 }
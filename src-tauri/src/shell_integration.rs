@@ -0,0 +1,391 @@
+use base64::Engine;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use tauri::{AppHandle, Emitter};
+use tokio::sync::RwLock;
+use crate::ssh::SshManager;
+
+/// 单个 OSC 序列的最大长度，超过则视为异常数据丢弃
+const MAX_OSC: usize = 4096;
+/// 每个会话保留的命令记录数
+const MAX_COMMANDS: usize = 200;
+
+const BASH_SNIPPET: &str = r#"if [ -z "$__LYNX_SI" ]; then
+__LYNX_SI=1
+__lynx_si_prompt() {
+  local s=$?
+  printf '\033]133;D;%s\007\033]7;file://%s%s\007' "$s" "$HOSTNAME" "$PWD"
+  return $s
+}
+PROMPT_COMMAND="__lynx_si_prompt${PROMPT_COMMAND:+;$PROMPT_COMMAND}"
+PS1="\[\033]133;A\007\]$PS1\[\033]133;B\007\]"
+PS0="${PS0}\033]133;C\007"
+fi"#;
+
+const ZSH_SNIPPET: &str = r#"if [[ -z $__LYNX_SI ]]; then
+__LYNX_SI=1
+__lynx_si_precmd() { printf '\033]133;D;%s\007\033]7;file://%s%s\007\033]133;A\007' "$?" "$HOST" "$PWD"; }
+__lynx_si_preexec() { printf '\033]133;C\007'; }
+autoload -Uz add-zsh-hook
+add-zsh-hook precmd __lynx_si_precmd
+add-zsh-hook preexec __lynx_si_preexec
+PS1="$PS1%{"$'\033]133;B\007'"%}"
+fi"#;
+
+const FISH_SNIPPET: &str = r#"if not set -q __LYNX_SI
+set -g __LYNX_SI 1
+function __lynx_si_prompt --on-event fish_prompt
+  printf '\033]7;file://%s%s\007\033]133;A\007' $hostname $PWD
+end
+function __lynx_si_preexec --on-event fish_preexec
+  printf '\033]133;C\007'
+end
+function __lynx_si_postexec --on-event fish_postexec
+  printf '\033]133;D;%s\007' $status
+end
+end"#;
+
+/// 根据 OSC 133 标记推断的 shell 状态
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum ShellPhase {
+    /// 还没有收到过 OSC 133（未启用 shell 集成）
+    #[default]
+    Unknown,
+    /// 正在输出提示符（133;A）
+    Prompt,
+    /// 提示符结束，用户正在输入命令（133;B）
+    Input,
+    /// 命令正在执行（133;C）
+    Running,
+}
+
+/// 一条命令的边界。offset 为该会话终端输出流中的字节位置
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CommandRecord {
+    pub id: u64,
+    /// 执行命令时的工作目录
+    pub cwd: Option<String>,
+    pub started_at: String,
+    pub finished_at: Option<String>,
+    pub exit_code: Option<i32>,
+    /// 提示符开始的位置
+    pub prompt_offset: Option<u64>,
+    /// 命令输出开始的位置
+    pub output_offset: u64,
+    /// 命令输出结束的位置
+    pub end_offset: Option<u64>,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ShellState {
+    /// 最近一次 OSC 7 报告的目录
+    pub cwd: Option<String>,
+    /// OSC 7 中的主机名
+    pub host: Option<String>,
+    pub phase: ShellPhase,
+    /// 已经处理的终端输出字节数
+    pub offset: u64,
+    /// 最近的命令，按时间先后
+    pub commands: VecDeque<CommandRecord>,
+    #[serde(skip)]
+    next_id: u64,
+    #[serde(skip)]
+    prompt_offset: Option<u64>,
+}
+
+impl ShellState {
+    fn running(&mut self) -> Option<&mut CommandRecord> {
+        self.commands.back_mut().filter(|c| c.finished_at.is_none())
+    }
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct CwdChangedEvent {
+    session_id: String,
+    cwd: String,
+    host: Option<String>,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct CommandStateEvent {
+    session_id: String,
+    phase: ShellPhase,
+    /// 开始或结束的命令
+    command: Option<CommandRecord>,
+}
+
+/// 终端数据中识别出的 OSC 序列
+#[derive(Debug, PartialEq)]
+enum Osc {
+    Cwd { host: Option<String>, path: String },
+    PromptStart,
+    PromptEnd,
+    CommandStart,
+    CommandEnd(Option<i32>),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ParseState {
+    Ground,
+    Escape,
+    Osc,
+    /// OSC 内遇到 ESC，等待 `\` 组成 ST
+    OscEscape,
+}
+
+/// 流式 OSC 解析器，序列可以跨数据块
+struct OscParser {
+    state: ParseState,
+    buf: Vec<u8>,
+    overflow: bool,
+}
+
+impl OscParser {
+    fn new() -> Self {
+        OscParser { state: ParseState::Ground, buf: Vec::new(), overflow: false }
+    }
+
+    /// 返回识别出的序列及其结束处在本数据块中的位置
+    fn feed(&mut self, data: &[u8]) -> Vec<(Osc, usize)> {
+        let mut found = Vec::new();
+        for (i, &b) in data.iter().enumerate() {
+            self.state = match (self.state, b) {
+                (ParseState::Ground, 0x1b) => ParseState::Escape,
+                (ParseState::Ground, _) => ParseState::Ground,
+                (ParseState::Escape, b']') => {
+                    self.buf.clear();
+                    self.overflow = false;
+                    ParseState::Osc
+                }
+                (ParseState::Escape, 0x1b) => ParseState::Escape,
+                (ParseState::Escape, _) => ParseState::Ground,
+                (ParseState::Osc, 0x07) | (ParseState::OscEscape, b'\\') => {
+                    if !self.overflow {
+                        if let Some(osc) = parse_osc(&self.buf) {
+                            found.push((osc, i + 1));
+                        }
+                    }
+                    ParseState::Ground
+                }
+                (ParseState::Osc, 0x1b) => ParseState::OscEscape,
+                // 被其他转义序列打断
+                (ParseState::OscEscape, b']') => {
+                    self.buf.clear();
+                    self.overflow = false;
+                    ParseState::Osc
+                }
+                (ParseState::OscEscape, _) => ParseState::Ground,
+                (ParseState::Osc, _) => {
+                    if self.buf.len() < MAX_OSC {
+                        self.buf.push(b);
+                    } else {
+                        self.overflow = true;
+                    }
+                    ParseState::Osc
+                }
+            };
+        }
+        found
+    }
+}
+
+fn parse_osc(body: &[u8]) -> Option<Osc> {
+    let body = std::str::from_utf8(body).ok()?;
+    let (code, rest) = body.split_once(';')?;
+    match code {
+        "7" => parse_cwd_url(rest),
+        "133" => {
+            let mut parts = rest.split(';');
+            match parts.next()? {
+                "A" => Some(Osc::PromptStart),
+                "B" => Some(Osc::PromptEnd),
+                "C" => Some(Osc::CommandStart),
+                "D" => Some(Osc::CommandEnd(parts.next().and_then(|s| s.trim().parse().ok()))),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// 解析 `file://host/path`（路径可能经过百分号编码）
+fn parse_cwd_url(url: &str) -> Option<Osc> {
+    let rest = url.strip_prefix("file://").or_else(|| url.strip_prefix("kitty-shell-cwd://"))?;
+    let slash = rest.find('/')?;
+    let host = Some(rest[..slash].to_string()).filter(|h| !h.is_empty());
+    Some(Osc::Cwd { host, path: percent_decode(&rest[slash..]) })
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|h| std::str::from_utf8(h).ok());
+        if let Some(b) = hex.filter(|_| bytes[i] == b'%').and_then(|h| u8::from_str_radix(h, 16).ok()) {
+            out.push(b);
+            i += 3;
+            continue;
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).to_string()
+}
+
+/// 终端数据监听任务持有的解析状态
+pub struct ShellTracker {
+    parser: OscParser,
+    state: Arc<RwLock<ShellState>>,
+}
+
+impl ShellTracker {
+    pub fn new(state: Arc<RwLock<ShellState>>) -> Self {
+        ShellTracker { parser: OscParser::new(), state }
+    }
+
+    /// 处理一段即将显示到终端的数据，状态变化时发出 `ssh:cwd-changed` / `ssh:command-state`
+    pub async fn observe(&mut self, app: &AppHandle, session_id: &str, data: &[u8]) {
+        let marks = self.parser.feed(data);
+        let mut state = self.state.write().await;
+        let base = state.offset;
+        state.offset += data.len() as u64;
+        for (osc, end) in marks {
+            let offset = base + end as u64;
+            match osc {
+                Osc::Cwd { host, path } => {
+                    if state.cwd.as_deref() == Some(path.as_str()) && state.host == host {
+                        continue;
+                    }
+                    state.cwd = Some(path.clone());
+                    state.host = host.clone();
+                    let _ = app.emit("ssh:cwd-changed", CwdChangedEvent {
+                        session_id: session_id.to_string(),
+                        cwd: path,
+                        host,
+                    });
+                    continue;
+                }
+                Osc::PromptStart => {
+                    state.phase = ShellPhase::Prompt;
+                    state.prompt_offset = Some(offset);
+                }
+                Osc::PromptEnd => state.phase = ShellPhase::Input,
+                Osc::CommandStart => {
+                    if state.phase == ShellPhase::Running {
+                        continue;
+                    }
+                    state.phase = ShellPhase::Running;
+                    state.next_id += 1;
+                    let record = CommandRecord {
+                        id: state.next_id,
+                        cwd: state.cwd.clone(),
+                        started_at: chrono::Utc::now().to_rfc3339(),
+                        finished_at: None,
+                        exit_code: None,
+                        prompt_offset: state.prompt_offset.take(),
+                        output_offset: offset,
+                        end_offset: None,
+                    };
+                    if state.commands.len() >= MAX_COMMANDS {
+                        state.commands.pop_front();
+                    }
+                    state.commands.push_back(record);
+                }
+                Osc::CommandEnd(exit_code) => {
+                    // 提示符钩子在没有执行命令时也会发出 D，忽略
+                    let Some(record) = state.running() else { continue };
+                    record.finished_at = Some(chrono::Utc::now().to_rfc3339());
+                    record.exit_code = exit_code;
+                    record.end_offset = Some(offset);
+                    state.phase = ShellPhase::Prompt;
+                }
+            }
+            let command = match state.phase {
+                ShellPhase::Running => state.commands.back().cloned(),
+                _ => state.commands.back().filter(|c| c.end_offset == Some(offset)).cloned(),
+            };
+            let _ = app.emit("ssh:command-state", CommandStateEvent {
+                session_id: session_id.to_string(),
+                phase: state.phase,
+                command,
+            });
+        }
+    }
+}
+
+fn snippet(shell: &str) -> Result<&'static str, String> {
+    match shell {
+        "bash" => Ok(BASH_SNIPPET),
+        "zsh" => Ok(ZSH_SNIPPET),
+        "fish" => Ok(FISH_SNIPPET),
+        _ => Err(format!("Unsupported shell: {}", shell)),
+    }
+}
+
+/// 用远程登录 shell（$SHELL）推断 shell 类型
+async fn detect_shell(session: &crate::ssh::SshSession) -> Result<String, String> {
+    let mut channel = session.handle.channel_open_session().await.map_err(|e| e.to_string())?;
+    channel.exec(true, "echo \"$SHELL\"").await.map_err(|e| e.to_string())?;
+    let mut stdout = Vec::new();
+    while let Some(msg) = channel.wait().await {
+        match msg {
+            russh::ChannelMsg::Data { data } => stdout.extend_from_slice(&data),
+            russh::ChannelMsg::Close => break,
+            _ => {}
+        }
+    }
+    let path = String::from_utf8_lossy(&stdout).trim().to_string();
+    Ok(path.rsplit('/').next().unwrap_or_default().to_string())
+}
+
+/// 查询会话当前的工作目录与命令记录
+#[tauri::command]
+pub async fn ssh_shell_state(
+    manager: tauri::State<'_, SshManager>,
+    session_id: String,
+) -> Result<ShellState, String> {
+    let session = manager.0.read().await.get(&session_id).cloned()
+        .ok_or_else(|| format!("Session not found: {}", session_id))?;
+    let state = session.shell.read().await.clone();
+    Ok(state)
+}
+
+/// 返回 bash / zsh / fish 的集成脚本，供用户自行加入 rc 文件
+#[tauri::command]
+pub async fn ssh_shell_integration_script(shell: String) -> Result<String, String> {
+    snippet(&shell).map(|s| s.to_string())
+}
+
+/// 向交互 shell 注入集成脚本（仅对当前 shell 进程生效），返回使用的 shell 类型。
+/// shell 为空时根据远程 $SHELL 判断
+#[tauri::command]
+pub async fn ssh_inject_shell_integration(
+    manager: tauri::State<'_, SshManager>,
+    session_id: String,
+    shell: Option<String>,
+) -> Result<String, String> {
+    let session = manager.0.read().await.get(&session_id).cloned()
+        .ok_or_else(|| format!("Session not found: {}", session_id))?;
+    let shell = match shell.filter(|s| !s.is_empty()) {
+        Some(shell) => shell,
+        None => detect_shell(&session).await?,
+    };
+    let script = base64::engine::general_purpose::STANDARD.encode(snippet(&shell)?);
+    // 前导空格让 HISTCONTROL=ignorespace / HIST_IGNORE_SPACE 不记录这一行
+    let line = if shell == "fish" {
+        format!(" printf %s '{}' | base64 -d | source\r", script)
+    } else {
+        format!(" eval \"$(printf %s '{}' | base64 -d)\"\r", script)
+    };
+    let mut ch = session.channel.lock().await;
+    let channel = ch.as_mut().ok_or("Channel is closed")?;
+    channel.data(line.as_bytes()).await.map_err(|e| e.to_string())?;
+    Ok(shell)
+}
@@ -4,7 +4,11 @@ use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::{Mutex, RwLock};
-use tauri::{AppHandle, Emitter};
+use tauri::{AppHandle, Emitter, Manager};
+use crate::scrollback::{Scrollback, ScrollbackManager};
+use crate::shell_integration::{ShellState, ShellTracker};
+use crate::term_transfer::{Detector, TermTransferManager};
+use crate::triggers::{CompiledTrigger, TriggerRunner};
 use crate::db::DecryptedHostConfig;
 
 pub(crate) struct ClientHandler;
@@ -21,9 +25,16 @@ impl client::Handler for ClientHandler {
 }
 
 pub struct SshSession {
+    pub host_id: String,
     pub channel: Arc<Mutex<Option<russh::Channel<client::Msg>>>>,
     pub handle: russh::client::Handle<ClientHandler>,
     pub is_connected: Arc<std::sync::atomic::AtomicBool>,
+    /// 通过 OSC 7 / OSC 133 跟踪的工作目录与命令边界
+    pub shell: Arc<RwLock<ShellState>>,
+    /// 后端保留的终端输出，用于重新打开视图时回放和搜索
+    pub scrollback: Arc<RwLock<Scrollback>>,
+    /// 主机与全局的输出触发器，保存设置后重新编译
+    pub triggers: Arc<RwLock<Vec<CompiledTrigger>>>,
 }
 
 pub struct SshManager(pub RwLock<HashMap<String, Arc<SshSession>>>);
@@ -47,6 +58,28 @@ struct SshClosedEvent {
     session_id: String,
 }
 
+/// 把终端输出交给 shell 集成解析、写入回滚缓冲区、执行触发器后转发给前端
+async fn emit_data(
+    app: &AppHandle,
+    session_id: &str,
+    tracker: &mut ShellTracker,
+    scrollback: &RwLock<Scrollback>,
+    runner: &mut TriggerRunner,
+    data: &[u8],
+) {
+    if data.is_empty() {
+        return;
+    }
+    tracker.observe(app, session_id, data).await;
+    let limit = app.state::<ScrollbackManager>().limit();
+    scrollback.write().await.push(data, limit);
+    runner.observe(app, session_id, data).await;
+    let _ = app.emit("ssh:data", SshDataEvent {
+        session_id: session_id.to_string(),
+        data: String::from_utf8_lossy(data).to_string(),
+    });
+}
+
 /// 创建带有 keep-alive 的 SSH 客户端配置
 fn create_ssh_config() -> Arc<client::Config> {
     let config = client::Config {
@@ -175,12 +208,20 @@ pub async fn ssh_connect(
 
     let channel_arc = Arc::new(Mutex::new(Some(channel)));
     let is_connected = Arc::new(std::sync::atomic::AtomicBool::new(true));
+    let shell = Arc::new(RwLock::new(ShellState::default()));
+    let scrollback = Arc::new(RwLock::new(Scrollback::new()));
+    let global_triggers = crate::triggers::load_global(&app);
+    let triggers = Arc::new(RwLock::new(crate::triggers::compile_all(&host_config.triggers, &global_triggers)));
 
     // Create the session
     let ssh_session = Arc::new(SshSession {
+        host_id: host_config.id.clone(),
         channel: channel_arc.clone(),
         handle: session_handle,
         is_connected: is_connected.clone(),
+        shell: shell.clone(),
+        scrollback: scrollback.clone(),
+        triggers: triggers.clone(),
     });
 
     manager.0.write().await.insert(session_id.clone(), ssh_session);
@@ -195,6 +236,10 @@ pub async fn ssh_connect(
 
         // 用于追踪连续的错误次数
         let mut consecutive_errors = 0u32;
+        // 识别 rz/sz 与 trz/tsz 的起始序列
+        let mut detector = Detector::new();
+        let mut tracker = ShellTracker::new(shell);
+        let mut runner = TriggerRunner::new(triggers, channel_arc.clone());
         const MAX_ERRORS: u32 = 10;
 
         loop {
@@ -240,11 +285,22 @@ pub async fn ssh_connect(
             if let Some(msg) = msg_result {
                 match msg {
                     Some(russh::ChannelMsg::Data { data }) => {
-                        let text = String::from_utf8_lossy(&data).to_string();
-                        let _ = app_clone.emit("ssh:data", SshDataEvent {
-                            session_id: session_id_clone.clone(),
-                            data: text,
-                        });
+                        let Some(detection) = detector.feed(&data) else {
+                            emit_data(&app_clone, &session_id_clone, &mut tracker, &scrollback, &mut runner, &data).await;
+                            continue;
+                        };
+                        // 传输期间由协议接管通道，结束后把剩余输出交还终端
+                        emit_data(&app_clone, &session_id_clone, &mut tracker, &scrollback, &mut runner, &detection.before).await;
+                        let (rest, closed) =
+                            crate::term_transfer::run(&app_clone, &session_id_clone, channel_arc.clone(), detection).await;
+                        emit_data(&app_clone, &session_id_clone, &mut tracker, &scrollback, &mut runner, &rest).await;
+                        if closed {
+                            is_connected.store(false, std::sync::atomic::Ordering::Relaxed);
+                            let _ = app_clone.emit("ssh:closed", SshClosedEvent {
+                                session_id: session_id_clone.clone()
+                            });
+                            break;
+                        }
                     }
                     Some(russh::ChannelMsg::Eof) => {
                         eprintln!("[ssh_connect] Received EOF, waiting for close");
@@ -279,8 +335,9 @@ pub async fn ssh_connect(
             }
         }
 
-        // 清理：标记连接已断开
+        // 清理：标记连接已断开，并把触发器日志写入磁盘
         is_connected.store(false, std::sync::atomic::Ordering::Relaxed);
+        runner.close_log().await;
         eprintln!("[ssh_connect] Data listener exited for session {}", session_id_clone);
     });
 
@@ -289,7 +346,16 @@ pub async fn ssh_connect(
 }
 
 #[tauri::command]
-pub async fn ssh_input(manager: tauri::State<'_, SshManager>, session_id: String, data: String) -> Result<(), String> {
+pub async fn ssh_input(
+    manager: tauri::State<'_, SshManager>,
+    transfers: tauri::State<'_, TermTransferManager>,
+    session_id: String,
+    data: String,
+) -> Result<(), String> {
+    // 终端内文件传输期间不转发键盘输入
+    if transfers.intercept_input(&session_id, &data).await {
+        return Ok(());
+    }
     let map = manager.0.read().await;
     if let Some(session) = map.get(&session_id) {
         // 先检查连接状态
@@ -0,0 +1,256 @@
+use russh::client;
+use russh_sftp::client::{RawSftpSession, SftpSession};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+use tokio::sync::{oneshot, Mutex, RwLock};
+use crate::sftp::{exec_on_session, open_exec_channel, open_subsystem_sftp, sftp_get_host, shell_quote, SessionHostMap, SftpManager};
+
+/// sudo 的 -p 提示符，出现在 stderr 上表示需要输入密码
+const PASSWORD_MARKER: &str = "[sftp-sudo-password]";
+/// 认证通过后由 sh 输出，之后通道上就是 SFTP 协议数据
+const READY_MARKER: &str = "[sftp-sudo-ready]";
+/// 等待前端输入密码的最长时间
+const PROMPT_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// 依次尝试 sshd_config 中配置的 Subsystem 路径和各发行版的默认位置
+const DETECT_SCRIPT: &str = r#"p=$(awk '$1=="Subsystem" && $2=="sftp" {print $3}' /etc/ssh/sshd_config 2>/dev/null)
+case "$p" in /*) [ -x "$p" ] && { echo "$p"; exit 0; };; esac
+for p in /usr/lib/openssh/sftp-server /usr/libexec/openssh/sftp-server /usr/lib/ssh/sftp-server /usr/libexec/sftp-server /usr/lib/sftp-server /usr/local/libexec/sftp-server; do
+  [ -x "$p" ] && { echo "$p"; exit 0; }
+done
+command -v sftp-server"#;
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SudoStatus {
+    pub session_id: String,
+    pub elevated: bool,
+    pub server_path: Option<String>,
+}
+
+/// `sftp:sudo-prompt` 事件负载，前端用 `sftp_sudo_answer` 回复
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct SudoPromptEvent {
+    prompt_id: String,
+    session_id: String,
+    username: String,
+    /// 上一次输入的密码被 sudo 拒绝
+    retry: bool,
+}
+
+pub struct SudoManager {
+    /// promptId -> 等待前端输入的密码（None 表示取消）
+    prompts: RwLock<HashMap<String, oneshot::Sender<Option<String>>>>,
+    /// sessionId -> 以 root 运行的 sftp-server 路径
+    elevated: RwLock<HashMap<String, String>>,
+}
+
+impl SudoManager {
+    pub fn new() -> Self {
+        SudoManager {
+            prompts: RwLock::new(HashMap::new()),
+            elevated: RwLock::new(HashMap::new()),
+        }
+    }
+}
+
+async fn detect_server_path(mgr: &SftpManager, session_id: &str) -> Result<String, String> {
+    let (code, stdout) = exec_on_session(mgr, session_id, DETECT_SCRIPT).await?;
+    let path = String::from_utf8_lossy(&stdout).trim().to_string();
+    if code != 0 || path.is_empty() {
+        return Err("sftp-server not found on the server".to_string());
+    }
+    Ok(path)
+}
+
+async fn ask_password(
+    app: &AppHandle,
+    sudo: &SudoManager,
+    session_id: &str,
+    username: &str,
+    retry: bool,
+) -> Result<String, String> {
+    let prompt_id = uuid::Uuid::new_v4().to_string();
+    let (tx, rx) = oneshot::channel();
+    sudo.prompts.write().await.insert(prompt_id.clone(), tx);
+    let _ = app.emit("sftp:sudo-prompt", SudoPromptEvent {
+        prompt_id: prompt_id.clone(),
+        session_id: session_id.to_string(),
+        username: username.to_string(),
+        retry,
+    });
+    let answer = tokio::time::timeout(PROMPT_TIMEOUT, rx).await;
+    sudo.prompts.write().await.remove(&prompt_id);
+    match answer {
+        Ok(Ok(Some(password))) => Ok(password),
+        Ok(Ok(None)) | Ok(Err(_)) => Err("Cancelled".to_string()),
+        Err(_) => Err("sudo password prompt timed out".to_string()),
+    }
+}
+
+/// 在 exec 通道上运行 `sudo -S sftp-server`，按需输入密码，认证通过后返回该通道（之后是 SFTP 协议数据）
+/// 以及被接受的密码。密码优先使用 known（上一次被接受的）或主机保存的登录密码，
+/// 被拒绝或没有时通过 `sftp:sudo-prompt` 向前端询问
+async fn open_sudo_channel(
+    app: &AppHandle,
+    mgr: &SftpManager,
+    sudo: &SudoManager,
+    session_id: &str,
+    host: &crate::db::DecryptedHostConfig,
+    server_path: &str,
+    known: Option<String>,
+) -> Result<(russh::Channel<client::Msg>, Option<String>), String> {
+    let command = format!(
+        "sudo -S -p {} sh -c {}",
+        shell_quote(PASSWORD_MARKER),
+        shell_quote(&format!("echo {}; exec {}", shell_quote(READY_MARKER), shell_quote(server_path))),
+    );
+    let mut channel = open_exec_channel(mgr, session_id, &command).await?;
+
+    let mut stored = known.or_else(|| host.password.clone()).filter(|p| !p.is_empty());
+    let mut accepted = None;
+    let mut stdout = String::new();
+    let mut stderr = String::new();
+    let mut prompts_seen = 0;
+    loop {
+        let Some(msg) = channel.wait().await else { break };
+        match msg {
+            russh::ChannelMsg::Data { data } => {
+                stdout.push_str(&String::from_utf8_lossy(&data));
+                if stdout.contains(READY_MARKER) {
+                    return Ok((channel, accepted));
+                }
+            }
+            russh::ChannelMsg::ExtendedData { data, ext: 1 } => {
+                stderr.push_str(&String::from_utf8_lossy(&data));
+                // 每出现一次提示符就需要一次密码，sudo 默认最多尝试三次
+                while stderr.matches(PASSWORD_MARKER).count() > prompts_seen {
+                    let retry = prompts_seen > 0;
+                    prompts_seen += 1;
+                    let password = match stored.take() {
+                        Some(password) if !retry => password,
+                        _ => match ask_password(app, sudo, session_id, &host.username, retry).await {
+                            Ok(password) => password,
+                            Err(e) => {
+                                let _ = channel.close().await;
+                                return Err(e);
+                            }
+                        },
+                    };
+                    channel
+                        .data(format!("{}\n", password).as_bytes())
+                        .await
+                        .map_err(|e| e.to_string())?;
+                    accepted = Some(password);
+                }
+            }
+            russh::ChannelMsg::Close => break,
+            _ => {}
+        }
+    }
+
+    let message = stderr.replace(PASSWORD_MARKER, "").trim().to_string();
+    if message.is_empty() {
+        Err("sudo exited before starting sftp-server".to_string())
+    } else {
+        Err(format!("sudo failed: {}", message))
+    }
+}
+
+/// 把会话的 SFTP 切换为以 root 运行。只影响 SFTP 操作，搜索/压缩传输等 exec 命令仍以登录用户执行
+#[tauri::command]
+pub async fn sftp_sudo_enable(
+    app: AppHandle,
+    host_map: tauri::State<'_, SessionHostMap>,
+    mgr: tauri::State<'_, SftpManager>,
+    sudo: tauri::State<'_, SudoManager>,
+    session_id: String,
+    server_path: Option<String>,
+) -> Result<SudoStatus, String> {
+    // 确保连接已建立
+    let (_, host) = sftp_get_host(&app, &host_map, &mgr, &session_id).await?;
+    let server_path = match server_path.filter(|p| !p.is_empty()) {
+        Some(path) => path,
+        None => detect_server_path(&mgr, &session_id).await?,
+    };
+    let (channel, password) = open_sudo_channel(&app, &mgr, &sudo, &session_id, &host, &server_path, None).await?;
+    let sftp = SftpSession::new(channel.into_stream()).await.map_err(|e| e.to_string())?;
+
+    // 扩展请求（posix-rename、check-file）也要以 root 执行，另开一个通道保留原始会话。
+    // 打不开时不声明任何扩展，保存等操作退回到 SftpSession 自身的 rename
+    let (raw, extensions) = match open_sudo_channel(&app, &mgr, &sudo, &session_id, &host, &server_path, password).await {
+        Ok((channel, _)) => {
+            let raw = RawSftpSession::new(channel.into_stream());
+            match raw.init().await {
+                Ok(version) => (Some(Arc::new(raw)), version.extensions),
+                Err(e) => {
+                    log::warn!("Elevated SFTP extensions unavailable for {}: {}", session_id, e);
+                    (None, HashMap::new())
+                }
+            }
+        }
+        Err(e) => {
+            log::warn!("Elevated SFTP extensions unavailable for {}: {}", session_id, e);
+            (None, HashMap::new())
+        }
+    };
+
+    // 进行中的传输仍持有旧会话，完成后自然释放
+    mgr.sessions.write().await.insert(session_id.clone(), Arc::new(Mutex::new(sftp)));
+    mgr.owner_names.write().await.remove(&session_id);
+    mgr.extensions.write().await.insert(session_id.clone(), Arc::new(extensions));
+    match raw {
+        Some(raw) => mgr.elevated_raw.write().await.insert(session_id.clone(), raw),
+        None => mgr.elevated_raw.write().await.remove(&session_id),
+    };
+    sudo.elevated.write().await.insert(session_id.clone(), server_path.clone());
+
+    Ok(SudoStatus { session_id, elevated: true, server_path: Some(server_path) })
+}
+
+/// 恢复为以登录用户运行的普通 SFTP 子系统
+#[tauri::command]
+pub async fn sftp_sudo_disable(
+    mgr: tauri::State<'_, SftpManager>,
+    sudo: tauri::State<'_, SudoManager>,
+    session_id: String,
+) -> Result<SudoStatus, String> {
+    if sudo.elevated.write().await.remove(&session_id).is_some() {
+        let sftp = open_subsystem_sftp(&mgr, &session_id).await?;
+        mgr.sessions.write().await.insert(session_id.clone(), Arc::new(Mutex::new(sftp)));
+        mgr.owner_names.write().await.remove(&session_id);
+        mgr.extensions.write().await.remove(&session_id);
+        mgr.elevated_raw.write().await.remove(&session_id);
+    }
+    Ok(SudoStatus { session_id, elevated: false, server_path: None })
+}
+
+#[tauri::command]
+pub async fn sftp_sudo_status(
+    sudo: tauri::State<'_, SudoManager>,
+    session_id: String,
+) -> Result<SudoStatus, String> {
+    let server_path = sudo.elevated.read().await.get(&session_id).cloned();
+    Ok(SudoStatus { session_id, elevated: server_path.is_some(), server_path })
+}
+
+/// 回复 `sftp:sudo-prompt`；password 为空表示取消
+#[tauri::command]
+pub async fn sftp_sudo_answer(
+    sudo: tauri::State<'_, SudoManager>,
+    prompt_id: String,
+    password: Option<String>,
+) -> Result<(), String> {
+    let tx = sudo.prompts.write().await.remove(&prompt_id).ok_or("Prompt not found")?;
+    let _ = tx.send(password);
+    Ok(())
+}
+
+/// 会话断开时清除提权状态
+pub async fn close_session(sudo: &SudoManager, session_id: &str) {
+    sudo.elevated.write().await.remove(session_id);
+}
@@ -0,0 +1,571 @@
+use russh_sftp::client::SftpSession;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tauri::{AppHandle, Manager};
+use tauri_plugin_store::StoreExt;
+use tokio::sync::Mutex;
+use crate::queue::{QueuedTransfer, TransferDirection, TransferOptions, TransferQueue};
+use crate::sftp::{
+    acquire_slot, begin_transfer, download_file, end_transfer, join_remote, sftp_get_host,
+    system_time_secs, upload_file, ConflictPolicy, SessionHostMap, SftpManager, TransferCtx,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum SyncMode {
+    /// 以本地为准，远程多出的内容可删除
+    MirrorToRemote,
+    /// 以远程为准，本地多出的内容可删除
+    MirrorToLocal,
+    /// 双向同步，两端都修改过的文件记为冲突
+    TwoWay,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum CompareMethod {
+    #[default]
+    SizeMtime,
+    /// 大小相同时再比较哈希（check-file 扩展或 exec sha256sum）
+    Checksum,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncOptions {
+    pub mode: SyncMode,
+    #[serde(default)]
+    pub compare: CompareMethod,
+    /// 镜像时删除目标端多余的内容；双向同步时传播删除
+    #[serde(default = "default_true")]
+    pub delete: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum SyncActionKind {
+    MkdirLocal,
+    MkdirRemote,
+    Upload,
+    Download,
+    DeleteLocal,
+    DeleteRemote,
+    /// 需要在执行时通过 resolutions 指定为 upload 或 download，否则跳过
+    Conflict,
+}
+
+/// 某一端条目的大小与修改时间（毫秒，与 FileEntry 一致）
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncSide {
+    pub size: u64,
+    pub mtime: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncAction {
+    pub kind: SyncActionKind,
+    /// 相对于同步根目录的路径，使用 `/` 分隔
+    pub path: String,
+    pub is_dir: bool,
+    pub local: Option<SyncSide>,
+    pub remote: Option<SyncSide>,
+    pub reason: String,
+}
+
+/// 试运行结果，原样传回 `sftp_sync_execute` 执行
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncPlan {
+    pub session_id: String,
+    pub local_root: String,
+    pub remote_root: String,
+    pub mode: SyncMode,
+    pub actions: Vec<SyncAction>,
+    pub upload_bytes: u64,
+    pub download_bytes: u64,
+    pub conflicts: usize,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncReport {
+    pub applied: usize,
+    /// 未指定处理方式而跳过的冲突
+    pub skipped: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Stamp {
+    pub(crate) is_dir: bool,
+    pub(crate) size: u64,
+    pub(crate) mtime: u64,
+}
+
+impl Stamp {
+    pub(crate) fn side(&self) -> SyncSide {
+        SyncSide { size: self.size, mtime: self.mtime * 1000 }
+    }
+
+    /// 与上次同步时相比是否有变化（目录只看类型）
+    fn changed_since(&self, base: &Stamp) -> bool {
+        self.is_dir != base.is_dir || (!self.is_dir && (self.size != base.size || self.mtime != base.mtime))
+    }
+}
+
+/// 上次成功同步后两端的状态，用于双向同步区分“修改”与“删除”
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+struct BaseEntry {
+    local: Stamp,
+    remote: Stamp,
+}
+
+pub(crate) type Tree = BTreeMap<String, Stamp>;
+type Baseline = BTreeMap<String, BaseEntry>;
+
+pub(crate) fn local_join(root: &str, rel: &str) -> String {
+    let mut path = PathBuf::from(root);
+    for part in rel.split('/') {
+        path.push(part);
+    }
+    path.to_string_lossy().to_string()
+}
+
+/// 扫描本地目录树，符号链接不参与同步
+pub(crate) async fn scan_local(root: &str) -> Result<Tree, String> {
+    let mut tree = Tree::new();
+    if !tokio::fs::try_exists(root).await.unwrap_or(false) {
+        return Ok(tree);
+    }
+    let mut stack = vec![(PathBuf::from(root), String::new())];
+    while let Some((dir, rel_dir)) = stack.pop() {
+        let mut rd = tokio::fs::read_dir(&dir).await.map_err(|e| e.to_string())?;
+        while let Some(entry) = rd.next_entry().await.map_err(|e| e.to_string())? {
+            let meta = entry.metadata().await.map_err(|e| e.to_string())?;
+            let rel = join_remote(&rel_dir, &entry.file_name().to_string_lossy());
+            if meta.is_dir() {
+                stack.push((entry.path(), rel.clone()));
+                tree.insert(rel, Stamp { is_dir: true, size: 0, mtime: 0 });
+            } else if meta.is_file() {
+                let mtime = system_time_secs(meta.modified());
+                tree.insert(rel, Stamp { is_dir: false, size: meta.len(), mtime });
+            }
+        }
+    }
+    Ok(tree)
+}
+
+/// 扫描远程目录树，只收集普通文件与目录
+pub(crate) async fn scan_remote(sftp_arc: &Arc<Mutex<SftpSession>>, root: &str) -> Result<Tree, String> {
+    let mut tree = Tree::new();
+    {
+        let sftp = sftp_arc.lock().await;
+        if !sftp.try_exists(root).await.map_err(|e| e.to_string())? {
+            return Ok(tree);
+        }
+    }
+    let mut stack = vec![String::new()];
+    while let Some(rel_dir) = stack.pop() {
+        let entries = {
+            let sftp = sftp_arc.lock().await;
+            let dir = if rel_dir.is_empty() { root.to_string() } else { join_remote(root, &rel_dir) };
+            sftp.read_dir(dir.as_str()).await.map_err(|e| e.to_string())?
+        };
+        for entry in entries {
+            let metadata = entry.metadata();
+            let rel = join_remote(&rel_dir, &entry.file_name());
+            let file_type = metadata.file_type();
+            if file_type.is_dir() {
+                stack.push(rel.clone());
+                tree.insert(rel, Stamp { is_dir: true, size: 0, mtime: 0 });
+            } else if file_type.is_file() {
+                let stamp = Stamp {
+                    is_dir: false,
+                    size: metadata.size.unwrap_or(0),
+                    mtime: metadata.mtime.unwrap_or(0) as u64,
+                };
+                tree.insert(rel, stamp);
+            }
+        }
+    }
+    Ok(tree)
+}
+
+fn baseline_key(host_id: &str, local_root: &str, remote_root: &str) -> String {
+    format!("{}|{}|{}", host_id, local_root, remote_root)
+}
+
+fn load_baseline(app: &AppHandle, key: &str) -> Baseline {
+    app.store(PathBuf::from("sync.json"))
+        .ok()
+        .and_then(|store| store.get("baselines"))
+        .and_then(|v| serde_json::from_value::<HashMap<String, Baseline>>(v).ok())
+        .and_then(|mut all| all.remove(key))
+        .unwrap_or_default()
+}
+
+fn save_baseline(app: &AppHandle, key: &str, baseline: Baseline) -> Result<(), String> {
+    let store = app.store(PathBuf::from("sync.json")).map_err(|e| e.to_string())?;
+    let mut all: HashMap<String, Baseline> = store
+        .get("baselines")
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default();
+    all.insert(key.to_string(), baseline);
+    store.set("baselines", serde_json::to_value(&all).map_err(|e| e.to_string())?);
+    let _ = store.save();
+    Ok(())
+}
+
+/// 两端文件内容是否一致
+async fn same_content(
+    mgr: &SftpManager,
+    session_id: &str,
+    compare: CompareMethod,
+    local_path: &str,
+    remote_path: &str,
+    l: &Stamp,
+    r: &Stamp,
+) -> bool {
+    if l.size != r.size {
+        return false;
+    }
+    match compare {
+        // 部分文件系统的时间精度为 1~2 秒
+        CompareMethod::SizeMtime => l.mtime.abs_diff(r.mtime) <= 1,
+        CompareMethod::Checksum => crate::checksum::verify(mgr, session_id, remote_path, local_path)
+            .await
+            .map(|v| v.matched)
+            .unwrap_or(false),
+    }
+}
+
+fn action(kind: SyncActionKind, path: &str, l: Option<&Stamp>, r: Option<&Stamp>, reason: &str) -> SyncAction {
+    SyncAction {
+        kind,
+        path: path.to_string(),
+        is_dir: l.or(r).map(|s| s.is_dir).unwrap_or(false),
+        local: l.filter(|s| !s.is_dir).map(Stamp::side),
+        remote: r.filter(|s| !s.is_dir).map(Stamp::side),
+        reason: reason.to_string(),
+    }
+}
+
+/// 只在一端存在的条目：复制到另一端，或删除
+fn one_sided(
+    path: &str,
+    stamp: &Stamp,
+    local_side: bool,
+    options: &SyncOptions,
+    base: Option<&BaseEntry>,
+) -> Option<SyncAction> {
+    use SyncActionKind::*;
+    let (copy, delete) = match (local_side, stamp.is_dir) {
+        (true, true) => (MkdirRemote, DeleteLocal),
+        (true, false) => (Upload, DeleteLocal),
+        (false, true) => (MkdirLocal, DeleteRemote),
+        (false, false) => (Download, DeleteRemote),
+    };
+    let (l, r) = if local_side { (Some(stamp), None) } else { (None, Some(stamp)) };
+    let source_is_local = options.mode == SyncMode::MirrorToRemote;
+
+    match options.mode {
+        SyncMode::MirrorToRemote | SyncMode::MirrorToLocal => {
+            if local_side == source_is_local {
+                Some(action(copy, path, l, r, "missing on target"))
+            } else if options.delete {
+                Some(action(delete, path, l, r, "not on source"))
+            } else {
+                None
+            }
+        }
+        SyncMode::TwoWay => {
+            let base = base.filter(|_| options.delete);
+            let base_stamp = base.map(|b| if local_side { b.local } else { b.remote });
+            match base_stamp {
+                None => Some(action(copy, path, l, r, "new")),
+                Some(b) if !stamp.changed_since(&b) => {
+                    let reason = if local_side { "deleted on remote" } else { "deleted locally" };
+                    Some(action(delete, path, l, r, reason))
+                }
+                Some(_) => {
+                    let reason = if local_side {
+                        "modified locally, deleted on remote"
+                    } else {
+                        "modified on remote, deleted locally"
+                    };
+                    Some(action(Conflict, path, l, r, reason))
+                }
+            }
+        }
+    }
+}
+
+/// 执行顺序：建目录（由浅到深）→ 传输 → 冲突 → 删文件 → 删目录（由深到浅）
+fn phase(a: &SyncAction) -> u8 {
+    match a.kind {
+        SyncActionKind::MkdirLocal | SyncActionKind::MkdirRemote => 0,
+        SyncActionKind::Upload | SyncActionKind::Download => 1,
+        SyncActionKind::Conflict => 2,
+        SyncActionKind::DeleteLocal | SyncActionKind::DeleteRemote if !a.is_dir => 3,
+        _ => 4,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn build_plan(
+    mgr: &SftpManager,
+    session_id: &str,
+    local_root: &str,
+    remote_root: &str,
+    options: &SyncOptions,
+    local: &Tree,
+    remote: &Tree,
+    baseline: &Baseline,
+) -> Vec<SyncAction> {
+    use SyncActionKind::*;
+    let paths: BTreeSet<&String> = local.keys().chain(remote.keys()).collect();
+    let mut actions = Vec::new();
+
+    for path in paths {
+        let (l, r) = (local.get(path), remote.get(path));
+        let base = baseline.get(path);
+        let next = match (l, r) {
+            (Some(ls), Some(rs)) if ls.is_dir && rs.is_dir => None,
+            (Some(ls), Some(rs)) if ls.is_dir != rs.is_dir => {
+                Some(action(Conflict, path, l, r, "file on one side, directory on the other"))
+            }
+            (Some(ls), Some(rs)) => {
+                let local_path = local_join(local_root, path);
+                let remote_path = join_remote(remote_root, path);
+                if same_content(mgr, session_id, options.compare, &local_path, &remote_path, ls, rs).await {
+                    None
+                } else {
+                    match options.mode {
+                        SyncMode::MirrorToRemote => Some(action(Upload, path, l, r, "differs")),
+                        SyncMode::MirrorToLocal => Some(action(Download, path, l, r, "differs")),
+                        SyncMode::TwoWay => {
+                            let (lc, rc) = match base {
+                                Some(b) => (ls.changed_since(&b.local), rs.changed_since(&b.remote)),
+                                None => (true, true),
+                            };
+                            match (lc, rc) {
+                                (true, false) => Some(action(Upload, path, l, r, "modified locally")),
+                                (false, true) => Some(action(Download, path, l, r, "modified on remote")),
+                                _ => Some(action(Conflict, path, l, r, "modified on both sides")),
+                            }
+                        }
+                    }
+                }
+            }
+            (Some(ls), None) => one_sided(path, ls, true, options, base),
+            (None, Some(rs)) => one_sided(path, rs, false, options, base),
+            (None, None) => None,
+        };
+        actions.extend(next);
+    }
+
+    // 待删除的目录里若还有要保留的内容，改为在另一端补建目录
+    let kept: Vec<(String, SyncActionKind)> = actions
+        .iter()
+        .filter(|a| !matches!(a.kind, DeleteLocal | DeleteRemote))
+        .map(|a| (a.path.clone(), a.kind))
+        .collect();
+    for a in actions.iter_mut().filter(|a| a.is_dir && matches!(a.kind, DeleteLocal | DeleteRemote)) {
+        let prefix = format!("{}/", a.path);
+        if kept.iter().any(|(p, _)| p.starts_with(&prefix)) {
+            a.kind = if a.kind == DeleteLocal { MkdirRemote } else { MkdirLocal };
+            a.reason = "contains changes".to_string();
+        }
+    }
+
+    actions.sort_by(|a, b| {
+        phase(a).cmp(&phase(b)).then_with(|| {
+            if phase(a) == 4 { b.path.cmp(&a.path) } else { a.path.cmp(&b.path) }
+        })
+    });
+    actions
+}
+
+/// 比较两端并生成同步计划（试运行，不做任何修改）
+#[tauri::command]
+pub async fn sftp_sync_plan(
+    app: AppHandle,
+    host_map: tauri::State<'_, SessionHostMap>,
+    mgr: tauri::State<'_, SftpManager>,
+    session_id: String,
+    local_root: String,
+    remote_root: String,
+    options: SyncOptions,
+) -> Result<SyncPlan, String> {
+    let (sftp_arc, host) = sftp_get_host(&app, &host_map, &mgr, &session_id).await?;
+    let local = scan_local(&local_root).await?;
+    let remote = scan_remote(&sftp_arc, &remote_root).await?;
+    let baseline = load_baseline(&app, &baseline_key(&host.id, &local_root, &remote_root));
+
+    let actions = build_plan(
+        &mgr, &session_id, &local_root, &remote_root, &options, &local, &remote, &baseline,
+    )
+    .await;
+    let size_of = |side: Option<SyncSide>| side.map(|s| s.size).unwrap_or(0);
+    Ok(SyncPlan {
+        upload_bytes: actions
+            .iter()
+            .filter(|a| a.kind == SyncActionKind::Upload)
+            .map(|a| size_of(a.local))
+            .sum(),
+        download_bytes: actions
+            .iter()
+            .filter(|a| a.kind == SyncActionKind::Download)
+            .map(|a| size_of(a.remote))
+            .sum(),
+        conflicts: actions.iter().filter(|a| a.kind == SyncActionKind::Conflict).count(),
+        session_id,
+        local_root,
+        remote_root,
+        mode: options.mode,
+        actions,
+    })
+}
+
+async fn apply_action(
+    mgr: &SftpManager,
+    sftp_arc: &Arc<Mutex<SftpSession>>,
+    ctx: &mut TransferCtx,
+    plan: &SyncPlan,
+    kind: SyncActionKind,
+    a: &SyncAction,
+) -> Result<(), String> {
+    let local_path = local_join(&plan.local_root, &a.path);
+    let remote_path = join_remote(&plan.remote_root, &a.path);
+    match kind {
+        SyncActionKind::MkdirLocal => tokio::fs::create_dir_all(&local_path).await.map_err(|e| e.to_string()),
+        SyncActionKind::MkdirRemote => {
+            let sftp = sftp_arc.lock().await;
+            if !sftp.try_exists(remote_path.as_str()).await.map_err(|e| e.to_string())? {
+                sftp.create_dir(remote_path.as_str()).await.map_err(|e| e.to_string())?;
+            }
+            Ok(())
+        }
+        SyncActionKind::Upload => upload_file(mgr, sftp_arc, ctx, &local_path, remote_path).await,
+        SyncActionKind::Download => download_file(mgr, sftp_arc, ctx, &remote_path, local_path).await,
+        SyncActionKind::DeleteLocal if a.is_dir => tokio::fs::remove_dir(&local_path).await.map_err(|e| e.to_string()),
+        SyncActionKind::DeleteLocal => tokio::fs::remove_file(&local_path).await.map_err(|e| e.to_string()),
+        SyncActionKind::DeleteRemote => {
+            let sftp = sftp_arc.lock().await;
+            if a.is_dir {
+                sftp.remove_dir(remote_path.as_str()).await.map_err(|e| e.to_string())
+            } else {
+                sftp.remove_file(remote_path.as_str()).await.map_err(|e| e.to_string())
+            }
+        }
+        SyncActionKind::Conflict => Ok(()),
+    }
+}
+
+/// 冲突只能解决为来源存在的上传或下载；一侧已删除时也可以接受删除
+fn resolve(a: &SyncAction, resolutions: &HashMap<String, SyncActionKind>) -> Option<SyncActionKind> {
+    if a.kind != SyncActionKind::Conflict {
+        return Some(a.kind);
+    }
+    resolutions.get(&a.path).copied().filter(|k| {
+        !a.is_dir
+            && match k {
+                SyncActionKind::Upload => a.local.is_some(),
+                SyncActionKind::Download => a.remote.is_some(),
+                SyncActionKind::DeleteLocal => a.local.is_some() && a.remote.is_none(),
+                SyncActionKind::DeleteRemote => a.remote.is_some() && a.local.is_none(),
+                _ => false,
+            }
+    })
+}
+
+/// 执行同步计划；进度、暂停与取消沿用传输队列（transferId）
+#[tauri::command]
+pub async fn sftp_sync_execute(
+    app: AppHandle,
+    host_map: tauri::State<'_, SessionHostMap>,
+    mgr: tauri::State<'_, SftpManager>,
+    transfer_id: String,
+    plan: SyncPlan,
+    resolutions: Option<HashMap<String, SyncActionKind>>,
+    verify: Option<bool>,
+) -> Result<SyncReport, String> {
+    let resolutions = resolutions.unwrap_or_default();
+    let host_id = host_map.0.read().await.get(&plan.session_id).cloned().ok_or("Session not found")?;
+    // 比较依赖 mtime，同步时总是保留时间戳；冲突已在计划阶段决定
+    let options = TransferOptions {
+        conflict_policy: Some(ConflictPolicy::Overwrite),
+        preserve: Some(true),
+        verify,
+        archive: None,
+    };
+    if let Some(queue) = app.try_state::<TransferQueue>() {
+        queue.upsert(&app, QueuedTransfer::new(
+            &transfer_id, &plan.session_id, &host_id, TransferDirection::Sync,
+            &plan.local_root, &plan.remote_root, options.clone(),
+        ));
+    }
+
+    let mut report = SyncReport::default();
+    let mut ctx = begin_transfer(&app, &mgr, &plan.session_id, &transfer_id, &options).await;
+    let result = async {
+        let (sftp_arc, _) = sftp_get_host(&app, &host_map, &mgr, &plan.session_id).await?;
+        let _slot = acquire_slot(&app, &mgr, &mut ctx, 0).await?;
+
+        ctx.total = plan
+            .actions
+            .iter()
+            .map(|a| match resolve(a, &resolutions) {
+                Some(SyncActionKind::Upload) => a.local.map(|s| s.size).unwrap_or(0),
+                Some(SyncActionKind::Download) => a.remote.map(|s| s.size).unwrap_or(0),
+                _ => 0,
+            })
+            .sum();
+
+        // 目标根目录可能尚不存在
+        let kinds: Vec<SyncActionKind> = plan.actions.iter().filter_map(|a| resolve(a, &resolutions)).collect();
+        if kinds.iter().any(|k| matches!(k, SyncActionKind::Upload | SyncActionKind::MkdirRemote)) {
+            let sftp = sftp_arc.lock().await;
+            if !sftp.try_exists(plan.remote_root.as_str()).await.map_err(|e| e.to_string())? {
+                sftp.create_dir(plan.remote_root.as_str()).await.map_err(|e| e.to_string())?;
+            }
+        }
+        if kinds.iter().any(|k| matches!(k, SyncActionKind::Download | SyncActionKind::MkdirLocal)) {
+            tokio::fs::create_dir_all(&plan.local_root).await.map_err(|e| e.to_string())?;
+        }
+
+        for a in &plan.actions {
+            ctx.checkpoint().await?;
+            let Some(kind) = resolve(a, &resolutions) else {
+                report.skipped.push(a.path.clone());
+                continue;
+            };
+            apply_action(&mgr, &sftp_arc, &mut ctx, &plan, kind, a).await?;
+            report.applied += 1;
+        }
+
+        // 成功后记录两端一致的条目作为下次双向同步的基线
+        let local = scan_local(&plan.local_root).await?;
+        let remote = scan_remote(&sftp_arc, &plan.remote_root).await?;
+        let baseline: Baseline = local
+            .iter()
+            .filter_map(|(path, l)| {
+                let r = remote.get(path)?;
+                let same = l.is_dir == r.is_dir && (l.is_dir || (l.size == r.size && l.mtime.abs_diff(r.mtime) <= 1));
+                same.then(|| (path.clone(), BaseEntry { local: *l, remote: *r }))
+            })
+            .collect();
+        save_baseline(&app, &baseline_key(&host_id, &plan.local_root, &plan.remote_root), baseline)
+    }
+    .await;
+
+    end_transfer(&mgr, ctx, result).await?;
+    Ok(report)
+}
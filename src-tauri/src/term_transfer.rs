@@ -0,0 +1,496 @@
+use regex::bytes::Regex;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+use russh::client;
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::{oneshot, Mutex, RwLock};
+use crate::sftp::{unique_path, TransferSide};
+
+/// 检测时在数据块之间保留的字节数，用于匹配被拆开的起始序列
+const TAIL: usize = 128;
+/// 进度事件的最小间隔
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+/// 等待前端回复 `ssh:transfer-request` 的最长时间，超时后取消传输并交还终端
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(120);
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum Protocol {
+    Zmodem,
+    Trzsz,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum Direction {
+    /// 远程运行 rz / trz，由本机选择文件发送
+    Upload,
+    /// 远程运行 sz / tsz，由本机选择保存目录
+    Download,
+}
+
+/// 终端输出中检测到的传输请求
+pub struct Detection {
+    pub protocol: Protocol,
+    pub direction: Direction,
+    /// trzsz 请求传输目录（trz -d），目前不支持
+    pub directory: bool,
+    /// 本数据块中位于起始序列之前、仍应显示在终端里的内容
+    pub before: Vec<u8>,
+    /// 从起始序列开始（ZMODEM）或紧随其后（trzsz）的数据，交给协议处理
+    pub initial: Vec<u8>,
+}
+
+fn trzsz_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"::TRZSZ:TRANSFER:([SRD]):(\d+\.\d+\.\d+)(:\d+)?").unwrap())
+}
+
+/// 在终端数据流中查找 ZMODEM（`**\x18B00` / `**\x18B01`）与 trzsz 的起始序列
+pub struct Detector {
+    tail: Vec<u8>,
+    /// 最近一次 trzsz 请求的唯一标识，避免回显或重绘时重复触发
+    last_trzsz: Option<Vec<u8>>,
+}
+
+impl Detector {
+    pub fn new() -> Self {
+        Detector { tail: Vec::new(), last_trzsz: None }
+    }
+
+    pub fn feed(&mut self, data: &[u8]) -> Option<Detection> {
+        let mut combined = std::mem::take(&mut self.tail);
+        let offset = combined.len();
+        combined.extend_from_slice(data);
+
+        let detection = Self::find_zmodem(&combined).or_else(|| self.find_trzsz(&combined));
+        let result = detection.map(|(protocol, direction, directory, start, protocol_start)| Detection {
+            protocol,
+            direction,
+            directory,
+            before: data[..start.saturating_sub(offset).min(data.len())].to_vec(),
+            initial: combined[protocol_start..].to_vec(),
+        });
+        if result.is_none() {
+            let keep = combined.len().saturating_sub(TAIL);
+            self.tail = combined.split_off(keep);
+        }
+        result
+    }
+
+    /// 返回 (协议, 方向, 是否目录, 起始序列位置, 协议数据起点)
+    fn find_zmodem(data: &[u8]) -> Option<(Protocol, Direction, bool, usize, usize)> {
+        const START: &[u8] = b"**\x18B0";
+        let pos = data.windows(START.len() + 1).position(|w| w.starts_with(START) && (w[5] == b'0' || w[5] == b'1'))?;
+        // sz 发出 ZRQINIT（类型 00），rz 发出 ZRINIT（类型 01）
+        let direction = if data[pos + 5] == b'0' { Direction::Download } else { Direction::Upload };
+        Some((Protocol::Zmodem, direction, false, pos, pos))
+    }
+
+    fn find_trzsz(&mut self, data: &[u8]) -> Option<(Protocol, Direction, bool, usize, usize)> {
+        let caps = trzsz_regex().captures(data)?;
+        let whole = caps.get(0)?;
+        // 唯一标识可能还没收全，等后面有数据时再处理
+        if whole.end() == data.len() {
+            return None;
+        }
+        let id = caps.get(3).map(|m| m.as_bytes().to_vec());
+        if id.is_some() && id == self.last_trzsz {
+            return None;
+        }
+        self.last_trzsz = id;
+        let (direction, directory) = match caps.get(1)?.as_bytes() {
+            b"S" => (Direction::Download, false),
+            b"R" => (Direction::Upload, false),
+            _ => (Direction::Upload, true),
+        };
+        Some((Protocol::Trzsz, direction, directory, whole.start(), whole.end()))
+    }
+}
+
+/// 传输期间接管终端通道的读写
+pub struct TermIo {
+    channel: Arc<Mutex<Option<russh::Channel<client::Msg>>>>,
+    buf: VecDeque<u8>,
+    cancel: Arc<AtomicBool>,
+    pub closed: bool,
+}
+
+impl TermIo {
+    fn new(channel: Arc<Mutex<Option<russh::Channel<client::Msg>>>>, initial: Vec<u8>, cancel: Arc<AtomicBool>) -> Self {
+        TermIo { channel, buf: initial.into(), cancel, closed: false }
+    }
+
+    pub fn check_cancel(&self) -> Result<(), String> {
+        if self.cancel.load(Ordering::Relaxed) {
+            Err("Cancelled".to_string())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// 等待更多数据，最多 timeout；返回是否收到数据
+    pub async fn fill(&mut self, timeout: Duration) -> Result<bool, String> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            self.check_cancel()?;
+            let wait = deadline.saturating_duration_since(Instant::now()).min(Duration::from_millis(200));
+            let msg = {
+                let mut ch = self.channel.lock().await;
+                let channel = ch.as_mut().ok_or("Channel is closed")?;
+                tokio::time::timeout(wait, channel.wait()).await
+            };
+            match msg {
+                Ok(Some(russh::ChannelMsg::Data { data })) => {
+                    self.buf.extend(data.iter());
+                    return Ok(true);
+                }
+                Ok(Some(russh::ChannelMsg::Close)) | Ok(None) => {
+                    self.closed = true;
+                    return Err("Connection closed".to_string());
+                }
+                Ok(Some(_)) => {}
+                Err(_) if Instant::now() >= deadline => return Ok(false),
+                Err(_) => {}
+            }
+        }
+    }
+
+    /// 不等待，只取走已经到达的数据
+    pub async fn poll(&mut self) -> Result<bool, String> {
+        if !self.buf.is_empty() {
+            return Ok(true);
+        }
+        self.fill(Duration::ZERO).await
+    }
+
+    /// 读取一个字节；超时返回 None
+    pub async fn read_byte(&mut self, timeout: Duration) -> Result<Option<u8>, String> {
+        if self.buf.is_empty() && !self.fill(timeout).await? {
+            return Ok(None);
+        }
+        Ok(self.buf.pop_front())
+    }
+
+    /// 读取一行（去掉行尾的 \r\n）；超时返回 None
+    pub async fn read_line(&mut self, timeout: Duration) -> Result<Option<String>, String> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(pos) = self.buf.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = self.buf.drain(..=pos).collect();
+                let text = String::from_utf8_lossy(&line);
+                return Ok(Some(text.trim_end_matches(['\r', '\n']).to_string()));
+            }
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() || !self.fill(left).await? {
+                return Ok(None);
+            }
+        }
+    }
+
+    pub fn contains(&self, byte: u8) -> bool {
+        self.buf.contains(&byte)
+    }
+
+    pub fn clear(&mut self) {
+        self.buf.clear();
+    }
+
+    /// 等待并丢弃对端结束时发送的固定序列（如 ZMODEM 的 `OO`）
+    pub async fn skip_prefix(&mut self, prefix: &[u8], timeout: Duration) -> Result<(), String> {
+        let deadline = Instant::now() + timeout;
+        while self.buf.len() < prefix.len() {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() || !self.fill(left).await? {
+                break;
+            }
+        }
+        if self.buf.iter().take(prefix.len()).eq(prefix.iter()) {
+            self.buf.drain(..prefix.len());
+        }
+        Ok(())
+    }
+
+    pub async fn write(&mut self, data: &[u8]) -> Result<(), String> {
+        let mut ch = self.channel.lock().await;
+        let channel = ch.as_mut().ok_or("Channel is closed")?;
+        channel.data(data).await.map_err(|e| e.to_string())
+    }
+
+    fn into_remaining(self) -> Vec<u8> {
+        self.buf.into()
+    }
+}
+
+/// `ssh:transfer-progress` 事件负载
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct ProgressEvent {
+    session_id: String,
+    protocol: Protocol,
+    direction: Direction,
+    file_name: String,
+    file_index: usize,
+    file_count: Option<usize>,
+    bytes: u64,
+    total: u64,
+}
+
+pub struct Progress {
+    app: AppHandle,
+    event: ProgressEvent,
+    last: Instant,
+}
+
+impl Progress {
+    /// 开始一个新文件；file_count 在接收时可能未知
+    pub fn file(&mut self, name: &str, index: usize, file_count: Option<usize>, total: u64) {
+        self.event.file_name = name.to_string();
+        self.event.file_index = index;
+        self.event.file_count = file_count;
+        self.event.total = total;
+        self.update(0, true);
+    }
+
+    pub fn update(&mut self, bytes: u64, force: bool) {
+        self.event.bytes = bytes;
+        if force || bytes >= self.event.total || self.last.elapsed() >= PROGRESS_INTERVAL {
+            self.last = Instant::now();
+            let _ = self.app.emit("ssh:transfer-progress", self.event.clone());
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub enum TransferState {
+    Started,
+    Completed,
+    Cancelled,
+    Failed,
+}
+
+/// `ssh:transfer-status` 事件负载
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct StatusEvent {
+    session_id: String,
+    protocol: Protocol,
+    direction: Direction,
+    state: TransferState,
+    /// 已完成的本地文件
+    files: Vec<String>,
+    error: Option<String>,
+}
+
+/// `ssh:transfer-request` 事件负载：上传时让用户选择文件，下载时选择保存目录
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct RequestEvent {
+    request_id: String,
+    session_id: String,
+    protocol: Protocol,
+    direction: Direction,
+}
+
+pub struct TermTransferManager {
+    /// requestId -> 等待前端选择的本地路径（None 表示取消）
+    requests: RwLock<HashMap<String, oneshot::Sender<Option<Vec<String>>>>>,
+    /// sessionId -> 正在进行的终端内传输的取消标记
+    active: RwLock<HashMap<String, Arc<AtomicBool>>>,
+}
+
+impl TermTransferManager {
+    pub fn new() -> Self {
+        TermTransferManager {
+            requests: RwLock::new(HashMap::new()),
+            active: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// 传输期间终端输入不再发给远程；Ctrl+C 取消传输。返回 true 表示输入已被接管
+    pub async fn intercept_input(&self, session_id: &str, data: &str) -> bool {
+        let Some(cancel) = self.active.read().await.get(session_id).cloned() else {
+            return false;
+        };
+        if data.contains('\x03') {
+            cancel.store(true, Ordering::Relaxed);
+        }
+        true
+    }
+}
+
+async fn ask_paths(
+    app: &AppHandle,
+    transfers: &TermTransferManager,
+    session_id: &str,
+    protocol: Protocol,
+    direction: Direction,
+    cancel: &AtomicBool,
+) -> Result<Vec<String>, String> {
+    let request_id = uuid::Uuid::new_v4().to_string();
+    let (tx, mut rx) = oneshot::channel();
+    transfers.requests.write().await.insert(request_id.clone(), tx);
+    let _ = app.emit("ssh:transfer-request", RequestEvent {
+        request_id: request_id.clone(),
+        session_id: session_id.to_string(),
+        protocol,
+        direction,
+    });
+
+    let deadline = Instant::now() + REQUEST_TIMEOUT;
+    let answer = loop {
+        if cancel.load(Ordering::Relaxed) {
+            break Ok(None);
+        }
+        if Instant::now() >= deadline {
+            break Err("Timed out waiting for file selection".to_string());
+        }
+        match tokio::time::timeout(Duration::from_millis(200), &mut rx).await {
+            Ok(Ok(answer)) => break Ok(answer),
+            Ok(Err(_)) => break Ok(None),
+            Err(_) => continue,
+        }
+    };
+    transfers.requests.write().await.remove(&request_id);
+    match answer? {
+        Some(paths) if !paths.is_empty() => Ok(paths),
+        _ => Err("Cancelled".to_string()),
+    }
+}
+
+/// 远程提供的文件名只取最后一段，避免写到保存目录之外；重名时自动编号
+pub async fn local_target(dir: &str, name: &str) -> Result<String, String> {
+    let name = name.rsplit(['/', '\\']).next().unwrap_or("");
+    let name = match name {
+        "" | "." | ".." => "unnamed",
+        name => name,
+    };
+    let path = crate::sftp::join_local(dir, name);
+    if tokio::fs::try_exists(&path).await.unwrap_or(false) {
+        unique_path(&TransferSide::Local, &path).await
+    } else {
+        Ok(path)
+    }
+}
+
+async fn transfer(
+    app: &AppHandle,
+    transfers: &TermTransferManager,
+    session_id: &str,
+    io: &mut TermIo,
+    progress: &mut Progress,
+    detection: &Detection,
+    cancel: &AtomicBool,
+) -> Result<Vec<String>, String> {
+    let (protocol, direction) = (detection.protocol, detection.direction);
+    if protocol == Protocol::Trzsz && detection.directory {
+        crate::trzsz::decline(io).await?;
+        return Err("trzsz directory transfers are not supported".to_string());
+    }
+    let paths = match ask_paths(app, transfers, session_id, protocol, direction, cancel).await {
+        Ok(paths) => paths,
+        // 用户关闭了选择对话框或等待超时：让远程程序退出，恢复终端（Ctrl+C 取消时由 run 统一处理）
+        Err(e) if !cancel.load(Ordering::Relaxed) => {
+            match protocol {
+                Protocol::Zmodem => io.write(crate::zmodem::ABORT).await?,
+                Protocol::Trzsz => crate::trzsz::decline(io).await?,
+            }
+            return Err(e);
+        }
+        Err(e) => return Err(e),
+    };
+    match (protocol, direction) {
+        (Protocol::Zmodem, Direction::Upload) => crate::zmodem::send(io, progress, &paths).await,
+        (Protocol::Zmodem, Direction::Download) => crate::zmodem::receive(io, progress, &paths[0]).await,
+        (Protocol::Trzsz, Direction::Upload) => crate::trzsz::send(io, progress, &paths).await,
+        (Protocol::Trzsz, Direction::Download) => crate::trzsz::receive(io, progress, &paths[0]).await,
+    }
+}
+
+/// 接管终端通道完成一次传输；返回应继续显示在终端中的剩余数据，以及通道是否已关闭
+pub async fn run(
+    app: &AppHandle,
+    session_id: &str,
+    channel: Arc<Mutex<Option<russh::Channel<client::Msg>>>>,
+    detection: Detection,
+) -> (Vec<u8>, bool) {
+    let transfers = app.state::<TermTransferManager>();
+    let cancel = Arc::new(AtomicBool::new(false));
+    transfers.active.write().await.insert(session_id.to_string(), cancel.clone());
+
+    let (protocol, direction) = (detection.protocol, detection.direction);
+    let status = |state, files, error| StatusEvent {
+        session_id: session_id.to_string(),
+        protocol,
+        direction,
+        state,
+        files,
+        error,
+    };
+    let _ = app.emit("ssh:transfer-status", status(TransferState::Started, Vec::new(), None));
+
+    let mut io = TermIo::new(channel, detection.initial.clone(), cancel.clone());
+    let mut progress = Progress {
+        app: app.clone(),
+        event: ProgressEvent {
+            session_id: session_id.to_string(),
+            protocol,
+            direction,
+            file_name: String::new(),
+            file_index: 0,
+            file_count: None,
+            bytes: 0,
+            total: 0,
+        },
+        last: Instant::now(),
+    };
+    let result = transfer(app, &transfers, session_id, &mut io, &mut progress, &detection, &cancel).await;
+    transfers.active.write().await.remove(session_id);
+
+    let event = match result {
+        Ok(files) => status(TransferState::Completed, files, None),
+        Err(e) => {
+            log::warn!("{:?} transfer on {} ended: {}", protocol, session_id, e);
+            // 用户取消时通知远程程序中止
+            if cancel.load(Ordering::Relaxed) && !io.closed {
+                let _ = match protocol {
+                    Protocol::Zmodem => io.write(crate::zmodem::ABORT).await,
+                    Protocol::Trzsz => crate::trzsz::fail(&mut io, &e).await,
+                };
+            }
+            let state = if e == "Cancelled" { TransferState::Cancelled } else { TransferState::Failed };
+            status(state, Vec::new(), Some(e))
+        }
+    };
+    let _ = app.emit("ssh:transfer-status", event);
+    let closed = io.closed;
+    (io.into_remaining(), closed)
+}
+
+/// 回复 `ssh:transfer-request`；paths 为空表示取消
+#[tauri::command]
+pub async fn ssh_transfer_answer(
+    transfers: tauri::State<'_, TermTransferManager>,
+    request_id: String,
+    paths: Option<Vec<String>>,
+) -> Result<(), String> {
+    let tx = transfers.requests.write().await.remove(&request_id).ok_or("Request not found")?;
+    let _ = tx.send(paths);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn ssh_transfer_cancel(
+    transfers: tauri::State<'_, TermTransferManager>,
+    session_id: String,
+) -> Result<bool, String> {
+    let Some(cancel) = transfers.active.read().await.get(&session_id).cloned() else {
+        return Ok(false);
+    };
+    cancel.store(true, Ordering::Relaxed);
+    Ok(true)
+}
@@ -0,0 +1,348 @@
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use russh::client;
+use tauri::{AppHandle, Emitter, Manager};
+use tauri_plugin_store::StoreExt;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{Mutex, RwLock};
+use crate::scrollback::strip_ansi;
+use crate::ssh::SshManager;
+
+/// 未结束的行最多保留的字节数，超过后丢弃前面的部分
+const MAX_LINE: usize = 4096;
+
+fn new_id() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_rate_limit() -> u64 {
+    1000
+}
+
+/// 触发器的动作
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum TriggerAction {
+    /// 由前端高亮匹配的文本
+    #[serde(rename_all = "camelCase")]
+    Highlight {
+        #[serde(default)]
+        color: Option<String>,
+        #[serde(default)]
+        background: Option<String>,
+    },
+    /// 由前端弹出桌面通知；body 为空时使用匹配的行
+    Notify {
+        #[serde(default)]
+        title: Option<String>,
+        #[serde(default)]
+        body: Option<String>,
+    },
+    /// 向终端发送输入（如回答已知的提示），需要回车时在 input 中包含 `\r`
+    Respond { input: String },
+    /// 开始把输出（去掉控制序列）写入日志文件；path 为空时写到应用日志目录
+    StartLogging {
+        #[serde(default)]
+        path: Option<String>,
+    },
+    StopLogging,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Trigger {
+    #[serde(default = "new_id")]
+    pub id: String,
+    #[serde(default)]
+    pub name: String,
+    /// 对去掉控制序列后的每一行匹配的正则
+    pub pattern: String,
+    #[serde(default)]
+    pub case_sensitive: bool,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    pub action: TriggerAction,
+    /// 两次触发之间的最小间隔（毫秒）
+    #[serde(default = "default_rate_limit")]
+    pub rate_limit_ms: u64,
+}
+
+fn compile(trigger: &Trigger) -> Result<Regex, String> {
+    RegexBuilder::new(&trigger.pattern)
+        .case_insensitive(!trigger.case_sensitive)
+        .build()
+        .map_err(|e| format!("Invalid pattern in trigger {:?}: {}", trigger.name, e))
+}
+
+/// Respond 在等待输入的提示上每收到一块数据都会匹配，间隔不能太短，否则可能与远程程序来回刷屏
+const MIN_RESPOND_INTERVAL_MS: u64 = 500;
+
+/// 保存前检查正则是否有效，以及 Respond 的触发间隔
+pub fn validate(triggers: &[Trigger]) -> Result<(), String> {
+    triggers.iter().try_for_each(|t| {
+        compile(t)?;
+        if matches!(t.action, TriggerAction::Respond { .. }) && t.rate_limit_ms < MIN_RESPOND_INTERVAL_MS {
+            return Err(format!(
+                "Trigger {:?} responds to output and needs a rate limit of at least {} ms",
+                t.name, MIN_RESPOND_INTERVAL_MS
+            ));
+        }
+        Ok(())
+    })
+}
+
+pub struct CompiledTrigger {
+    trigger: Trigger,
+    regex: Regex,
+    last_fired: Option<Instant>,
+}
+
+/// 会话生效的触发器：主机的在前，全局的在后
+pub fn compile_all(host: &[Trigger], global: &[Trigger]) -> Vec<CompiledTrigger> {
+    host.iter()
+        .chain(global)
+        .filter(|t| t.enabled)
+        .filter_map(|t| match compile(t) {
+            Ok(regex) => {
+                let mut trigger = t.clone();
+                // 之前保存的触发器可能没有经过间隔检查
+                if matches!(trigger.action, TriggerAction::Respond { .. }) {
+                    trigger.rate_limit_ms = trigger.rate_limit_ms.max(MIN_RESPOND_INTERVAL_MS);
+                }
+                Some(CompiledTrigger { trigger, regex, last_fired: None })
+            }
+            Err(e) => {
+                log::warn!("{}", e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// 全局触发器与主机一起保存在 hosts.json 中
+pub fn load_global(app: &AppHandle) -> Vec<Trigger> {
+    let store = match app.store(PathBuf::from("hosts.json")) {
+        Ok(s) => s,
+        Err(_) => return vec![],
+    };
+    let stored: Vec<serde_json::Value> = store
+        .get("triggers")
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default();
+    crate::db::decrypt_triggers(app, stored)
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct TriggerEvent {
+    session_id: String,
+    trigger_id: String,
+    name: String,
+    action: TriggerAction,
+    /// 去掉控制序列后的行
+    line: String,
+    /// 行内匹配的 [起, 止) 字符位置
+    ranges: Vec<(usize, usize)>,
+    /// 行还没有结束（如等待输入的提示）
+    partial: bool,
+    /// 开始/停止记录时的日志文件
+    log_path: Option<String>,
+}
+
+/// 数据监听任务中执行触发器的状态
+pub struct TriggerRunner {
+    triggers: Arc<RwLock<Vec<CompiledTrigger>>>,
+    channel: Arc<Mutex<Option<russh::Channel<client::Msg>>>>,
+    /// 当前未结束的行
+    line: Vec<u8>,
+    /// 在当前未结束的行上已经触发过的触发器，行结束时不再重复触发
+    fired: HashSet<String>,
+    log: Option<(String, tokio::fs::File)>,
+}
+
+impl TriggerRunner {
+    pub fn new(
+        triggers: Arc<RwLock<Vec<CompiledTrigger>>>,
+        channel: Arc<Mutex<Option<russh::Channel<client::Msg>>>>,
+    ) -> Self {
+        TriggerRunner { triggers, channel, line: Vec::new(), fired: HashSet::new(), log: None }
+    }
+
+    pub async fn observe(&mut self, app: &AppHandle, session_id: &str, data: &[u8]) {
+        if self.log.is_none() && self.triggers.read().await.is_empty() {
+            self.line.clear();
+            return;
+        }
+        let mut rest = data;
+        while let Some(pos) = rest.iter().position(|b| *b == b'\n') {
+            self.line.extend_from_slice(&rest[..pos]);
+            rest = &rest[pos + 1..];
+            let line = strip_ansi(&std::mem::take(&mut self.line));
+            self.evaluate(app, session_id, &line, false).await;
+            self.write_log(&line).await;
+            self.fired.clear();
+        }
+        if rest.is_empty() {
+            return;
+        }
+        self.line.extend_from_slice(rest);
+        if self.line.len() > MAX_LINE {
+            let cut = self.line.len() - MAX_LINE;
+            self.line.drain(..cut);
+        }
+        let line = strip_ansi(&self.line);
+        self.evaluate(app, session_id, &line, true).await;
+    }
+
+    async fn evaluate(&mut self, app: &AppHandle, session_id: &str, line: &str, partial: bool) {
+        if line.is_empty() {
+            return;
+        }
+        let mut fired = Vec::new();
+        {
+            let mut triggers = self.triggers.write().await;
+            let now = Instant::now();
+            for compiled in triggers.iter_mut() {
+                if self.fired.contains(&compiled.trigger.id) {
+                    continue;
+                }
+                let ranges: Vec<(usize, usize)> = compiled
+                    .regex
+                    .find_iter(line)
+                    .map(|m| (line[..m.start()].chars().count(), line[..m.end()].chars().count()))
+                    .collect();
+                if ranges.is_empty() {
+                    continue;
+                }
+                let interval = Duration::from_millis(compiled.trigger.rate_limit_ms);
+                if compiled.last_fired.is_some_and(|t| now.duration_since(t) < interval) {
+                    continue;
+                }
+                compiled.last_fired = Some(now);
+                if partial {
+                    self.fired.insert(compiled.trigger.id.clone());
+                }
+                fired.push((compiled.trigger.clone(), ranges));
+            }
+        }
+
+        for (trigger, ranges) in fired {
+            let log_path = match &trigger.action {
+                TriggerAction::Respond { input } => {
+                    let mut ch = self.channel.lock().await;
+                    if let Some(channel) = ch.as_mut() {
+                        if let Err(e) = channel.data(input.as_bytes()).await {
+                            log::warn!("Trigger {} failed to respond: {}", trigger.id, e);
+                        }
+                    }
+                    None
+                }
+                TriggerAction::StartLogging { path } => match self.start_log(app, session_id, path.as_deref()).await {
+                    Ok(path) => Some(path),
+                    Err(e) => {
+                        log::warn!("Trigger {} failed to start logging: {}", trigger.id, e);
+                        continue;
+                    }
+                },
+                TriggerAction::StopLogging => {
+                    // 匹配的这一行也写入日志
+                    if !partial {
+                        self.write_log(line).await;
+                    }
+                    self.close_log().await
+                }
+                _ => None,
+            };
+            let _ = app.emit("ssh:trigger", TriggerEvent {
+                session_id: session_id.to_string(),
+                trigger_id: trigger.id,
+                name: trigger.name,
+                action: trigger.action,
+                line: line.to_string(),
+                ranges,
+                partial,
+                log_path,
+            });
+        }
+    }
+
+    async fn start_log(&mut self, app: &AppHandle, session_id: &str, path: Option<&str>) -> Result<String, String> {
+        if let Some((current, _)) = &self.log {
+            return Ok(current.clone());
+        }
+        let path = match path.filter(|p| !p.is_empty()) {
+            Some(path) => PathBuf::from(path),
+            None => {
+                let dir = app.path().app_log_dir().map_err(|e| e.to_string())?.join("terminal");
+                tokio::fs::create_dir_all(&dir).await.map_err(|e| e.to_string())?;
+                let stamp = chrono::Local::now().format("%Y%m%d-%H%M%S");
+                dir.join(format!("{}-{}.log", session_id, stamp))
+            }
+        };
+        let file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await
+            .map_err(|e| e.to_string())?;
+        let path = path.to_string_lossy().to_string();
+        self.log = Some((path.clone(), file));
+        Ok(path)
+    }
+
+    /// 刷新并关闭日志文件，返回其路径
+    pub async fn close_log(&mut self) -> Option<String> {
+        let (path, mut file) = self.log.take()?;
+        if let Err(e) = file.flush().await {
+            log::warn!("Failed to flush terminal log {}: {}", path, e);
+        }
+        Some(path)
+    }
+
+    async fn write_log(&mut self, line: &str) {
+        let Some((path, file)) = self.log.as_mut() else { return };
+        if let Err(e) = file.write_all(format!("{}\n", line).as_bytes()).await {
+            log::warn!("Failed to write terminal log {}: {}", path, e);
+            self.log = None;
+        }
+    }
+}
+
+/// 重新编译会话的触发器（主机或全局设置保存后调用）
+pub async fn reload(app: &AppHandle, host_id: Option<&str>) {
+    let global = load_global(app);
+    let hosts = crate::db::get_hosts(app.clone()).await.unwrap_or_default();
+    let sessions: Vec<_> = app.state::<SshManager>().0.read().await.values().cloned().collect();
+    for session in sessions {
+        if host_id.is_some_and(|id| id != session.host_id) {
+            continue;
+        }
+        let host_triggers = hosts.iter().find(|h| h.id == session.host_id).map(|h| h.triggers.as_slice()).unwrap_or(&[]);
+        *session.triggers.write().await = compile_all(host_triggers, &global);
+    }
+}
+
+#[tauri::command]
+pub async fn get_global_triggers(app: AppHandle) -> Result<Vec<Trigger>, String> {
+    Ok(load_global(&app))
+}
+
+/// 保存全局触发器，立即应用到所有已连接的会话
+#[tauri::command]
+pub async fn save_global_triggers(app: AppHandle, triggers: Vec<Trigger>) -> Result<(), String> {
+    validate(&triggers)?;
+    let store = app.store(PathBuf::from("hosts.json")).map_err(|e| e.to_string())?;
+    let stored = crate::db::encrypt_triggers(&app, &triggers)?;
+    store.set("triggers", serde_json::Value::Array(stored));
+    let _ = store.save();
+    reload(&app, None).await;
+    Ok(())
+}
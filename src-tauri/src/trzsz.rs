@@ -0,0 +1,221 @@
+use base64::Engine;
+use flate2::Compression;
+use md5::{Digest, Md5};
+use std::io::{Read, Write};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use crate::term_transfer::{local_target, Progress, TermIo};
+
+/// 在 #ACT 中声明的协议版本
+const VERSION: &str = "1.1.6";
+/// 服务器未在 #CFG 中给出超时时的默认值
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(20);
+/// 上传时数据块从 16K 开始逐步加倍，不超过服务器的 bufsize 与 1M
+const MIN_CHUNK: usize = 16 * 1024;
+const MAX_CHUNK: usize = 1024 * 1024;
+
+/// trzsz 的文本编码：zlib 压缩后 base64
+fn encode(data: &[u8]) -> String {
+    let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), Compression::default());
+    let _ = encoder.write_all(data);
+    let compressed = encoder.finish().unwrap_or_default();
+    base64::engine::general_purpose::STANDARD.encode(compressed)
+}
+
+fn decode(text: &str) -> Result<Vec<u8>, String> {
+    let compressed = base64::engine::general_purpose::STANDARD
+        .decode(text.trim())
+        .map_err(|e| format!("trzsz: invalid data: {}", e))?;
+    let mut data = Vec::new();
+    flate2::read::ZlibDecoder::new(compressed.as_slice())
+        .read_to_end(&mut data)
+        .map_err(|e| format!("trzsz: invalid data: {}", e))?;
+    Ok(data)
+}
+
+struct Trzsz<'a> {
+    io: &'a mut TermIo,
+    timeout: Duration,
+    /// 服务器允许的最大数据块
+    bufsize: usize,
+}
+
+impl Trzsz<'_> {
+    async fn send_line(&mut self, kind: &str, value: &str) -> Result<(), String> {
+        self.io.write(format!("#{}:{}\n", kind, value).as_bytes()).await
+    }
+
+    /// 读取指定类型的一行；#FAIL / #fail 转成错误，其他杂项行忽略
+    async fn recv_line(&mut self, kind: &str) -> Result<String, String> {
+        let prefix = format!("#{}:", kind);
+        loop {
+            let line = self.io.read_line(self.timeout).await?.ok_or("trzsz: timed out")?;
+            if let Some(pos) = line.rfind(&prefix) {
+                return Ok(line[pos + prefix.len()..].to_string());
+            }
+            for fail in ["#FAIL:", "#fail:"] {
+                if let Some(pos) = line.rfind(fail) {
+                    let message = decode(&line[pos + fail.len()..]).unwrap_or_default();
+                    return Err(format!("trzsz: {}", String::from_utf8_lossy(&message)));
+                }
+            }
+        }
+    }
+
+    async fn recv_integer(&mut self, kind: &str) -> Result<u64, String> {
+        let value = self.recv_line(kind).await?;
+        value.trim().parse().map_err(|_| format!("trzsz: invalid {} {:?}", kind, value))
+    }
+
+    async fn check_integer(&mut self, expected: u64) -> Result<(), String> {
+        let value = self.recv_integer("SUCC").await?;
+        if value != expected {
+            return Err(format!("trzsz: expected {} but got {}", expected, value));
+        }
+        Ok(())
+    }
+
+    async fn recv_binary(&mut self, kind: &str) -> Result<Vec<u8>, String> {
+        let value = self.recv_line(kind).await?;
+        decode(&value)
+    }
+}
+
+async fn send_action(io: &mut TermIo, confirm: bool) -> Result<(), String> {
+    let action = serde_json::json!({
+        "lang": "rust",
+        "confirm": confirm,
+        "version": VERSION,
+        "support_dir": false,
+        "binary": false,
+    });
+    io.write(format!("#ACT:{}\n", encode(action.to_string().as_bytes())).as_bytes()).await
+}
+
+/// 拒绝这次传输，远程 trz/tsz 随即退出
+pub async fn decline(io: &mut TermIo) -> Result<(), String> {
+    send_action(io, false).await
+}
+
+/// 传输中途出错时通知远程退出
+pub async fn fail(io: &mut TermIo, message: &str) -> Result<(), String> {
+    io.write(format!("#fail:{}\n", encode(message.as_bytes())).as_bytes()).await
+}
+
+/// 确认传输并读取服务器配置；只支持 base64 模式
+async fn accept(io: &mut TermIo) -> Result<Trzsz<'_>, String> {
+    send_action(io, true).await?;
+    let mut trzsz = Trzsz { io, timeout: DEFAULT_TIMEOUT, bufsize: MAX_CHUNK };
+    let config: serde_json::Value = serde_json::from_slice(&trzsz.recv_binary("CFG").await?)
+        .map_err(|e| format!("trzsz: invalid config: {}", e))?;
+    if config["binary"].as_bool() == Some(true) {
+        let message = "binary mode is not supported, run trz/tsz without -b";
+        fail(trzsz.io, message).await?;
+        return Err(format!("trzsz: {}", message));
+    }
+    if let Some(timeout) = config["timeout"].as_u64().filter(|t| *t > 0) {
+        trzsz.timeout = Duration::from_secs(timeout);
+    }
+    if let Some(bufsize) = config["bufsize"].as_u64().filter(|b| *b > 0) {
+        trzsz.bufsize = (bufsize as usize).clamp(1024, MAX_CHUNK);
+    }
+    Ok(trzsz)
+}
+
+/// 远程 `trz`：发送本地文件，返回成功发送的路径
+pub async fn send(io: &mut TermIo, progress: &mut Progress, paths: &[String]) -> Result<Vec<String>, String> {
+    for path in paths {
+        if !tokio::fs::metadata(path).await.map_err(|e| e.to_string())?.is_file() {
+            decline(io).await?;
+            return Err(format!("trzsz can only send regular files: {}", path));
+        }
+    }
+    let mut tz = accept(io).await?;
+
+    tz.send_line("NUM", &paths.len().to_string()).await?;
+    tz.check_integer(paths.len() as u64).await?;
+    for (index, path) in paths.iter().enumerate() {
+        let name = std::path::Path::new(path).file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        tz.send_line("NAME", &encode(name.as_bytes())).await?;
+        tz.recv_line("SUCC").await?;
+
+        let mut file = tokio::fs::File::open(path).await.map_err(|e| e.to_string())?;
+        let size = file.metadata().await.map_err(|e| e.to_string())?.len();
+        tz.send_line("SIZE", &size.to_string()).await?;
+        tz.check_integer(size).await?;
+        progress.file(&name, index, Some(paths.len()), size);
+
+        let mut hasher = Md5::new();
+        let mut chunk = MIN_CHUNK.min(tz.bufsize);
+        let mut buf = vec![0u8; tz.bufsize];
+        let mut sent = 0u64;
+        while sent < size {
+            tz.io.check_cancel()?;
+            let n = file.read(&mut buf[..chunk]).await.map_err(|e| e.to_string())?;
+            if n == 0 {
+                return Err(format!("{} shrank during upload", path));
+            }
+            hasher.update(&buf[..n]);
+            tz.send_line("DATA", &encode(&buf[..n])).await?;
+            tz.check_integer(n as u64).await?;
+            sent += n as u64;
+            progress.update(sent, false);
+            chunk = (chunk * 2).min(tz.bufsize);
+        }
+
+        let digest = hasher.finalize().to_vec();
+        tz.send_line("MD5", &encode(&digest)).await?;
+        if tz.recv_binary("SUCC").await? != digest {
+            return Err(format!("trzsz: MD5 mismatch for {}", name));
+        }
+    }
+    Ok(paths.to_vec())
+}
+
+/// 远程 `tsz`：接收文件保存到 dir，返回保存的本地路径
+pub async fn receive(io: &mut TermIo, progress: &mut Progress, dir: &str) -> Result<Vec<String>, String> {
+    let mut tz = accept(io).await?;
+
+    let count = tz.recv_integer("NUM").await?;
+    tz.send_line("SUCC", &count.to_string()).await?;
+    let mut files = Vec::new();
+    for index in 0..count as usize {
+        let name = String::from_utf8_lossy(&tz.recv_binary("NAME").await?).to_string();
+        let path = local_target(dir, &name).await?;
+        let local_name = std::path::Path::new(&path).file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        tz.send_line("SUCC", &encode(local_name.as_bytes())).await?;
+
+        let size = tz.recv_integer("SIZE").await?;
+        tz.send_line("SUCC", &size.to_string()).await?;
+        progress.file(&name, index, Some(count as usize), size);
+
+        let mut file = tokio::fs::File::create(&path).await.map_err(|e| e.to_string())?;
+        let result: Result<(), String> = async {
+            let mut hasher = Md5::new();
+            let mut received = 0u64;
+            while received < size {
+                let data = tz.recv_binary("DATA").await?;
+                file.write_all(&data).await.map_err(|e| e.to_string())?;
+                hasher.update(&data);
+                received += data.len() as u64;
+                tz.send_line("SUCC", &data.len().to_string()).await?;
+                progress.update(received, false);
+            }
+            file.flush().await.map_err(|e| e.to_string())?;
+
+            let digest = tz.recv_binary("MD5").await?;
+            if digest != hasher.finalize().to_vec() {
+                return Err(format!("trzsz: MD5 mismatch for {}", name));
+            }
+            tz.send_line("SUCC", &encode(&digest)).await
+        }
+        .await;
+        drop(file);
+        if let Err(e) = result {
+            let _ = tokio::fs::remove_file(&path).await;
+            return Err(e);
+        }
+        files.push(path);
+    }
+    Ok(files)
+}
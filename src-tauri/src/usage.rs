@@ -0,0 +1,301 @@
+use russh_sftp::client::SftpSession;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter};
+use tokio::sync::Mutex;
+use crate::search::SearchManager;
+use crate::sftp::{join_remote, open_exec_channel, sftp_get_host, shell_quote, SessionHostMap, SftpManager};
+
+/// 默认返回的目录树深度
+const DEFAULT_DEPTH: usize = 3;
+
+/// 目录占用树中的一个节点，只包含目录
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DuNode {
+    pub name: String,
+    pub path: String,
+    /// 递归总大小（字节）
+    pub size: u64,
+    /// 该目录下直接文件（及未展开的子目录）的大小，即 size 减去 children 之和
+    pub self_size: u64,
+    /// 按大小从大到小排列
+    pub children: Vec<DuNode>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DuResult {
+    pub scan_id: String,
+    /// `exec`（du，按磁盘占用计算）或 `sftp`（遍历，按文件大小计算）
+    pub method: String,
+    /// 部分目录无权限读取，结果偏小
+    pub partial: bool,
+    pub tree: DuNode,
+}
+
+/// `sftp:du-progress` 事件负载（仅 SFTP 遍历时发送）
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DuProgressEvent {
+    pub scan_id: String,
+    pub scanned_dirs: usize,
+    pub bytes: u64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FsSpace {
+    pub path: String,
+    pub total: u64,
+    pub free: u64,
+    /// 普通用户可用的空间（不含 root 保留块）
+    pub available: u64,
+    pub used: u64,
+    pub inodes: u64,
+    pub inodes_free: u64,
+}
+
+fn normalize_root(root: &str) -> &str {
+    if root.len() > 1 { root.trim_end_matches('/') } else { root }
+}
+
+/// 由 (目录路径, 递归大小) 列表构造不超过 max_depth 层的树
+fn build_tree(root: &str, dirs: Vec<(String, u64)>, max_depth: usize) -> DuNode {
+    let mut sizes: BTreeMap<String, u64> = BTreeMap::new();
+    for (path, size) in dirs {
+        let path = normalize_root(&path);
+        let rel = if path == root {
+            ""
+        } else {
+            match path.strip_prefix(root) {
+                Some(rest) => rest.trim_start_matches('/'),
+                None => continue,
+            }
+        };
+        if rel.is_empty() || rel.split('/').count() <= max_depth {
+            sizes.insert(rel.to_string(), size);
+        }
+    }
+    let mut children_of: HashMap<&str, Vec<&str>> = HashMap::new();
+    for rel in sizes.keys().filter(|r| !r.is_empty()) {
+        let parent = rel.rsplit_once('/').map(|(p, _)| p).unwrap_or("");
+        children_of.entry(parent).or_default().push(rel);
+    }
+
+    fn node(root: &str, rel: &str, sizes: &BTreeMap<String, u64>, children_of: &HashMap<&str, Vec<&str>>) -> DuNode {
+        let size = sizes.get(rel).copied().unwrap_or(0);
+        let mut children: Vec<DuNode> = children_of
+            .get(rel)
+            .map(|list| list.iter().map(|c| node(root, c, sizes, children_of)).collect())
+            .unwrap_or_default();
+        children.sort_by_key(|c| std::cmp::Reverse(c.size));
+        let children_size: u64 = children.iter().map(|c| c.size).sum();
+        DuNode {
+            name: if rel.is_empty() { root.to_string() } else { rel.rsplit('/').next().unwrap_or(rel).to_string() },
+            path: if rel.is_empty() { root.to_string() } else { join_remote(root, rel) },
+            size,
+            self_size: size.saturating_sub(children_size),
+            children,
+        }
+    }
+    node(root, "", &sizes, &children_of)
+}
+
+/// 运行 `du -k -d N`；返回 None 表示 du 不可用，应改用 SFTP 遍历
+async fn du_exec(
+    mgr: &SftpManager,
+    session_id: &str,
+    root: &str,
+    max_depth: usize,
+    cancel: &AtomicBool,
+) -> Result<Option<(Vec<(String, u64)>, bool)>, String> {
+    let command = format!("du -k -d {} {}", max_depth, shell_quote(root));
+    let Ok(mut channel) = open_exec_channel(mgr, session_id, &command).await else {
+        return Ok(None);
+    };
+    let mut stdout = Vec::new();
+    let mut stderr = false;
+    let mut exit = None;
+    loop {
+        if cancel.load(Ordering::Relaxed) {
+            let _ = channel.close().await;
+            return Err("Cancelled".to_string());
+        }
+        // du 在大目录上可能长时间没有输出，定时醒来检查取消
+        let msg = match tokio::time::timeout(Duration::from_millis(500), channel.wait()).await {
+            Ok(Some(msg)) => msg,
+            Ok(None) => break,
+            Err(_) => continue,
+        };
+        match msg {
+            russh::ChannelMsg::Data { data } => stdout.extend_from_slice(&data),
+            russh::ChannelMsg::ExtendedData { .. } => stderr = true,
+            russh::ChannelMsg::ExitStatus { exit_status } => exit = Some(exit_status),
+            russh::ChannelMsg::Close => break,
+            _ => {}
+        }
+    }
+
+    let dirs: Vec<(String, u64)> = String::from_utf8_lossy(&stdout)
+        .lines()
+        .filter_map(|line| {
+            let (kb, path) = line.split_once('\t')?;
+            Some((path.to_string(), kb.trim().parse::<u64>().ok()? * 1024))
+        })
+        .collect();
+    // 无权限的子目录会让 du 返回 1，但其余结果仍然有效
+    if dirs.is_empty() && exit != Some(0) {
+        return Ok(None);
+    }
+    Ok(Some((dirs, stderr || exit != Some(0))))
+}
+
+/// 通过 SFTP 遍历统计；不跟随符号链接
+async fn du_sftp(
+    app: &AppHandle,
+    scan_id: &str,
+    sftp_arc: &Arc<Mutex<SftpSession>>,
+    root: &str,
+    max_depth: usize,
+    cancel: &AtomicBool,
+) -> Result<(Vec<(String, u64)>, bool), String> {
+    // (路径, 父目录下标, 深度, 直接文件大小)；子目录总在父目录之后登记
+    let mut dirs: Vec<(String, usize, usize, u64)> = vec![(root.to_string(), 0, 0, 0)];
+    let mut stack = vec![0usize];
+    let mut partial = false;
+    let mut bytes = 0u64;
+    let mut last_report = Instant::now();
+    while let Some(i) = stack.pop() {
+        if cancel.load(Ordering::Relaxed) {
+            return Err("Cancelled".to_string());
+        }
+        let entries = {
+            let sftp = sftp_arc.lock().await;
+            match sftp.read_dir(dirs[i].0.as_str()).await {
+                Ok(entries) => entries,
+                Err(e) if i == 0 => return Err(e.to_string()),
+                Err(_) => {
+                    partial = true;
+                    continue;
+                }
+            }
+        };
+        for entry in entries {
+            let metadata = entry.metadata();
+            let file_type = metadata.file_type();
+            if file_type.is_dir() {
+                let path = join_remote(&dirs[i].0, &entry.file_name());
+                dirs.push((path, i, dirs[i].2 + 1, 0));
+                stack.push(dirs.len() - 1);
+            } else if file_type.is_file() {
+                let size = metadata.size.unwrap_or(0);
+                dirs[i].3 += size;
+                bytes += size;
+            }
+        }
+        if last_report.elapsed() >= Duration::from_millis(500) {
+            last_report = Instant::now();
+            let _ = app.emit("sftp:du-progress", DuProgressEvent {
+                scan_id: scan_id.to_string(),
+                scanned_dirs: dirs.len(),
+                bytes,
+            });
+        }
+    }
+
+    // 倒序累加：子目录的下标总是大于父目录
+    let mut totals: Vec<u64> = dirs.iter().map(|d| d.3).collect();
+    for i in (1..dirs.len()).rev() {
+        totals[dirs[i].1] += totals[i];
+    }
+    let result = dirs
+        .into_iter()
+        .zip(totals)
+        .filter(|((_, _, depth, _), _)| *depth <= max_depth)
+        .map(|((path, _, _, _), total)| (path, total))
+        .collect();
+    Ok((result, partial))
+}
+
+/// 统计远程目录的递归大小，返回 maxDepth 层以内的目录树；可用 `sftp_du_cancel` 取消
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn sftp_du(
+    app: AppHandle,
+    host_map: tauri::State<'_, SessionHostMap>,
+    mgr: tauri::State<'_, SftpManager>,
+    scans: tauri::State<'_, SearchManager>,
+    session_id: String,
+    scan_id: String,
+    path: String,
+    max_depth: Option<usize>,
+) -> Result<DuResult, String> {
+    let (sftp_arc, _) = sftp_get_host(&app, &host_map, &mgr, &session_id).await?;
+    let max_depth = max_depth.unwrap_or(DEFAULT_DEPTH).max(1);
+    let root = normalize_root(&path).to_string();
+    let cancel = Arc::new(AtomicBool::new(false));
+    scans.0.write().await.insert(scan_id.clone(), cancel.clone());
+
+    let result = async {
+        if let Some((dirs, partial)) = du_exec(&mgr, &session_id, &root, max_depth, &cancel).await? {
+            return Ok(("exec", dirs, partial));
+        }
+        let (dirs, partial) = du_sftp(&app, &scan_id, &sftp_arc, &root, max_depth, &cancel).await?;
+        Ok::<_, String>(("sftp", dirs, partial))
+    }
+    .await;
+    scans.0.write().await.remove(&scan_id);
+    let (method, dirs, partial) = result?;
+
+    Ok(DuResult {
+        scan_id,
+        method: method.to_string(),
+        partial,
+        tree: build_tree(&root, dirs, max_depth),
+    })
+}
+
+#[tauri::command]
+pub async fn sftp_du_cancel(
+    scans: tauri::State<'_, SearchManager>,
+    scan_id: String,
+) -> Result<bool, String> {
+    let Some(cancel) = scans.0.read().await.get(&scan_id).cloned() else {
+        return Ok(false);
+    };
+    cancel.store(true, Ordering::Relaxed);
+    Ok(true)
+}
+
+/// 通过 statvfs@openssh.com 扩展查询 path 所在文件系统的容量
+#[tauri::command]
+pub async fn sftp_statvfs(
+    app: AppHandle,
+    host_map: tauri::State<'_, SessionHostMap>,
+    mgr: tauri::State<'_, SftpManager>,
+    session_id: String,
+    path: String,
+) -> Result<FsSpace, String> {
+    let (sftp_arc, _) = sftp_get_host(&app, &host_map, &mgr, &session_id).await?;
+    let sftp = sftp_arc.lock().await;
+    let stat = sftp
+        .fs_info(path.as_str())
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or("Server does not support statvfs@openssh.com")?;
+    // 块数以 f_frsize 为单位，部分实现返回 0 时退回 f_bsize
+    let unit = if stat.fragment_size > 0 { stat.fragment_size } else { stat.block_size };
+    Ok(FsSpace {
+        path,
+        total: stat.blocks * unit,
+        free: stat.blocks_free * unit,
+        available: stat.blocks_avail * unit,
+        used: stat.blocks.saturating_sub(stat.blocks_free) * unit,
+        inodes: stat.inodes,
+        inodes_free: stat.inodes_free,
+    })
+}
@@ -0,0 +1,625 @@
+use std::io::SeekFrom;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use crate::term_transfer::{local_target, Progress, TermIo};
+
+const ZPAD: u8 = b'*';
+const ZDLE: u8 = 0x18;
+const ZBIN: u8 = b'A';
+const ZHEX: u8 = b'B';
+const ZBIN32: u8 = b'C';
+
+const ZRQINIT: u8 = 0;
+const ZRINIT: u8 = 1;
+const ZSINIT: u8 = 2;
+const ZACK: u8 = 3;
+const ZFILE: u8 = 4;
+const ZSKIP: u8 = 5;
+const ZNAK: u8 = 6;
+const ZABORT: u8 = 7;
+const ZFIN: u8 = 8;
+const ZRPOS: u8 = 9;
+const ZDATA: u8 = 10;
+const ZEOF: u8 = 11;
+const ZFERR: u8 = 12;
+const ZCAN: u8 = 16;
+
+/// 数据子包结束标记
+const ZCRCE: u8 = b'h';
+const ZCRCG: u8 = b'i';
+const ZCRCQ: u8 = b'j';
+const ZCRCW: u8 = b'k';
+const ZRUB0: u8 = b'l';
+const ZRUB1: u8 = b'm';
+
+/// ZRINIT 的 ZF0 能力位
+const CANFDX: u8 = 0x01;
+const CANOVIO: u8 = 0x02;
+const CANFC32: u8 = 0x20;
+const ESCCTL: u8 = 0x40;
+/// ZFILE 的 ZF0：按二进制传输
+const ZCBIN: u8 = 1;
+
+const SUBPACKET: usize = 1024;
+/// 接收时允许的最大子包长度（兼容 8K 扩展）
+const MAX_SUBPACKET: usize = 8192;
+const TIMEOUT: Duration = Duration::from_secs(10);
+const RETRIES: usize = 10;
+
+/// 取消序列：8 个 CAN 加 10 个退格
+pub const ABORT: &[u8] = b"\x18\x18\x18\x18\x18\x18\x18\x18\x08\x08\x08\x08\x08\x08\x08\x08\x08\x08";
+
+enum Error {
+    Timeout,
+    /// 校验失败或帧损坏，可以请求重传
+    Corrupt,
+    RemoteCancel,
+    Other(String),
+}
+
+impl From<String> for Error {
+    fn from(e: String) -> Self {
+        Error::Other(e)
+    }
+}
+
+impl From<Error> for String {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Timeout => "ZMODEM timed out".to_string(),
+            Error::Corrupt => "ZMODEM received too many corrupt frames".to_string(),
+            Error::RemoteCancel => "ZMODEM transfer cancelled by remote".to_string(),
+            Error::Other(e) => e,
+        }
+    }
+}
+
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for &b in data {
+        crc ^= (b as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &b in data {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+struct Header {
+    kind: u8,
+    data: [u8; 4],
+    /// 二进制头使用 CRC32 时，随后的数据子包也使用 CRC32
+    crc32: bool,
+}
+
+impl Header {
+    fn pos(&self) -> u64 {
+        u32::from_le_bytes(self.data) as u64
+    }
+
+    fn zf0(&self) -> u8 {
+        self.data[3]
+    }
+}
+
+fn pos_data(pos: u64) -> [u8; 4] {
+    (pos as u32).to_le_bytes()
+}
+
+fn flags_data(zf0: u8) -> [u8; 4] {
+    [0, 0, 0, zf0]
+}
+
+enum Byte {
+    Data(u8),
+    End(u8),
+}
+
+struct Zmodem<'a> {
+    io: &'a mut TermIo,
+    /// 发送二进制头与子包时使用 CRC32
+    crc32: bool,
+    /// 接收方要求转义所有控制字符
+    escape_ctl: bool,
+}
+
+impl Zmodem<'_> {
+    fn escape(&self, out: &mut Vec<u8>, b: u8) {
+        match b {
+            ZDLE | 0x10 | 0x11 | 0x13 | 0x90 | 0x91 | 0x93 | 0x0d | 0x8d => out.extend_from_slice(&[ZDLE, b ^ 0x40]),
+            0x7f if self.escape_ctl => out.extend_from_slice(&[ZDLE, ZRUB0]),
+            0xff if self.escape_ctl => out.extend_from_slice(&[ZDLE, ZRUB1]),
+            _ if self.escape_ctl && b & 0x60 == 0 => out.extend_from_slice(&[ZDLE, b ^ 0x40]),
+            _ => out.push(b),
+        }
+    }
+
+    async fn send_hex(&mut self, kind: u8, data: [u8; 4]) -> Result<(), Error> {
+        let mut raw = vec![kind];
+        raw.extend_from_slice(&data);
+        let crc = crc16(&raw);
+        raw.extend_from_slice(&crc.to_be_bytes());
+        let mut out = vec![ZPAD, ZPAD, ZDLE, ZHEX];
+        out.extend_from_slice(hex::encode(&raw).as_bytes());
+        out.extend_from_slice(b"\r\n");
+        if kind != ZFIN && kind != ZACK {
+            out.push(0x11);
+        }
+        Ok(self.io.write(&out).await?)
+    }
+
+    async fn send_bin(&mut self, kind: u8, data: [u8; 4]) -> Result<(), Error> {
+        let mut raw = vec![kind];
+        raw.extend_from_slice(&data);
+        let mut out = vec![ZPAD, ZDLE, if self.crc32 { ZBIN32 } else { ZBIN }];
+        for &b in &raw {
+            self.escape(&mut out, b);
+        }
+        let crc = if self.crc32 { crc32(&raw).to_le_bytes().to_vec() } else { crc16(&raw).to_be_bytes().to_vec() };
+        for b in crc {
+            self.escape(&mut out, b);
+        }
+        Ok(self.io.write(&out).await?)
+    }
+
+    async fn send_subpacket(&mut self, data: &[u8], end: u8) -> Result<(), Error> {
+        let mut out = Vec::with_capacity(data.len() + data.len() / 8 + 16);
+        for &b in data {
+            self.escape(&mut out, b);
+        }
+        out.extend_from_slice(&[ZDLE, end]);
+        let mut covered = data.to_vec();
+        covered.push(end);
+        let crc = if self.crc32 { crc32(&covered).to_le_bytes().to_vec() } else { crc16(&covered).to_be_bytes().to_vec() };
+        for b in crc {
+            self.escape(&mut out, b);
+        }
+        if end == ZCRCW {
+            out.push(0x11);
+        }
+        Ok(self.io.write(&out).await?)
+    }
+
+    async fn raw(&mut self) -> Result<u8, Error> {
+        self.io.read_byte(TIMEOUT).await?.ok_or(Error::Timeout)
+    }
+
+    /// 读取一个经过 ZDLE 转义的字节；未转义的 XON/XOFF 被丢弃
+    async fn zdl_read(&mut self) -> Result<Byte, Error> {
+        loop {
+            match self.raw().await? {
+                ZDLE => break,
+                0x11 | 0x13 | 0x91 | 0x93 => continue,
+                c => return Ok(Byte::Data(c)),
+            }
+        }
+        let mut cans = 1;
+        loop {
+            match self.raw().await? {
+                c @ (ZCRCE | ZCRCG | ZCRCQ | ZCRCW) => return Ok(Byte::End(c)),
+                ZRUB0 => return Ok(Byte::Data(0x7f)),
+                ZRUB1 => return Ok(Byte::Data(0xff)),
+                0x11 | 0x13 | 0x91 | 0x93 => continue,
+                ZDLE => {
+                    cans += 1;
+                    if cans >= 5 {
+                        return Err(Error::RemoteCancel);
+                    }
+                }
+                c if c & 0x60 == 0x40 => return Ok(Byte::Data(c ^ 0x40)),
+                _ => return Err(Error::Corrupt),
+            }
+        }
+    }
+
+    async fn zdl_data(&mut self) -> Result<u8, Error> {
+        match self.zdl_read().await? {
+            Byte::Data(b) => Ok(b),
+            Byte::End(_) => Err(Error::Corrupt),
+        }
+    }
+
+    async fn hex_byte(&mut self) -> Result<u8, Error> {
+        let digits = [self.raw().await? & 0x7f, self.raw().await? & 0x7f];
+        let text = std::str::from_utf8(&digits).map_err(|_| Error::Corrupt)?;
+        u8::from_str_radix(text, 16).map_err(|_| Error::Corrupt)
+    }
+
+    /// 跳过杂乱数据直到读出一个帧头；连续 5 个 CAN 视为对端取消
+    async fn read_header(&mut self) -> Result<Header, Error> {
+        let mut cans = 0;
+        let mut garbage = 0;
+        loop {
+            let mut c = self.raw().await?;
+            if c != ZPAD {
+                cans = if c == ZDLE { cans + 1 } else { 0 };
+                if cans >= 5 {
+                    return Err(Error::RemoteCancel);
+                }
+                garbage += 1;
+                if garbage > 64 * 1024 {
+                    return Err(Error::Corrupt);
+                }
+                continue;
+            }
+            cans = 0;
+            while c == ZPAD {
+                c = self.raw().await?;
+            }
+            if c != ZDLE {
+                continue;
+            }
+            let format = self.raw().await?;
+            let mut raw = [0u8; 5];
+            let header_crc32 = format == ZBIN32;
+            match format {
+                ZHEX => {
+                    for b in raw.iter_mut() {
+                        *b = self.hex_byte().await?;
+                    }
+                    let crc = u16::from_be_bytes([self.hex_byte().await?, self.hex_byte().await?]);
+                    if crc != crc16(&raw) {
+                        return Err(Error::Corrupt);
+                    }
+                }
+                ZBIN | ZBIN32 => {
+                    for b in raw.iter_mut() {
+                        *b = self.zdl_data().await?;
+                    }
+                    let ok = if header_crc32 {
+                        let mut crc = [0u8; 4];
+                        for b in crc.iter_mut() {
+                            *b = self.zdl_data().await?;
+                        }
+                        u32::from_le_bytes(crc) == crc32(&raw)
+                    } else {
+                        u16::from_be_bytes([self.zdl_data().await?, self.zdl_data().await?]) == crc16(&raw)
+                    };
+                    if !ok {
+                        return Err(Error::Corrupt);
+                    }
+                }
+                _ => continue,
+            }
+            return Ok(Header { kind: raw[0], data: [raw[1], raw[2], raw[3], raw[4]], crc32: header_crc32 });
+        }
+    }
+
+    async fn read_subpacket(&mut self, use_crc32: bool) -> Result<(Vec<u8>, u8), Error> {
+        let mut data = Vec::new();
+        let end = loop {
+            match self.zdl_read().await? {
+                Byte::Data(b) => {
+                    data.push(b);
+                    if data.len() > MAX_SUBPACKET {
+                        return Err(Error::Corrupt);
+                    }
+                }
+                Byte::End(end) => break end,
+            }
+        };
+        let mut covered = data.clone();
+        covered.push(end);
+        let ok = if use_crc32 {
+            let mut crc = [0u8; 4];
+            for b in crc.iter_mut() {
+                *b = self.zdl_data().await?;
+            }
+            u32::from_le_bytes(crc) == crc32(&covered)
+        } else {
+            u16::from_be_bytes([self.zdl_data().await?, self.zdl_data().await?]) == crc16(&covered)
+        };
+        if !ok {
+            return Err(Error::Corrupt);
+        }
+        Ok((data, end))
+    }
+
+    /// 发送数据期间检查接收方是否发来了 ZRPOS 等控制帧
+    async fn poll_header(&mut self) -> Result<Option<Header>, Error> {
+        if !self.io.poll().await? {
+            return Ok(None);
+        }
+        if self.io.contains(ZPAD) || self.io.contains(ZDLE) {
+            return match self.read_header().await {
+                Ok(header) => Ok(Some(header)),
+                Err(Error::Corrupt) | Err(Error::Timeout) => Ok(None),
+                Err(e) => Err(e),
+            };
+        }
+        self.io.clear();
+        Ok(None)
+    }
+}
+
+/// ZFILE 子包：`文件名\0大小 修改时间(八进制) 权限(八进制) ...\0`
+fn parse_file_info(info: &[u8]) -> (String, u64, u64) {
+    let mut parts = info.split(|b| *b == 0);
+    let name = String::from_utf8_lossy(parts.next().unwrap_or_default()).to_string();
+    let meta = String::from_utf8_lossy(parts.next().unwrap_or_default()).to_string();
+    let mut fields = meta.split_whitespace();
+    let size = fields.next().and_then(|s| s.parse().ok()).unwrap_or(0);
+    let mtime = fields.next().and_then(|s| u64::from_str_radix(s, 8).ok()).unwrap_or(0);
+    (name, size, mtime)
+}
+
+/// 接收一个文件的数据直到 ZEOF
+async fn receive_data(
+    zm: &mut Zmodem<'_>,
+    progress: &mut Progress,
+    file: &mut tokio::fs::File,
+) -> Result<(), Error> {
+    let mut pos = 0u64;
+    let mut errors = 0;
+    zm.send_hex(ZRPOS, pos_data(pos)).await?;
+    loop {
+        let header = match zm.read_header().await {
+            Ok(header) => header,
+            Err(Error::Timeout) | Err(Error::Corrupt) if errors < RETRIES => {
+                errors += 1;
+                zm.send_hex(ZRPOS, pos_data(pos)).await?;
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        match header.kind {
+            ZDATA if header.pos() != pos => zm.send_hex(ZRPOS, pos_data(pos)).await?,
+            ZDATA => loop {
+                let (data, end) = match zm.read_subpacket(header.crc32).await {
+                    Ok(packet) => packet,
+                    Err(Error::Timeout) | Err(Error::Corrupt) if errors < RETRIES => {
+                        errors += 1;
+                        zm.send_hex(ZRPOS, pos_data(pos)).await?;
+                        break;
+                    }
+                    Err(e) => return Err(e),
+                };
+                errors = 0;
+                file.write_all(&data).await.map_err(|e| e.to_string())?;
+                pos += data.len() as u64;
+                progress.update(pos, false);
+                match end {
+                    ZCRCW => {
+                        zm.send_hex(ZACK, pos_data(pos)).await?;
+                        break;
+                    }
+                    ZCRCQ => zm.send_hex(ZACK, pos_data(pos)).await?,
+                    ZCRCG => {}
+                    _ => break,
+                }
+            },
+            // 数据还没全部到达时的 ZEOF 忽略，等发送方处理我们的 ZRPOS
+            ZEOF if header.pos() == pos => return Ok(()),
+            ZEOF => {}
+            // 发送方没收到 ZRPOS，重发了文件头
+            ZFILE => {
+                let _ = zm.read_subpacket(header.crc32).await;
+                zm.send_hex(ZRPOS, pos_data(pos)).await?;
+            }
+            ZNAK => zm.send_hex(ZRPOS, pos_data(pos)).await?,
+            ZCAN | ZABORT | ZFIN | ZFERR => return Err(Error::RemoteCancel),
+            _ => {}
+        }
+    }
+}
+
+/// 远程 `sz`：接收文件保存到 dir，返回保存的本地路径
+pub async fn receive(io: &mut TermIo, progress: &mut Progress, dir: &str) -> Result<Vec<String>, String> {
+    let mut zm = Zmodem { io, crc32: false, escape_ctl: false };
+    let rinit = flags_data(CANFDX | CANOVIO | CANFC32);
+    let mut files = Vec::new();
+    let mut errors = 0;
+    zm.send_hex(ZRINIT, rinit).await?;
+    loop {
+        let header = match zm.read_header().await {
+            Ok(header) => header,
+            Err(Error::Timeout) | Err(Error::Corrupt) if errors < RETRIES => {
+                errors += 1;
+                zm.send_hex(ZRINIT, rinit).await?;
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        match header.kind {
+            ZRQINIT | ZEOF | ZDATA => zm.send_hex(ZRINIT, rinit).await?,
+            ZSINIT => {
+                let _ = zm.read_subpacket(header.crc32).await;
+                zm.send_hex(ZACK, [0; 4]).await?;
+            }
+            ZFILE => {
+                let info = match zm.read_subpacket(header.crc32).await {
+                    Ok((info, _)) => info,
+                    Err(Error::Timeout) | Err(Error::Corrupt) => {
+                        zm.send_hex(ZNAK, [0; 4]).await?;
+                        continue;
+                    }
+                    Err(e) => return Err(e.into()),
+                };
+                let (name, size, mtime) = parse_file_info(&info);
+                let path = local_target(dir, &name).await?;
+                progress.file(&name, files.len(), None, size);
+
+                let mut file = tokio::fs::File::create(&path).await.map_err(|e| e.to_string())?;
+                let result = receive_data(&mut zm, progress, &mut file).await;
+                let result = match result {
+                    Ok(()) => file.flush().await.map_err(|e| e.to_string()),
+                    Err(e) => Err(e.into()),
+                };
+                drop(file);
+                if let Err(e) = result {
+                    let _ = tokio::fs::remove_file(&path).await;
+                    return Err(e);
+                }
+                if mtime > 0 {
+                    let mtime = filetime::FileTime::from_unix_time(mtime as i64, 0);
+                    let _ = filetime::set_file_mtime(&path, mtime);
+                }
+                files.push(path);
+                errors = 0;
+                zm.send_hex(ZRINIT, rinit).await?;
+            }
+            ZFIN => {
+                zm.send_hex(ZFIN, [0; 4]).await?;
+                // 发送方以 "OO" 结束会话
+                zm.io.skip_prefix(b"OO", Duration::from_secs(1)).await?;
+                return Ok(files);
+            }
+            ZCAN | ZABORT => return Err(Error::RemoteCancel.into()),
+            _ => {}
+        }
+    }
+}
+
+/// 发送文件内容；返回 false 表示接收方跳过了该文件
+async fn send_data(
+    zm: &mut Zmodem<'_>,
+    progress: &mut Progress,
+    path: &str,
+    start: u64,
+) -> Result<bool, Error> {
+    let mut file = tokio::fs::File::open(path).await.map_err(|e| e.to_string())?;
+    let mut buf = vec![0u8; SUBPACKET];
+    let mut pos = start;
+    'frame: loop {
+        file.seek(SeekFrom::Start(pos)).await.map_err(|e| e.to_string())?;
+        zm.send_bin(ZDATA, pos_data(pos)).await?;
+        loop {
+            zm.io.check_cancel()?;
+            let n = file.read(&mut buf).await.map_err(|e| e.to_string())?;
+            let end = if n < SUBPACKET { ZCRCE } else { ZCRCG };
+            zm.send_subpacket(&buf[..n], end).await?;
+            pos += n as u64;
+            progress.update(pos, false);
+
+            if let Some(header) = zm.poll_header().await? {
+                match header.kind {
+                    // 接收方要求从某个位置重传
+                    ZRPOS => {
+                        if end != ZCRCE {
+                            zm.send_subpacket(&[], ZCRCE).await?;
+                        }
+                        pos = header.pos();
+                        continue 'frame;
+                    }
+                    ZSKIP => return Ok(false),
+                    ZCAN | ZABORT | ZFERR => return Err(Error::RemoteCancel),
+                    _ => {}
+                }
+            }
+            if end == ZCRCE {
+                break;
+            }
+        }
+
+        let mut errors = 0;
+        loop {
+            zm.send_bin(ZEOF, pos_data(pos)).await?;
+            match zm.read_header().await {
+                Ok(header) => match header.kind {
+                    ZRINIT => return Ok(true),
+                    ZRPOS => {
+                        pos = header.pos();
+                        continue 'frame;
+                    }
+                    ZSKIP => return Ok(false),
+                    ZCAN | ZABORT | ZFERR => return Err(Error::RemoteCancel),
+                    _ => {}
+                },
+                Err(Error::Timeout) | Err(Error::Corrupt) if errors < RETRIES => errors += 1,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// 远程 `rz`：发送本地文件，返回成功发送的路径
+pub async fn send(io: &mut TermIo, progress: &mut Progress, paths: &[String]) -> Result<Vec<String>, String> {
+    let mut files = Vec::new();
+    for path in paths {
+        let meta = tokio::fs::metadata(path).await.map_err(|e| e.to_string())?;
+        if !meta.is_file() {
+            return Err(format!("ZMODEM can only send regular files: {}", path));
+        }
+        files.push((path.clone(), meta));
+    }
+
+    // 等待接收方的 ZRINIT（检测时收到的那一帧在缓冲区开头）
+    let mut zm = Zmodem { io, crc32: false, escape_ctl: false };
+    let mut errors = 0;
+    loop {
+        match zm.read_header().await {
+            Ok(header) if header.kind == ZRINIT => {
+                zm.crc32 = header.zf0() & CANFC32 != 0;
+                zm.escape_ctl = header.zf0() & ESCCTL != 0;
+                break;
+            }
+            Ok(header) if header.kind == ZCAN || header.kind == ZABORT => return Err(Error::RemoteCancel.into()),
+            Ok(_) => {}
+            Err(Error::Timeout) | Err(Error::Corrupt) if errors < RETRIES => {
+                errors += 1;
+                zm.send_hex(ZRQINIT, [0; 4]).await?;
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    let mut sent = Vec::new();
+    let mut bytes_left: u64 = files.iter().map(|(_, m)| m.len()).sum();
+    for (index, (path, meta)) in files.iter().enumerate() {
+        let name = std::path::Path::new(path).file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        let mtime = crate::sftp::system_time_secs(meta.modified());
+        let info = format!(
+            "{}\0{} {:o} {:o} 0 {} {}\0",
+            name, meta.len(), mtime, 0o100644, files.len() - index, bytes_left,
+        );
+        bytes_left -= meta.len();
+        progress.file(&name, index, Some(files.len()), meta.len());
+
+        let mut errors = 0;
+        let start = loop {
+            zm.send_bin(ZFILE, flags_data(ZCBIN)).await?;
+            zm.send_subpacket(info.as_bytes(), ZCRCW).await?;
+            match zm.read_header().await {
+                Ok(header) => match header.kind {
+                    ZRPOS => break Some(header.pos()),
+                    ZSKIP => break None,
+                    ZCAN | ZABORT | ZFERR => return Err(Error::RemoteCancel.into()),
+                    _ => {}
+                },
+                Err(Error::Timeout) | Err(Error::Corrupt) if errors < RETRIES => {}
+                Err(e) => return Err(e.into()),
+            }
+            errors += 1;
+            if errors > RETRIES {
+                return Err(Error::Timeout.into());
+            }
+        };
+        let Some(start) = start else { continue };
+        if send_data(&mut zm, progress, path, start).await? {
+            sent.push(path.clone());
+        }
+    }
+
+    // 文件已全部送达，收尾失败不影响结果
+    for _ in 0..RETRIES {
+        zm.send_hex(ZFIN, [0; 4]).await?;
+        match zm.read_header().await {
+            Ok(header) if header.kind == ZFIN => break,
+            Ok(_) | Err(Error::Timeout) | Err(Error::Corrupt) => {}
+            Err(_) => break,
+        }
+    }
+    zm.io.write(b"OO").await?;
+    Ok(sent)
+}
@@ -39,7 +39,28 @@ export const hostsAPI = {
   getAll: () => invoke('get_hosts'),
   save: (host) => invoke('save_host', { host }),
   delete: (id) => invoke('delete_host', { id }),
-  get: (id) => invoke('get_host', { id })
+  get: (id) => invoke('get_host', { id }),
+  // 输出触发器：主机的触发器保存在 host.triggers 中，这里是对所有主机生效的全局触发器
+  // trigger = { id, name, pattern, caseSensitive, enabled, rateLimitMs, action: { type: highlight | notify | respond | startLogging | stopLogging, ... } }
+  // respond 动作的 rateLimitMs 不能小于 500
+  getGlobalTriggers: () => invoke('get_global_triggers'),
+  saveGlobalTriggers: (triggers) => invoke('save_global_triggers', { triggers })
+}
+
+// === 应用设置（后端） ===
+export const settingsAPI = {
+  get: () => invoke('get_app_settings'),
+  save: (settings) => invoke('save_app_settings', { settings })
+}
+
+// === 传输历史 ===
+// filter: { hostId, from, to, result, direction, search, limit }，时间为 ISO 8601 字符串
+export const historyAPI = {
+  query: (filter) => invoke('transfer_history_query', { filter }),
+  stats: (filter) => invoke('transfer_history_stats', { filter }),
+  // format: csv | json，返回导出的条数
+  export: (path, format, filter) => invoke('transfer_history_export', { path, format, filter }),
+  clear: (hostId) => invoke('transfer_history_clear', { hostId })
 }
 
 // === SSH 操作 ===
@@ -59,6 +80,53 @@ export const sshAPI = {
     return listen('ssh:closed', (event) => {
       cb(event.payload)
     })
+  },
+  // 终端内 rz/sz、trz/tsz 传输：收到 onTransferRequest 后回复要发送的文件（上传）或保存目录（下载），paths 为空表示取消
+  // 两分钟内未回复时后端取消传输并交还终端
+  transferAnswer: (requestId, paths) => invoke('ssh_transfer_answer', { requestId, paths }),
+  transferCancel: (sessionId) => invoke('ssh_transfer_cancel', { sessionId }),
+  onTransferRequest: (cb) => {
+    return listen('ssh:transfer-request', (event) => {
+      cb(event.payload)
+    })
+  },
+  onTransferProgress: (cb) => {
+    return listen('ssh:transfer-progress', (event) => {
+      cb(event.payload)
+    })
+  },
+  onTransferStatus: (cb) => {
+    return listen('ssh:transfer-status', (event) => {
+      cb(event.payload)
+    })
+  },
+  // shell 集成：通过 OSC 7 / OSC 133 跟踪工作目录和命令边界
+  shellState: (sessionId) => invoke('ssh_shell_state', { sessionId }),
+  // shell: bash | zsh | fish
+  shellIntegrationScript: (shell) => invoke('ssh_shell_integration_script', { shell }),
+  // shell 为空时按远程 $SHELL 判断，返回实际使用的 shell
+  injectShellIntegration: (sessionId, shell) => invoke('ssh_inject_shell_integration', { sessionId, shell }),
+  onCwdChanged: (cb) => {
+    return listen('ssh:cwd-changed', (event) => {
+      cb(event.payload)
+    })
+  },
+  onCommandState: (cb) => {
+    return listen('ssh:command-state', (event) => {
+      cb(event.payload)
+    })
+  },
+  // 后端回滚缓冲区（大小由设置 scrollbackLimit 控制）：since 为上次返回的 end，用于增量回放
+  scrollbackReplay: (sessionId, since) => invoke('ssh_scrollback_replay', { sessionId, since }),
+  // sessionIds 为空时搜索所有会话
+  scrollbackSearch: (sessionIds, pattern, isRegex = false, caseSensitive = false, maxResults) =>
+    invoke('ssh_scrollback_search', { sessionIds, pattern, isRegex, caseSensitive, maxResults }),
+  scrollbackClear: (sessionId) => invoke('ssh_scrollback_clear', { sessionId }),
+  // 触发器命中：highlight / notify 由前端处理，respond 与日志已在后端执行
+  onTrigger: (cb) => {
+    return listen('ssh:trigger', (event) => {
+      cb(event.payload)
+    })
   }
 }
 
@@ -70,38 +138,145 @@ export const sftpAPI = {
   list: (sessionId, path) => invoke('sftp_list', { sessionId, path }),
   // ls 是 list 的别名，保持与 Electron API 兼容
   ls: (sessionId, path) => invoke('sftp_list', { sessionId, path }),
-  upload: (sessionId, transferId, localPath, remotePath) => invoke('sftp_upload', { sessionId, transferId, localPath, remotePath }),
-  download: (sessionId, transferId, remotePath, localPath) => invoke('sftp_download', { sessionId, transferId, remotePath, localPath }),
+  // conflictPolicy: overwrite | skip | rename | newer | sizeDiffers | ask
+  // preserve 为空时使用设置中的默认值；verify 开启后在传输状态的 verification 中返回校验结果
+  // priority 越大越先执行（超过并发上限时排队）
+  upload: (sessionId, transferId, localPath, remotePath, conflictPolicy, preserve, verify, priority) => invoke('sftp_upload', { sessionId, transferId, localPath, remotePath, conflictPolicy, preserve, verify, priority }),
+  download: (sessionId, transferId, remotePath, localPath, conflictPolicy, preserve, verify, priority) => invoke('sftp_download', { sessionId, transferId, remotePath, localPath, conflictPolicy, preserve, verify, priority }),
+  // 目录打包传输：format 为 gzip | zstd | tar；下载时 extract 为 true 则 localPath 是解压目录，否则是归档文件
+  // 会话间复制（如 staging -> production），数据不落地
+  relay: (sessionId, targetSessionId, transferId, sourcePath, targetPath, conflictPolicy, preserve, verify, priority) =>
+    invoke('sftp_relay', { sessionId, targetSessionId, transferId, sourcePath, targetPath, conflictPolicy, preserve, verify, priority }),
+  archiveDownload: (sessionId, transferId, remotePath, localPath, format = 'gzip', extract = false, priority) =>
+    invoke('sftp_archive_download', { sessionId, transferId, remotePath, localPath, format, extract, priority }),
+  archiveUpload: (sessionId, transferId, localPath, remotePath, format = 'gzip', priority) =>
+    invoke('sftp_archive_upload', { sessionId, transferId, localPath, remotePath, format, priority }),
+  resolveConflict: (conflictId, policy, applyToAll) => invoke('sftp_resolve_conflict', { conflictId, policy, applyToAll }),
+  // sessionId 为空时设置全局限速（字节/秒，0 为不限速）
+  setBandwidthLimit: (sessionId, bytesPerSec) => invoke('sftp_set_bandwidth_limit', { sessionId, bytesPerSec }),
+  setMaxConcurrent: (sessionId, max) => invoke('sftp_set_max_concurrent', { sessionId, max }),
+  setTransferPriority: (sessionId, transferId, priority) => invoke('sftp_set_transfer_priority', { sessionId, transferId, priority }),
   delete: (sessionId, path) => invoke('sftp_delete', { sessionId, path }),
   rename: (sessionId, oldPath, newPath) => invoke('sftp_rename', { sessionId, oldPath, newPath }),
   mkdir: (sessionId, path) => invoke('sftp_mkdir', { sessionId, path }),
+  chmod: (sessionId, path, mode, recursive) => invoke('sftp_chmod', { sessionId, path, mode, recursive }),
+  // user/group 可以是名称或数字 id
+  chown: (sessionId, path, user, group, recursive) => invoke('sftp_chown', { sessionId, path, user, group, recursive }),
+  setTimes: (sessionId, path, mtime, atime, recursive) => invoke('sftp_set_times', { sessionId, path, mtime, atime, recursive }),
   getFile: (sessionId, path) => invoke('sftp_get_file', { sessionId, path }),
-  putFile: (sessionId, path, content) => invoke('sftp_put_file', { sessionId, path, content }),
+  // 原子保存：expectedSize/expectedMtime 为打开时 getFile 返回的 size/mtime，远程被修改时拒绝保存
+  // backup 为 true 时保留 <name>.bak；返回保存后的 { size, mtime }
+  // textFormat = { encoding, bom, lineEnding }，通常取 getFile 返回的 encoding/hasBom/lineEnding 以保持原格式
+  // 无法原子保存（目录不可写、无法保留属主）时报错；allowInPlace 为 true 时直接覆盖，返回的 atomic 为 false
+  putFile: (sessionId, path, content, expectedSize, expectedMtime, backup, textFormat = {}, allowInPlace = false) =>
+    invoke('sftp_put_file', { sessionId, path, content, expectedSize, expectedMtime, backup, ...textFormat, allowInPlace }),
   pause: (transferId) => invoke('sftp_pause', { transferId }),
   resume: (transferId) => invoke('sftp_resume', { transferId }),
   cancel: (transferId) => invoke('sftp_cancel', { transferId }),
   move: (sessionId, srcPath, dstPath) => invoke('sftp_move', { sessionId, srcPath, dstPath }),
   stat: (sessionId, path) => invoke('sftp_stat', { sessionId, path }),
+  symlink: (sessionId, target, linkPath) => invoke('sftp_symlink', { sessionId, target, linkPath }),
+  hardlink: (sessionId, target, linkPath) => invoke('sftp_hardlink', { sessionId, target, linkPath }),
   readTextFile: (sessionId, path) => invoke('sftp_read_text_file', { sessionId, path }),
+  // 按范围读取大文件：asText 为 false 时 content 为 base64
+  readRange: (sessionId, path, offset, length, asText = false, encoding = null) =>
+    invoke('sftp_read_range', { sessionId, path, offset, length, asText, encoding }),
+  readTail: (sessionId, path, lines) => invoke('sftp_read_tail', { sessionId, path, lines }),
+  readHexPage: (sessionId, path, page, pageSize = 4096) =>
+    invoke('sftp_read_hex_page', { sessionId, path, page, pageSize }),
+  // 在外部编辑器中打开，保存后自动上传；editor 为空时使用设置中的编辑器或系统默认程序
+  editOpen: (sessionId, remotePath, editor) => invoke('sftp_edit_open', { sessionId, remotePath, editor }),
+  editList: (sessionId) => invoke('sftp_edit_list', { sessionId }),
+  // resolution: overwrite | reload
+  editResolve: (editId, resolution) => invoke('sftp_edit_resolve', { editId, resolution }),
+  editClose: (editId) => invoke('sftp_edit_close', { editId }),
+
+  // 跟随远程文件（tail -F）：mode 为 auto | poll | exec，追加内容通过 onFollowData 推送
+  followStart: (sessionId, path, initialLines = 10, mode = 'auto', intervalMs = 1000) =>
+    invoke('sftp_follow_start', { sessionId, path, initialLines, mode, intervalMs }),
+  followStop: (followId) => invoke('sftp_follow_stop', { followId }),
+  followList: (sessionId) => invoke('sftp_follow_list', { sessionId }),
+
+  // 远程搜索：结果通过 onFindResults 分批推送，Promise 在搜索结束后返回汇总
+  // query = { name, nameMatch: 'glob' | 'regex', caseSensitive, minSize, maxSize, modifiedAfter, modifiedBefore, content, contentRegex, includeDirs, maxDepth, maxResults }
+  find: (sessionId, searchId, root, query) => invoke('sftp_find', { sessionId, searchId, root, query }),
+  findCancel: (searchId) => invoke('sftp_find_cancel', { searchId }),
+
+  // 目录/文件比较：left/right = { sessionId, path }，sessionId 为空表示本机；compare 为 sizeMtime | checksum
+  diffDirs: (left, right, compare = 'sizeMtime') => invoke('sftp_diff_dirs', { left, right, compare }),
+  // identical 为字节相同；sameText 为解码后文本相同，二者不一致说明只有编码或 BOM 不同
+  diffFile: (left, right, context = 3) => invoke('sftp_diff_file', { left, right, context }),
+
+  // 磁盘用量：返回 maxDepth 层以内的目录大小树，可用于树图/旭日图；SFTP 遍历时通过 onDuProgress 推送进度
+  du: (sessionId, scanId, path, maxDepth = 3) => invoke('sftp_du', { sessionId, scanId, path, maxDepth }),
+  duCancel: (scanId) => invoke('sftp_du_cancel', { scanId }),
+  // 当前路径所在文件系统的总量/可用空间（statvfs@openssh.com）
+  statvfs: (sessionId, path) => invoke('sftp_statvfs', { sessionId, path }),
 
+  // 以 root 运行 SFTP（sudo -S sftp-server）；serverPath 为空时自动探测，需要密码时通过 onSudoPrompt 询问
+  sudoEnable: (sessionId, serverPath) => invoke('sftp_sudo_enable', { sessionId, serverPath }),
+  sudoDisable: (sessionId) => invoke('sftp_sudo_disable', { sessionId }),
+  sudoStatus: (sessionId) => invoke('sftp_sudo_status', { sessionId }),
+  // password 传 null 表示取消
+  sudoAnswer: (promptId, password) => invoke('sftp_sudo_answer', { promptId, password }),
+  // 返回 'sftp'，或服务器禁用 SFTP 子系统时的 'scp'（此时只支持上传/下载）
+  transferProtocol: (sessionId) => invoke('sftp_transfer_protocol', { sessionId }),
+
+  // 后端传输队列：direction 为 upload | download，options 同 upload/download 的可选参数
+  // direction 为 relay 时需提供 targetSessionId 与 targetPath（目标会话中的路径），localPath 传空字符串
+  enqueue: (sessionId, direction, localPath, remotePath, options, targetSessionId, targetPath) =>
+    invoke('sftp_enqueue', { sessionId, direction, localPath, remotePath, options, targetSessionId, targetPath }),
+  queueList: () => invoke('sftp_queue_list'),
+  queueReorder: (ids) => invoke('sftp_queue_reorder', { ids }),
+  // sessionId 可选，用于在重连后的新会话上重试
+  queueRetry: (id, sessionId) => invoke('sftp_queue_retry', { id, sessionId }),
+  queueRemove: (id) => invoke('sftp_queue_remove', { id }),
+  queueClearCompleted: () => invoke('sftp_queue_clear_completed'),
+
+  // 目录同步：options = { mode: mirrorToRemote | mirrorToLocal | twoWay, compare: sizeMtime | checksum, delete }
+  // syncPlan 只比较不修改；syncExecute 传回计划执行，resolutions 为 { 相对路径: 'upload' | 'download' | 'deleteLocal' | 'deleteRemote' }（只能选择来源存在的方向；一侧已删除时可接受删除）
+  syncPlan: (sessionId, localRoot, remoteRoot, options) => invoke('sftp_sync_plan', { sessionId, localRoot, remoteRoot, options }),
+  syncExecute: (transferId, plan, resolutions, verify) => invoke('sftp_sync_execute', { transferId, plan, resolutions, verify }),
+
+  // 所有传输（含直接调用 upload/download）的状态与进度，负载为完整的队列条目
   onTransferStatus: (cb) => {
-    return listen('sftp-transfer-status', (event) => {
+    return listen('sftp:transfer-status', (event) => {
       cb(event.payload)
     })
   },
-  onTransferProgress: (cb) => {
-    return listen('sftp-transfer-progress', (event) => {
+  // state: synced | reloaded | conflict | error
+  onEditStatus: (cb) => {
+    return listen('sftp:edit-status', (event) => {
+      cb(event.payload)
+    })
+  },
+  onFollowData: (cb) => {
+    return listen('sftp:follow-data', (event) => {
+      cb(event.payload)
+    })
+  },
+  onFollowStatus: (cb) => {
+    return listen('sftp:follow-status', (event) => {
+      cb(event.payload)
+    })
+  },
+  onFindResults: (cb) => {
+    return listen('sftp:find-results', (event) => {
+      cb(event.payload)
+    })
+  },
+  onDuProgress: (cb) => {
+    return listen('sftp:du-progress', (event) => {
       cb(event.payload)
     })
   },
-  // 兼容 Electron API 的事件监听器
-  onUploadProgress: (cb) => {
-    return listen('sftp:upload-progress', (event) => {
+  onSudoPrompt: (cb) => {
+    return listen('sftp:sudo-prompt', (event) => {
       cb(event.payload)
     })
   },
-  onDownloadProgress: (cb) => {
-    return listen('sftp:download-progress', (event) => {
+  onTransferConflict: (cb) => {
+    return listen('sftp:transfer-conflict', (event) => {
       cb(event.payload)
     })
   }